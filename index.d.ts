@@ -8,16 +8,74 @@ export declare class BtcUri {
   assumeChecked(): CheckedBtcUri
 }
 
+/** Handle used to abort a long-running native operation from JavaScript. */
+export declare class CancellationToken {
+  constructor()
+  cancel(): void
+  isCancelled(): boolean
+}
+
 export declare class CheckedBtcUri {
   checkPjSupported(): PayjoinUri
 }
 
 export declare class MaybeInputsOwnedWrapper {
-  checkInputsNotOwned(isOwned: (arg: string) => boolean): MaybeInputsSeenWrapper
+  /**
+   * Serialize the proposal with its session, so it can be resumed after a
+   * restart with `fromJson`.
+   */
+  toJson(): string
+  /**
+   * Restore a proposal serialized by `toJson`. Fails if it was persisted
+   * in a different state.
+   */
+  static fromJson(jsonStr: string): MaybeInputsOwnedWrapper
+  /**
+   * Build the request that tells the sender this proposal was rejected with
+   * a BIP78 well-known error, so it can broadcast the original right away.
+   * Post it through the OHTTP relay with `PayjoinRequest::post`.
+   */
+  extractErrorRequest(code: WellKnownErrorCode, message?: string | undefined | null): PayjoinRequest
+  checkInputsNotOwned(isOwned: (arg: InputContext) => boolean): MaybeInputsSeenWrapper
+  /** Like `check_inputs_not_owned`, for wallets that answer with a Promise. */
+  checkInputsNotOwnedAsync(isOwned: (input: InputContext) => Promise<boolean>, cancellationToken?: CancellationToken | undefined | null): Promise<MaybeInputsSeenWrapper>
+  /**
+   * Like `check_inputs_not_owned_async`, but asks about every input's
+   * script in a single call. `are_owned` resolves with one answer per
+   * script, in order.
+   */
+  checkInputsNotOwnedBatch(areOwned: (inputs: InputContext[]) => Promise<boolean[]>, cancellationToken?: CancellationToken | undefined | null): Promise<MaybeInputsSeenWrapper>
 }
 
 export declare class MaybeInputsSeenWrapper {
-  checkNoInputsSeenBefore(isKnown: (arg: string) => boolean): OutputsUnknownWrapper
+  /**
+   * Serialize the proposal with its session, so it can be resumed after a
+   * restart with `fromJson`.
+   */
+  toJson(): string
+  /**
+   * Restore a proposal serialized by `toJson`. Fails if it was persisted
+   * in a different state.
+   */
+  static fromJson(jsonStr: string): MaybeInputsSeenWrapper
+  /**
+   * Build the request that tells the sender this proposal was rejected with
+   * a BIP78 well-known error, so it can broadcast the original right away.
+   * Post it through the OHTTP relay with `PayjoinRequest::post`.
+   */
+  extractErrorRequest(code: WellKnownErrorCode, message?: string | undefined | null): PayjoinRequest
+  checkNoInputsSeenBefore(isKnown: (arg: InputContext) => boolean): OutputsUnknownWrapper
+  /**
+   * Like `check_no_inputs_seen_before`, for wallets that answer with a
+   * Promise.
+   */
+  checkNoInputsSeenBeforeAsync(isKnown: (input: InputContext) => Promise<boolean>, cancellationToken?: CancellationToken | undefined | null): Promise<OutputsUnknownWrapper>
+  /**
+   * Like `check_no_inputs_seen_before_async`, but asks about every
+   * input's outpoint in a single call. `are_known` resolves with one
+   * answer per outpoint, in order.
+   */
+  checkNoInputsSeenBeforeBatch(areKnown: (inputs: InputContext[]) => Promise<boolean[]>, cancellationToken?: CancellationToken | undefined | null): Promise<OutputsUnknownWrapper>
 }
 
 export declare class OhttpContext {
@@ -25,17 +83,48 @@ export declare class OhttpContext {
 }
 
 export declare class OutputsUnknownWrapper {
-  identifyReceiverOutputs(isReceiverOutput: (arg: string) => boolean): WantsOutputsWrapper
+  /**
+   * Serialize the proposal with its session, so it can be resumed after a
+   * restart with `fromJson`.
+   */
+  toJson(): string
+  /**
+   * Restore a proposal serialized by `toJson`. Fails if it was persisted
+   * in a different state.
+   */
+  static fromJson(jsonStr: string): OutputsUnknownWrapper
+  /**
+   * Build the request that tells the sender this proposal was rejected with
+   * a BIP78 well-known error, so it can broadcast the original right away.
+   * Post it through the OHTTP relay with `PayjoinRequest::post`.
+   */
+  extractErrorRequest(code: WellKnownErrorCode, message?: string | undefined | null): PayjoinRequest
+  identifyReceiverOutputs(isReceiverOutput: (arg: OutputContext) => boolean): WantsOutputsWrapper
+  /**
+   * Like `identify_receiver_outputs`, for wallets that answer with a
+   * Promise.
+   */
+  identifyReceiverOutputsAsync(isReceiverOutput: (output: OutputContext) => Promise<boolean>, cancellationToken?: CancellationToken | undefined | null): Promise<WantsOutputsWrapper>
 }
 
 export declare class PayjoinOhttpKeys {
   constructor(bytes: Uint8Array)
-  static fetch(ohttpRelay: string, payjoinDirectory: string): Promise<PayjoinOhttpKeys>
+  static fetch(ohttpRelay: string, payjoinDirectory: string, cancellationToken?: CancellationToken | undefined | null): Promise<PayjoinOhttpKeys>
   toBytes(): Uint8Array
   static fromBytes(bytes: Uint8Array): PayjoinOhttpKeys
 }
 
 export declare class PayjoinProposalWrapper {
+  /**
+   * Serialize the proposal with its session, so it can be resumed after a
+   * restart with `fromJson`.
+   */
+  toJson(): string
+  /**
+   * Restore a proposal serialized by `toJson`. Fails if it was persisted
+   * in a different state.
+   */
+  static fromJson(jsonStr: string): PayjoinProposalWrapper
   utxosToBeLocked(): Array<string>
   isOutputSubstitutionDisabled(): boolean
   psbt(): string
@@ -48,16 +137,30 @@ export declare class PayjoinReceiver {
   constructor(address: string, directory: string, ohttpKeys: Uint8Array, ohttpRelay: string, expirySeconds?: bigint | undefined | null)
   toJson(): string
   static fromJson(jsonStr: string): PayjoinReceiver
+  /**
+   * Like `to_json`, encrypted under `key` for storage outside the process.
+   * The receiver's session secret never leaves it in plaintext.
+   */
+  toEncryptedJson(key: ExportKey): string
+  /** Restore a receiver exported by `to_encrypted_json` with the same key. */
+  static fromEncryptedJson(jsonStr: string, key: ExportKey): PayjoinReceiver
   pjUrl(): string
   pjUriBuilder(): PayjoinUriBuilder
   extractRequest(): PayjoinRequest
   processResponse(response: Uint8Array, request: PayjoinRequest): UncheckedProposalWrapper | null
+  /**
+   * Poll the directory through the relay until a sender posts an original
+   * PSBT. Resolves with `null` once the polling budget runs out and rejects
+   * when the session expires or the token is cancelled.
+   */
+  pollForProposal(options?: PollOptions | undefined | null, cancellationToken?: CancellationToken | undefined | null): Promise<UncheckedProposalWrapper | null>
 }
 
 export declare class PayjoinRequest {
   url(): string
+  contentType(): string
   body(): Uint8Array
-  post(): Promise<Uint8Array>
+  post(cancellationToken?: CancellationToken | undefined | null): Promise<Uint8Array>
   processResponse(response: Uint8Array): PayjoinResponse
 }
 
@@ -68,22 +171,48 @@ export declare class PayjoinResponse {
 }
 
 export declare class PayjoinSender {
-  extractV2(ohttpRelay: string): Promise<PayjoinRequest>
+  version(): string
+  /**
+   * Extract the request for whichever protocol version the endpoint supports.
+   * The OHTTP relay is only required for v2 endpoints.
+   */
+  extractRequest(ohttpRelay?: string | undefined | null): PayjoinRequest
+  extractV1(): PayjoinRequest
+  extractV2(ohttpRelay: string): PayjoinRequest
+  /**
+   * POST the original PSBT to the directory, then poll for the receiver's
+   * proposal until it arrives, the polling budget runs out or the
+   * receiver's session expires.
+   */
+  sendV2(ohttpRelay: string, options?: PollOptions | undefined | null, cancellationToken?: CancellationToken | undefined | null): Promise<SendOutcome>
   toJson(): string
   static fromJson(json: string): PayjoinSender
+  /**
+   * Like `to_json`, encrypted under `key`. The sender's reply key never
+   * leaves the process in plaintext.
+   */
+  toEncryptedJson(key: ExportKey): string
+  /** Restore a sender exported by `to_encrypted_json` with the same key. */
+  static fromEncryptedJson(json: string, key: ExportKey): PayjoinSender
 }
 
 export declare class PayjoinSenderBuilder {
   static fromPsbtAndUri(psbt: string, uri: string): PayjoinSenderBuilder
+  /**
+   * Protocol version the sender will use: "v2" when the endpoint carries
+   * BIP77 parameters, "v1" otherwise.
+   */
+  version(): string
   disableOutputSubstitution(disable: boolean): this
   buildRecommended(minFeeRateSatPerVb: number): PayjoinSender
-  buildWithAdditionalFee(maxFeeContributionSats: number, changeIndex: number | undefined | null, minFeeRateSatPerVb: number, clampFeeContribution: boolean): PayjoinSender
+  buildWithAdditionalFee(maxFeeContributionSats: bigint, changeIndex: number | undefined | null, minFeeRateSatPerVb: number, clampFeeContribution: boolean): PayjoinSender
 }
 
 export declare class PayjoinUri {
   endpoint(): PayjoinUrl
   exp(): bigint | null
-  amount(): number | null
+  /** The requested amount, in satoshis. */
+  amount(): bigint | null
   address(): string | null
 }
 
@@ -92,7 +221,7 @@ export declare class PayjoinUri {
  * */
 export declare class PayjoinUriBuilder {
   constructor(address: string, endpoint: string)
-  amount(amountSat: number): PayjoinUriBuilder
+  amount(amountSat: bigint): PayjoinUriBuilder
   message(message: string): PayjoinUriBuilder
   label(label: string): PayjoinUriBuilder
   disableOutputSubstitution(): PayjoinUriBuilder
@@ -103,38 +232,323 @@ export declare class PayjoinUrl {
   toString(): string
 }
 
+/** The sender's side of a v2 session once the original PSBT was posted. */
 export declare class PayjoinV2Context {
+  /**
+   * Whether `extract_request` can poll for the proposal. A restored
+   * context cannot until the original PSBT has been posted again with
+   * `extract_post_request`.
+   */
+  isPolling(): boolean
+  /**
+   * Post the original PSBT again, to resume polling a restored context
+   * through the directory's acknowledgement. The receiver's reply mailbox
+   * is the same, so a proposal it already sent is still found.
+   */
+  extractPostRequest(ohttpRelay: string): PayjoinRequest
+  /** Poll the receiver's reply mailbox for the proposal. */
   extractRequest(ohttpRelay: string): PayjoinRequest
   processResponse(response: Uint8Array, request: PayjoinRequest): string | null
+  toJson(): string
+  /**
+   * Restore a context serialized by `to_json`. It cannot poll until the
+   * original PSBT has been posted again with `extract_post_request`, since
+   * polling can only resume through the directory's acknowledgement.
+   */
+  static fromJson(json: string): PayjoinV2Context
 }
 
 export declare class ProvisionalProposalWrapper {
+  /**
+   * Serialize the proposal with its session, so it can be resumed after a
+   * restart with `fromJson`.
+   */
+  toJson(): string
+  /**
+   * Restore a proposal serialized by `toJson`. Fails if it was persisted
+   * in a different state.
+   */
+  static fromJson(jsonStr: string): ProvisionalProposalWrapper
+  /**
+   * Build the request that tells the sender this proposal was rejected with
+   * a BIP78 well-known error, so it can broadcast the original right away.
+   * Post it through the OHTTP relay with `PayjoinRequest::post`.
+   */
+  extractErrorRequest(code: WellKnownErrorCode, message?: string | undefined | null): PayjoinRequest
   finalizeProposal(minFeerateSatPerVb: number | undefined | null, maxFeerateSatPerVb: number | undefined | null, walletProcessPsbt: (arg: string) => string): PayjoinProposalWrapper
+  /**
+   * Like `finalize_proposal`, for wallets that sign asynchronously, e.g.
+   * through `walletprocesspsbt` over RPC or a hardware signer.
+   */
+  finalizeProposalAsync(minFeerateSatPerVb: number | undefined | null, maxFeerateSatPerVb: number | undefined | null, walletProcessPsbt: (psbt: string) => Promise<string>, cancellationToken?: CancellationToken | undefined | null): Promise<PayjoinProposalWrapper>
+}
+
+/**
+ * Owns many receiver sessions, e.g. one per invoice, and polls them all
+ * concurrently while `run` is pending.
+ */
+export declare class ReceiverManager {
+  constructor(options?: ReceiverManagerOptions | undefined | null)
+  /**
+   * Start managing `receiver` and return its ID, which is its mailbox on
+   * the directory. Adding the same session again keeps a single copy.
+   */
+  add(receiver: PayjoinReceiver): string
+  /** Stop managing a session. Returns whether it was managed. */
+  remove(id: string): boolean
+  /** IDs of the sessions still being managed. */
+  sessionIds(): Array<string>
+  /**
+   * Poll every session until the token is cancelled, reporting proposals,
+   * expiries and failed polls to `on_event`. Sessions added while running
+   * are picked up right away. Resolves once cancelled.
+   */
+  run(onEvent: (event: ReceiverEvent) => void, cancellationToken?: CancellationToken | undefined | null): Promise<void>
+}
+
+/**
+ * Persists receiver and sender sessions under stable IDs, so they can be
+ * resumed after a restart. Given a key, a store keeps each session as its
+ * encrypted export, so no backend ever sees its secrets in plaintext.
+ */
+export declare class SessionStore {
+  /** Keep sessions in this process only, e.g. for tests. */
+  static memory(key?: ExportKey | undefined | null): SessionStore
+  /**
+   * Keep each session as a JSON file in `directory`, which must exist.
+   * Files are created readable by their owner only.
+   */
+  static file(directory: string, key?: ExportKey | undefined | null): SessionStore
+  /** Keep sessions wherever `backend` puts them. */
+  static custom(backend: SessionBackend, key?: ExportKey | undefined | null): SessionStore
+  /** Store `receiver`, replacing any earlier save, and return its ID. */
+  saveReceiver(receiver: PayjoinReceiver): Promise<string>
+  /** Store `sender`, replacing any earlier save, and return its ID. */
+  saveSender(sender: PayjoinSender): Promise<string>
+  loadReceiver(id: string): Promise<PayjoinReceiver | null>
+  loadSender(id: string): Promise<PayjoinSender | null>
+  list(): Promise<Array<SessionInfo>>
+  /** Resolves with whether a session was deleted. */
+  delete(id: string): Promise<boolean>
+  /**
+   * Delete every session whose expiry has passed and resolve with their
+   * IDs.
+   */
+  sweepExpired(): Promise<Array<string>>
 }
 
 export declare class UncheckedProposalWrapper {
+  /**
+   * Serialize the proposal with its session, so it can be resumed after a
+   * restart with `fromJson`.
+   */
+  toJson(): string
+  /**
+   * Restore a proposal serialized by `toJson`. Fails if it was persisted
+   * in a different state.
+   */
+  static fromJson(jsonStr: string): UncheckedProposalWrapper
+  /**
+   * Build the request that tells the sender this proposal was rejected with
+   * a BIP78 well-known error, so it can broadcast the original right away.
+   * Post it through the OHTTP relay with `PayjoinRequest::post`.
+   */
+  extractErrorRequest(code: WellKnownErrorCode, message?: string | undefined | null): PayjoinRequest
   originalTx(): string
-  checkBroadcastSuitability(minFeeRate: number | undefined | null, canBroadcast: (arg: string) => boolean): MaybeInputsOwnedWrapper
+  checkBroadcastSuitability(minFeeRate: number | undefined | null, canBroadcast: (arg: TxContext) => boolean): MaybeInputsOwnedWrapper
+  /**
+   * Like `check_broadcast_suitability`, for wallets that answer with a
+   * Promise, e.g. a `testmempoolaccept` RPC call.
+   */
+  checkBroadcastSuitabilityAsync(minFeeRate: number | undefined | null, canBroadcast: (tx: TxContext) => Promise<boolean>, cancellationToken?: CancellationToken | undefined | null): Promise<MaybeInputsOwnedWrapper>
   assumeInteractiveReceiver(): MaybeInputsOwnedWrapper
 }
 
 export declare class WantsInputsWrapper {
+  /**
+   * Serialize the proposal with its session, so it can be resumed after a
+   * restart with `fromJson`.
+   */
+  toJson(): string
+  /**
+   * Restore a proposal serialized by `toJson`. Fails if it was persisted
+   * in a different state.
+   */
+  static fromJson(jsonStr: string): WantsInputsWrapper
+  /**
+   * Build the request that tells the sender this proposal was rejected with
+   * a BIP78 well-known error, so it can broadcast the original right away.
+   * Post it through the OHTTP relay with `PayjoinRequest::post`.
+   */
+  extractErrorRequest(code: WellKnownErrorCode, message?: string | undefined | null): PayjoinRequest
+  /**
+   * Pick the candidate that best preserves privacy, following the
+   * unnecessary input heuristic, and return its index in
+   * `candidate_inputs`. Nothing is contributed until `contribute_inputs`.
+   */
+  selectPrivacyPreservingInput(candidateInputs: Array<InputPairRequest>): number
+  /**
+   * Add `inputs` to the proposal. Can be called again to contribute more
+   * before `commit_inputs`.
+   */
+  contributeInputs(inputs: Array<InputPairRequest>): WantsInputsWrapper
+  /**
+   * Stop contributing inputs. Committing without contributing any is
+   * allowed, e.g. when the wallet has nothing suitable to offer.
+   */
+  commitInputs(): ProvisionalProposalWrapper
+  /**
+   * Select one privacy preserving input from `candidate_inputs`,
+   * contribute it and commit, in a single call.
+   */
   tryContributeInputs(candidateInputs: Array<InputPairRequest>): ProvisionalProposalWrapper
+  /**
+   * Like `try_contribute_inputs`, but when no candidate avoids the
+   * unnecessary input heuristics, `fallback` decides what happens instead
+   * of failing. The outcome reports which branch was taken.
+   */
+  contributeInputsWithFallback(candidateInputs: Array<InputPairRequest>, fallback: SelectionFallback): ContributionOutcome
 }
 
 export declare class WantsOutputsWrapper {
+  /**
+   * Serialize the proposal with its session, so it can be resumed after a
+   * restart with `fromJson`.
+   */
+  toJson(): string
+  /**
+   * Restore a proposal serialized by `toJson`. Fails if it was persisted
+   * in a different state.
+   */
+  static fromJson(jsonStr: string): WantsOutputsWrapper
+  /**
+   * Build the request that tells the sender this proposal was rejected with
+   * a BIP78 well-known error, so it can broadcast the original right away.
+   * Post it through the OHTTP relay with `PayjoinRequest::post`.
+   */
+  extractErrorRequest(code: WellKnownErrorCode, message?: string | undefined | null): PayjoinRequest
   isOutputSubstitutionDisabled(): boolean
   substituteReceiverScript(outputScript: Array<number>): WantsOutputsWrapper
   replaceReceiverOutputs(replacementOutputs: Array<ReplacementOutput>, drainScript: Array<number>): WantsOutputsWrapper
   commitOutputs(): WantsInputsWrapper
 }
 
+/** Stop forwarding diagnostics to JavaScript. */
+export declare function clearLogger(): void
+
 export interface Bip32DerivationData {
   pubkey: Array<number>
+  /**
+   * The master key fingerprint, followed by any path steps before `child`
+   * as little-endian u32s, as in a PSBT key source.
+   */
   fingerprintPath: Array<number>
+  /** The final path step. Hardened steps have the high bit set. */
   child: number
 }
 
+/** Which inputs `contribute_inputs_with_fallback` ended up contributing. */
+export declare enum ContributionBranch {
+  /** The privacy preserving selection succeeded. */
+  PrivacyPreserving = 'PrivacyPreserving',
+  /** Selection failed and the largest candidate was contributed. */
+  LargestInput = 'LargestInput',
+  /** Selection failed and no input was contributed. */
+  NoInput = 'NoInput',
+}
+
+export interface ContributionOutcome {
+  proposal: ProvisionalProposalWrapper
+  branch: ContributionBranch
+  /** Index of the contributed input in the candidates, unless none was. */
+  selectedIndex?: number
+}
+
+/**
+ * Stable error codes. Every error thrown by the bindings carries one of these
+ * as its `code` property.
+ */
+export declare enum ErrorCode {
+  InvalidAddress = 'InvalidAddress',
+  InvalidAmount = 'InvalidAmount',
+  InvalidFeeRate = 'InvalidFeeRate',
+  InvalidInput = 'InvalidInput',
+  InvalidOhttpKeys = 'InvalidOhttpKeys',
+  InvalidPsbt = 'InvalidPsbt',
+  InvalidScript = 'InvalidScript',
+  InvalidUri = 'InvalidUri',
+  InvalidUrl = 'InvalidUrl',
+  InvalidOptions = 'InvalidOptions',
+  UnsupportedUri = 'UnsupportedUri',
+  CreateRequestFailed = 'CreateRequestFailed',
+  SessionExpired = 'SessionExpired',
+  MissingContext = 'MissingContext',
+  NetworkError = 'NetworkError',
+  HttpStatus = 'HttpStatus',
+  InvalidResponse = 'InvalidResponse',
+  ProposalRejected = 'ProposalRejected',
+  /** BIP78 `unavailable`: the receiver cannot process payjoins right now. */
+  ReceiverUnavailable = 'ReceiverUnavailable',
+  /** BIP78 `not-enough-money`: the receiver could not bump the fee. */
+  NotEnoughMoney = 'NotEnoughMoney',
+  /** BIP78 `version-unsupported`: retry with one of the `supported` versions. */
+  VersionUnsupported = 'VersionUnsupported',
+  /** BIP78 `original-psbt-rejected`: the receiver refused the original PSBT. */
+  OriginalPsbtRejected = 'OriginalPsbtRejected',
+  /** The receiver replied with an error code BIP78 does not define. */
+  UnrecognizedReceiverError = 'UnrecognizedReceiverError',
+  ReceiverFailed = 'ReceiverFailed',
+  CallbackFailed = 'CallbackFailed',
+  SerializationFailed = 'SerializationFailed',
+  Cancelled = 'Cancelled',
+  /** An error that did not originate from the bindings. */
+  Unknown = 'Unknown',
+}
+
+/** How a `PayjoinError` is classified, as set on the JavaScript error. */
+export interface ErrorInfo {
+  code: ErrorCode
+  stage?: ErrorStage
+  /** The underlying payjoin error type, when known. */
+  kind?: string
+  details?: any
+  message: string
+}
+
+/**
+ * The part of the payjoin flow an error originated from.
+ */
+export declare enum ErrorStage {
+  Uri = 'Uri',
+  OhttpKeys = 'OhttpKeys',
+  Http = 'Http',
+  SenderBuilder = 'SenderBuilder',
+  Sender = 'Sender',
+  Receiver = 'Receiver',
+  Proposal = 'Proposal',
+  Persistence = 'Persistence',
+}
+
+/** One of the sender's inputs, as passed to the input checks. */
+/** Secret an encrypted export is sealed with. Give exactly one of the two. */
+export interface ExportKey {
+  /** A random 32-byte key, e.g. from a KMS. */
+  key?: Uint8Array
+  /** A passphrase, stretched with scrypt and a random salt. */
+  passphrase?: string
+}
+
+export interface InputContext {
+  /** The spent output's script, hex encoded. */
+  script: string
+  /** The script's address on the receiver's network, when it has one. */
+  address?: string
+  /** The spent outpoint, as `txid:vout`. */
+  outpoint: string
+  /** The spent output's value in satoshis. */
+  value: bigint
+}
+
 export interface InputPairRequest {
   prevout: TxOutpoint
   scriptSig?: Array<number>
@@ -143,11 +557,70 @@ export interface InputPairRequest {
   psbtData: PsbtInputData
 }
 
+/** Verbosity of the diagnostics forwarded to JavaScript. */
+export declare enum LogLevel {
+  Off = 'Off',
+  Error = 'Error',
+  Warn = 'Warn',
+  Info = 'Info',
+  Debug = 'Debug',
+  Trace = 'Trace',
+}
+
+/**
+ * How much of PSBTs, transactions, txids, scripts and OHTTP payloads reaches
+ * the logger.
+ */
+export declare enum LogRedaction {
+  /** Replace them with a short prefix and a hash. The default. */
+  Redacted = 'Redacted',
+  /** Log them in full. Only meant for local debugging. */
+  Full = 'Full',
+}
+
+/** A single diagnostic emitted by the bindings or by rust-payjoin. */
+export interface LogRecord {
+  level: LogLevel
+  /** The Rust module the record came from, e.g. `payjoin::receive::v2`. */
+  target: string
+  message: string
+}
+
+/**
+ * One of the original transaction's outputs, as passed to
+ * `identify_receiver_outputs`.
+ */
+export interface OutputContext {
+  /** The output's script, hex encoded. */
+  script: string
+  /** The script's address on the receiver's network, when it has one. */
+  address?: string
+  vout: number
+  /** The output's value in satoshis. */
+  value: bigint
+}
+
 export interface PartialSigData {
   pubkey: Array<number>
   signature: Array<number>
 }
 
+/** Polling schedule used by the native send and receive loops. */
+export interface PollOptions {
+  /** Delay before the first re-poll, in milliseconds. Defaults to 5000. */
+  intervalMs?: number
+  /** Upper bound for the delay between polls, in milliseconds. Defaults to 60000. */
+  maxIntervalMs?: number
+  /** Factor the delay grows by after every empty poll. Defaults to 1.5. */
+  backoffMultiplier?: number
+  /**
+   * Overall time budget for the loop, in milliseconds, including requests
+   * still in flight. Defaults to 10 minutes when sending; receivers poll
+   * until their session expires.
+   */
+  timeoutMs?: number
+}
+
 export interface PsbtInputData {
   nonWitnessUtxo?: Array<number>
   witnessUtxo?: WitnessUtxoData
@@ -158,6 +631,53 @@ export interface PsbtInputData {
   bip32Derivation?: Array<Bip32DerivationData>
   finalScriptSig?: Array<number>
   finalScriptWitness?: Array<Array<number>>
+  /**
+   * Schnorr signature for a key path spend, with its sighash byte when
+   * not the default.
+   */
+  tapKeySig?: Array<number>
+  tapScriptSigs?: Array<TapScriptSigData>
+  tapScripts?: Array<TapLeafScriptData>
+  tapBip32Derivation?: Array<TapBip32DerivationData>
+  /** The 32-byte x-only internal key. */
+  tapInternalKey?: Array<number>
+  /** The 32-byte root of the script tree. */
+  tapMerkleRoot?: Array<number>
+}
+
+/** Reported to the callback given to `ReceiverManager::run`. */
+export interface ReceiverEvent {
+  kind: ReceiverEventKind
+  /** The session's ID, as returned by `add`. */
+  sessionId: string
+  /** The sender's proposal, for `Proposal` events. */
+  proposal?: UncheckedProposalWrapper
+  /** The failure, for `Error` events. */
+  error?: ErrorInfo
+}
+
+/** What happened to a managed session. */
+export declare enum ReceiverEventKind {
+  /** A sender posted an original PSBT. The session leaves the manager. */
+  Proposal = 'Proposal',
+  /** The session expired before a proposal arrived and was retired. */
+  Expired = 'Expired',
+  /**
+   * A poll failed. The session stays and is polled again, unless its
+   * poller itself failed, in which case it is retired.
+   */
+  Error = 'Error',
+}
+
+/** How a `ReceiverManager` polls its sessions. */
+export interface ReceiverManagerOptions {
+  /** Most polls in flight at once across all sessions. Defaults to 16. */
+  maxConcurrentPolls?: number
+  /**
+   * Schedule each session is polled on. `timeoutMs` is ignored: sessions
+   * are polled until they yield a proposal, expire or are removed.
+   */
+  poll?: PollOptions
 }
 
 export interface ReplacementOutput {
@@ -165,12 +685,145 @@ export interface ReplacementOutput {
   value: bigint
 }
 
+/** Result of driving a send to completion with `PayjoinSender::send_v2`. */
+export interface SendOutcome {
+  /**
+   * "proposal" when the receiver replied, "timeout" when the polling budget
+   * ran out and "expired" when the receiver's session expired. In the last
+   * two cases the original transaction should be broadcast.
+   */
+  status: string
+  /** The receiver's proposal PSBT, present when the status is "proposal". */
+  psbt?: string
+}
+
+/**
+ * What `contribute_inputs_with_fallback` does when no candidate avoids the
+ * unnecessary input heuristics.
+ */
+export declare enum SelectionFallback {
+  /**
+   * Contribute the candidate with the largest value anyway. payjoin does
+   * not expose how close a candidate comes to avoiding the heuristics, so
+   * candidates are ranked by value alone.
+   */
+  LargestInput = 'LargestInput',
+  /** Contribute nothing and still return a proposal. */
+  NoInput = 'NoInput',
+  /** Fail as `try_contribute_inputs` does. */
+  Abort = 'Abort',
+}
+
+/** A backend implemented in JavaScript, e.g. on top of an existing database. */
+export interface SessionBackend {
+  list: () => Promise<StoredSession[]>
+  load: (id: string) => Promise<StoredSession | null | undefined>
+  save: (session: StoredSession) => Promise<void>
+  /** Resolves with whether a session was deleted. */
+  delete: (id: string) => Promise<boolean>
+}
+
+/** A session as listed by `SessionStore::list`. */
+export interface SessionInfo {
+  id: string
+  kind: SessionKind
+  /** Unix timestamp after which the session can no longer complete. */
+  expiresAt?: bigint
+}
+
+/** Which side of a payjoin a stored session belongs to. */
+export declare enum SessionKind {
+  Receiver = 'Receiver',
+  Sender = 'Sender',
+}
+
+/**
+ * Forward diagnostics at `level` and above (default `Info`) to `callback`.
+ * Nothing is logged until a callback is installed, and the bindings never
+ * write log files themselves. Sensitive values are redacted unless
+ * `setLogRedaction('Full')` was called.
+ */
+export declare function setLogger(callback: (record: LogRecord) => void, level?: LogLevel | undefined | null): void
+
+/** Choose whether sensitive values are redacted from log records. */
+export declare function setLogRedaction(redaction: LogRedaction): void
+
+/** Change the verbosity of the installed logger. */
+export declare function setLogLevel(level: LogLevel): void
+
+/**
+ * A session as handed to and returned by a JavaScript backend. `json` is
+ * what the session's `toJson` produced, or its `toEncryptedJson` when the
+ * store was given a key.
+ */
+export interface StoredSession {
+  id: string
+  kind: SessionKind
+  expiresAt?: bigint
+  json: string
+}
+
+export interface TapBip32DerivationData {
+  /** The 32-byte x-only key. */
+  pubkey: Array<number>
+  /** Hashes of the leaves the key appears in. Empty for the internal key. */
+  leafHashes: Array<Array<number>>
+  /** As in `Bip32DerivationData`. */
+  fingerprintPath: Array<number>
+  child: number
+}
+
+/** A leaf script with the control block proving it is in the tree. */
+export interface TapLeafScriptData {
+  controlBlock: Array<number>
+  script: Array<number>
+  /** Defaults to 0xc0, tapscript. */
+  leafVersion?: number
+}
+
+/** A Schnorr signature for a script path spend. */
+export interface TapScriptSigData {
+  /** The 32-byte x-only key that signed. */
+  pubkey: Array<number>
+  /** The 32-byte hash of the leaf the signature is for. */
+  leafHash: Array<number>
+  signature: Array<number>
+}
+
+/**
+ * The sender's original transaction, as passed to
+ * `check_broadcast_suitability`.
+ */
+export interface TxContext {
+  /** The signed transaction, hex encoded. */
+  txHex: string
+  txid: string
+  vsize: number
+  /** The fee paid by the sender, in satoshis. */
+  fee: bigint
+  feeRateSatPerVb: number
+}
+
 export interface TxOutpoint {
   txid: string
   vout: number
 }
 
+/** BIP78 well-known errors a receiver can reply with. */
+export declare enum WellKnownErrorCode {
+  Unavailable = 'Unavailable',
+  NotEnoughMoney = 'NotEnoughMoney',
+  VersionUnsupported = 'VersionUnsupported',
+  OriginalPsbtRejected = 'OriginalPsbtRejected',
+}
+
+/**
+ * Check that `input` maps to a valid PSBT input and can be contributed,
+ * before any proposal needs it.
+ */
+export declare function validateInputPair(input: InputPairRequest): void
+
 export interface WitnessUtxoData {
-  amount: number
+  amount: bigint
   scriptPubKey: string
 }
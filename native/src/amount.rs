@@ -0,0 +1,83 @@
+use crate::error::{ErrorCode, ErrorStage, PayjoinError};
+use napi::bindgen_prelude::BigInt;
+use payjoin::bitcoin::{Amount, FeeRate};
+
+/// Read a satoshi amount passed from JavaScript. Negative values and values
+/// above the 21 million bitcoin supply are refused rather than truncated.
+pub(crate) fn to_amount(value: &BigInt, stage: ErrorStage, context: &str) -> napi::Result<Amount> {
+    let (negative, sats, lossless) = value.get_u64();
+    let error = |reason: &str| -> napi::Error {
+        PayjoinError::new(
+            ErrorCode::InvalidAmount,
+            stage,
+            format!("{}: {}", context, reason),
+        )
+        .into()
+    };
+
+    if negative && sats != 0 {
+        return Err(error("amount is negative"));
+    }
+    if !lossless || sats > Amount::MAX_MONEY.to_sat() {
+        return Err(error("amount exceeds the 21 million bitcoin supply"));
+    }
+    Ok(Amount::from_sat(sats))
+}
+
+/// Read a non-negative integer passed from JavaScript, e.g. a timestamp.
+/// Negative values and values that do not fit in 64 bits are refused rather
+/// than truncated.
+pub(crate) fn to_u64(
+    value: &BigInt,
+    code: ErrorCode,
+    stage: ErrorStage,
+    context: &str,
+) -> napi::Result<u64> {
+    let (negative, value, lossless) = value.get_u64();
+    let error = |reason: &str| -> napi::Error {
+        PayjoinError::new(code, stage, format!("{}: {}", context, reason)).into()
+    };
+
+    if negative && value != 0 {
+        return Err(error("value is negative"));
+    }
+    if !lossless {
+        return Err(error("value does not fit in 64 bits"));
+    }
+    Ok(value)
+}
+
+pub(crate) fn from_amount(amount: Amount) -> BigInt {
+    BigInt::from(amount.to_sat())
+}
+
+/// Read a fee rate in sat/vB passed from JavaScript. Fractional rates are kept
+/// to the nearest sat/kwu; negative, non-finite and overflowing rates are
+/// refused rather than truncated.
+pub(crate) fn to_fee_rate(
+    sat_per_vb: f64,
+    stage: ErrorStage,
+    context: &str,
+) -> napi::Result<FeeRate> {
+    let error = |reason: &str| -> napi::Error {
+        PayjoinError::new(
+            ErrorCode::InvalidFeeRate,
+            stage,
+            format!("{}: {}", context, reason),
+        )
+        .into()
+    };
+
+    if !sat_per_vb.is_finite() {
+        return Err(error("fee rate is not a finite number"));
+    }
+    if sat_per_vb < 0.0 {
+        return Err(error("fee rate is negative"));
+    }
+    // 1 sat/vB is 250 sat/kwu.
+    let sat_per_kwu = (sat_per_vb * 250.0).round();
+    if sat_per_kwu >= u64::MAX as f64 {
+        return Err(error("fee rate is too high"));
+    }
+    Ok(FeeRate::from_sat_per_kwu(sat_per_kwu as u64))
+}
@@ -0,0 +1,217 @@
+use crate::error::{self, ErrorCode, ErrorStage, PayjoinError};
+use napi::bindgen_prelude::{FromNapiValue, JsValuesTupleIntoVec, Promise};
+use napi::threadsafe_function::ThreadsafeFunction;
+use napi::Status;
+use std::borrow::Borrow;
+use std::cell::{Cell, RefCell};
+
+/// A JavaScript callback that answers with a Promise, e.g. a wallet lookup
+/// over RPC.
+pub type AsyncCallback<T, R> = ThreadsafeFunction<T, Promise<R>, T, Status, false>;
+
+/// Call `callback` and wait for the Promise it returns to settle.
+pub(crate) async fn call_async<T, R>(
+    callback: &AsyncCallback<T, R>,
+    value: T,
+    context: &str,
+) -> napi::Result<R>
+where
+    T: 'static + JsValuesTupleIntoVec,
+    R: 'static + FromNapiValue,
+{
+    let promise = callback
+        .call_async_catch(value)
+        .await
+        .map_err(|e| callback_error(e, context))?;
+    promise.await.map_err(|e| callback_error(e, context))
+}
+
+/// Report a callback failure, keeping what JavaScript threw or rejected with
+/// as the cause.
+pub(crate) fn callback_error(error: napi::Error, context: &str) -> napi::Error {
+    PayjoinError::new(
+        ErrorCode::CallbackFailed,
+        ErrorStage::Proposal,
+        format!("{}: {}", context, error::message(&error)),
+    )
+    .with_cause(error)
+    .into()
+}
+
+/// What a synchronous callback threw from inside one of payjoin's checks.
+/// payjoin only carries a message out of the check, so the error is kept here
+/// and rethrown in place of payjoin's.
+#[derive(Default)]
+pub(crate) struct Thrown(RefCell<Option<napi::Error>>);
+
+impl Thrown {
+    /// Keep `error` and give payjoin a stand-in to return.
+    pub(crate) fn catch(&self, error: napi::Error, context: &str) -> payjoin::Error {
+        let stand_in =
+            payjoin::Error::Server(format!("{}: {}", context, error::message(&error)).into());
+        self.0.replace(Some(error));
+        stand_in
+    }
+
+    /// `result`, unless it failed because the callback threw.
+    pub(crate) fn rethrow<T>(self, result: napi::Result<T>, context: &str) -> napi::Result<T> {
+        match (result, self.0.into_inner()) {
+            (Err(_), Some(thrown)) => Err(callback_error(thrown, context)),
+            (result, _) => result,
+        }
+    }
+}
+
+/// The answers a check was given, in the order payjoin asked for them, so the
+/// check can be replayed when a persisted proposal is restored.
+#[derive(Default)]
+pub(crate) struct Recording(RefCell<Vec<bool>>);
+
+impl Recording {
+    pub(crate) fn record(
+        &self,
+        answer: Result<bool, payjoin::Error>,
+    ) -> Result<bool, payjoin::Error> {
+        if let Ok(answer) = &answer {
+            self.0.borrow_mut().push(*answer);
+        }
+        answer
+    }
+
+    pub(crate) fn take(&self) -> Vec<bool> {
+        self.0.take()
+    }
+}
+
+/// Recorded answers fed back into a check, in order.
+pub(crate) struct Replay<'a> {
+    answers: &'a [bool],
+    next: Cell<usize>,
+}
+
+impl<'a> Replay<'a> {
+    pub(crate) fn new(answers: &'a [bool]) -> Self {
+        Self {
+            answers,
+            next: Cell::new(0),
+        }
+    }
+
+    pub(crate) fn answer(&self) -> Result<bool, payjoin::Error> {
+        let index = self.next.replace(self.next.get() + 1);
+        self.answers
+            .get(index)
+            .copied()
+            .ok_or_else(|| payjoin::Error::Server("No answer recorded for this check".into()))
+    }
+}
+
+/// payjoin's checks take synchronous closures, so a JavaScript Promise cannot
+/// be awaited from inside one. Instead the check is first run on a copy of the
+/// proposal to record every value it asks about, answering "no" each time.
+pub(crate) fn queried<Q>(
+    run: impl FnOnce(&dyn Fn(&Q) -> Result<bool, payjoin::Error>),
+) -> Vec<Q::Owned>
+where
+    Q: ?Sized + ToOwned,
+{
+    let queried = RefCell::new(Vec::new());
+    run(&|value: &Q| {
+        queried.borrow_mut().push(value.to_owned());
+        Ok(false)
+    });
+    queried.into_inner()
+}
+
+/// Answers collected from JavaScript, replayed into the real check. They are
+/// kept by position rather than by value: two inputs may spend the same
+/// script, and each is asked about with its own context.
+pub(crate) struct Answers<K> {
+    asked: Vec<(K, bool)>,
+    next: Cell<usize>,
+    given: Recording,
+}
+
+impl<K> Answers<K> {
+    /// Ask `callback` about each of `values` in turn. `to_arg` is given each
+    /// value's position in the order payjoin asked.
+    pub(crate) async fn collect<T>(
+        callback: &AsyncCallback<T, bool>,
+        values: Vec<K>,
+        to_arg: impl Fn(usize, &K) -> napi::Result<T>,
+        context: &str,
+    ) -> napi::Result<Self>
+    where
+        T: 'static + JsValuesTupleIntoVec,
+    {
+        let mut asked = Vec::with_capacity(values.len());
+        for (index, value) in values.into_iter().enumerate() {
+            let answer = call_async(callback, to_arg(index, &value)?, context).await?;
+            asked.push((value, answer));
+        }
+        Ok(Self::new(asked))
+    }
+
+    /// Ask `callback` about all of `values` in one call. It must resolve with
+    /// one answer per value, in the same order.
+    pub(crate) async fn collect_batch<T>(
+        callback: &AsyncCallback<Vec<T>, Vec<bool>>,
+        values: Vec<K>,
+        to_arg: impl Fn(usize, &K) -> napi::Result<T>,
+        context: &str,
+    ) -> napi::Result<Self>
+    where
+        Vec<T>: 'static + JsValuesTupleIntoVec,
+    {
+        let args = values
+            .iter()
+            .enumerate()
+            .map(|(index, value)| to_arg(index, value))
+            .collect::<napi::Result<_>>()?;
+        let answers = call_async(callback, args, context).await?;
+        if answers.len() != values.len() {
+            return Err(PayjoinError::new(
+                ErrorCode::CallbackFailed,
+                ErrorStage::Proposal,
+                format!(
+                    "{}: expected {} answers, got {}",
+                    context,
+                    values.len(),
+                    answers.len()
+                ),
+            )
+            .into());
+        }
+        Ok(Self::new(values.into_iter().zip(answers).collect()))
+    }
+
+    fn new(asked: Vec<(K, bool)>) -> Self {
+        Self {
+            asked,
+            next: Cell::new(0),
+            given: Recording::default(),
+        }
+    }
+
+    /// The next answer, checking payjoin asks about the same value it did
+    /// when the answers were collected.
+    pub(crate) fn get<Q>(&self, value: &Q) -> Result<bool, payjoin::Error>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + PartialEq,
+    {
+        let index = self.next.replace(self.next.get() + 1);
+        self.given.record(
+            self.asked
+                .get(index)
+                .filter(|(asked, _)| asked.borrow() == value)
+                .map(|(_, answer)| *answer)
+                .ok_or_else(|| payjoin::Error::Server("No answer recorded for this value".into())),
+        )
+    }
+
+    /// The answers handed to payjoin so far, in the order it asked.
+    pub(crate) fn given(&self) -> Vec<bool> {
+        self.given.take()
+    }
+}
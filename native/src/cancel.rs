@@ -0,0 +1,57 @@
+use crate::error::{ErrorCode, ErrorStage, PayjoinError};
+use napi_derive::napi;
+use std::future::Future;
+
+/// Handle used to abort a long-running native operation from JavaScript.
+#[napi]
+#[derive(Clone, Default)]
+pub struct CancellationToken {
+    inner: tokio_util::sync::CancellationToken,
+}
+
+#[napi]
+impl CancellationToken {
+    #[napi(constructor)]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    #[napi]
+    pub fn cancel(&self) {
+        self.inner.cancel();
+    }
+
+    #[napi]
+    pub fn is_cancelled(&self) -> bool {
+        self.inner.is_cancelled()
+    }
+}
+
+impl CancellationToken {
+    /// Resolve once the token is cancelled.
+    pub(crate) async fn cancelled(&self) {
+        self.inner.cancelled().await
+    }
+}
+
+/// Run `future` until it completes or `token` is cancelled. Cancelling drops
+/// the future and rejects with a `Cancelled` error attributed to `stage`.
+pub(crate) async fn with_cancellation<F, T>(
+    token: Option<CancellationToken>,
+    stage: ErrorStage,
+    future: F,
+) -> napi::Result<T>
+where
+    F: Future<Output = napi::Result<T>>,
+{
+    let Some(token) = token else {
+        return future.await;
+    };
+
+    tokio::select! {
+        _ = token.inner.cancelled() => {
+            Err(PayjoinError::new(ErrorCode::Cancelled, stage, "Operation cancelled").into())
+        }
+        result = future => result,
+    }
+}
@@ -0,0 +1,260 @@
+use crate::envelope::{check_state, open, open_current, seal, FORMAT_VERSION};
+use crate::error::{ErrorCode, ErrorStage, PayjoinError, ResultExt};
+use chacha20poly1305::aead::rand_core::RngCore;
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng, Payload};
+use chacha20poly1305::{Key, XChaCha20Poly1305, XNonce};
+use napi::bindgen_prelude::Uint8Array;
+use napi_derive::napi;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use zeroize::Zeroizing;
+
+const KEY_BYTES: usize = 32;
+const SALT_BYTES: usize = 16;
+
+// scrypt cost for passphrases: 128 MiB and well under a second on a server.
+// Stored with every export, so it can be raised without breaking old ones.
+// An export is read before it can be authenticated, so parameters above
+// these are refused rather than spent on.
+const SCRYPT_LOG_N: u8 = 17;
+const SCRYPT_R: u32 = 8;
+const SCRYPT_P: u32 = 1;
+
+/// Secret an encrypted export is sealed with. Give exactly one of the two.
+#[napi(object)]
+pub struct ExportKey {
+    /// A random 32-byte key, e.g. from a KMS.
+    pub key: Option<Uint8Array>,
+    /// A passphrase, stretched with scrypt and a random salt.
+    pub passphrase: Option<String>,
+}
+
+/// An `ExportKey` checked and copied out of JavaScript, so that it can be
+/// kept, e.g. by a `SessionStore` that seals every session it saves.
+#[derive(Clone)]
+pub(crate) enum Secret {
+    Key(Zeroizing<[u8; KEY_BYTES]>),
+    Passphrase(Zeroizing<String>),
+}
+
+impl TryFrom<ExportKey> for Secret {
+    type Error = napi::Error;
+
+    fn try_from(secret: ExportKey) -> napi::Result<Self> {
+        match (secret.key, secret.passphrase) {
+            (Some(raw), None) => {
+                let mut key = Zeroizing::new([0u8; KEY_BYTES]);
+                if raw.len() != KEY_BYTES {
+                    return Err(invalid_key("Export key must be 32 bytes"));
+                }
+                key.copy_from_slice(&raw);
+                Ok(Secret::Key(key))
+            }
+            (None, Some(passphrase)) => Ok(Secret::Passphrase(Zeroizing::new(passphrase))),
+            _ => Err(invalid_key("Give exactly one of key and passphrase")),
+        }
+    }
+}
+
+/// How the cipher key was obtained.
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "name", rename_all = "camelCase")]
+enum Kdf {
+    Raw,
+    #[serde(rename_all = "camelCase")]
+    Scrypt {
+        log_n: u8,
+        r: u32,
+        p: u32,
+        salt: String,
+    },
+}
+
+/// An envelope sealed with XChaCha20-Poly1305. Everything but the ciphertext
+/// is authenticated as associated data, so neither the state tag nor the KDF
+/// parameters can be swapped.
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct EncryptedExport {
+    format: u64,
+    state: String,
+    kdf: Kdf,
+    nonce: String,
+    ciphertext: String,
+}
+
+impl EncryptedExport {
+    fn associated_data(&self) -> Vec<u8> {
+        serde_json::to_vec(&(self.format, &self.state, &self.kdf, &self.nonce))
+            .expect("header serializes to JSON")
+    }
+}
+
+fn invalid_key(message: &str) -> napi::Error {
+    PayjoinError::new(ErrorCode::InvalidOptions, ErrorStage::Persistence, message).into()
+}
+
+fn decode(field: &str, context: &str) -> napi::Result<Vec<u8>> {
+    base64::decode(field).or_payjoin(
+        ErrorCode::SerializationFailed,
+        ErrorStage::Persistence,
+        context,
+    )
+}
+
+/// The cipher key for `secret`. A passphrase is stretched with `kdf` when
+/// given, or with fresh parameters that are returned for the export.
+fn derive_key(
+    secret: &Secret,
+    kdf: Option<&Kdf>,
+    context: &str,
+) -> napi::Result<(Zeroizing<[u8; KEY_BYTES]>, Kdf)> {
+    match secret {
+        Secret::Key(key) => {
+            if !matches!(kdf, None | Some(Kdf::Raw)) {
+                return Err(invalid_key("Export was sealed with a passphrase"));
+            }
+            Ok((key.clone(), Kdf::Raw))
+        }
+        Secret::Passphrase(passphrase) => {
+            let (log_n, r, p, salt) = match kdf {
+                Some(Kdf::Scrypt { log_n, r, p, .. })
+                    if *log_n > SCRYPT_LOG_N || *r > SCRYPT_R || *p > SCRYPT_P =>
+                {
+                    return Err(PayjoinError::new(
+                        ErrorCode::SerializationFailed,
+                        ErrorStage::Persistence,
+                        format!(
+                            "{}: scrypt parameters exceed log_n {}, r {} and p {}",
+                            context, SCRYPT_LOG_N, SCRYPT_R, SCRYPT_P
+                        ),
+                    )
+                    .with_kind("UnsupportedKdf")
+                    .into())
+                }
+                Some(Kdf::Scrypt { log_n, r, p, salt }) => (*log_n, *r, *p, decode(salt, context)?),
+                Some(Kdf::Raw) => return Err(invalid_key("Export was sealed with a raw key")),
+                None => {
+                    let mut salt = vec![0u8; SALT_BYTES];
+                    OsRng.fill_bytes(&mut salt);
+                    (SCRYPT_LOG_N, SCRYPT_R, SCRYPT_P, salt)
+                }
+            };
+            let params = scrypt::Params::new(log_n, r, p, KEY_BYTES).or_payjoin(
+                ErrorCode::SerializationFailed,
+                ErrorStage::Persistence,
+                context,
+            )?;
+            let mut key = Zeroizing::new([0u8; KEY_BYTES]);
+            scrypt::scrypt(passphrase.as_bytes(), &salt, &params, key.as_mut()).or_payjoin(
+                ErrorCode::SerializationFailed,
+                ErrorStage::Persistence,
+                context,
+            )?;
+            let kdf = Kdf::Scrypt {
+                log_n,
+                r,
+                p,
+                salt: base64::encode(salt),
+            };
+            Ok((key, kdf))
+        }
+    }
+}
+
+/// Serialize `data` as `seal` does, then encrypt the result under `secret`.
+pub(crate) fn seal_encrypted<T: Serialize>(
+    state: &str,
+    data: &T,
+    secret: &Secret,
+    context: &str,
+) -> napi::Result<String> {
+    let plaintext = Zeroizing::new(seal(state, data, context)?);
+    let (key, kdf) = derive_key(secret, None, context)?;
+    let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+
+    let mut export = EncryptedExport {
+        format: FORMAT_VERSION,
+        state: state.to_string(),
+        kdf,
+        nonce: base64::encode(nonce),
+        ciphertext: String::new(),
+    };
+    let ciphertext = XChaCha20Poly1305::new(Key::from_slice(key.as_ref()))
+        .encrypt(
+            &nonce,
+            Payload {
+                msg: plaintext.as_bytes(),
+                aad: &export.associated_data(),
+            },
+        )
+        .map_err(|_| {
+            PayjoinError::new(
+                ErrorCode::SerializationFailed,
+                ErrorStage::Persistence,
+                context,
+            )
+        })?;
+    export.ciphertext = base64::encode(ciphertext);
+
+    serde_json::to_string(&export).or_payjoin(
+        ErrorCode::SerializationFailed,
+        ErrorStage::Persistence,
+        context,
+    )
+}
+
+/// Decrypt an export written by `seal_encrypted` and deserialize it as `open`
+/// does.
+pub(crate) fn open_encrypted<T: DeserializeOwned>(
+    state: &str,
+    json: &str,
+    secret: &Secret,
+    context: &str,
+) -> napi::Result<T> {
+    let export: EncryptedExport = serde_json::from_str(json).or_payjoin(
+        ErrorCode::SerializationFailed,
+        ErrorStage::Persistence,
+        context,
+    )?;
+    let (key, _) = derive_key(secret, Some(&export.kdf), context)?;
+    let nonce = decode(&export.nonce, context)?;
+    if nonce.len() != XNonce::default().len() {
+        return Err(PayjoinError::new(
+            ErrorCode::SerializationFailed,
+            ErrorStage::Persistence,
+            format!("{}: invalid nonce", context),
+        )
+        .into());
+    }
+
+    let plaintext = XChaCha20Poly1305::new(Key::from_slice(key.as_ref()))
+        .decrypt(
+            XNonce::from_slice(&nonce),
+            Payload {
+                msg: &decode(&export.ciphertext, context)?,
+                aad: &export.associated_data(),
+            },
+        )
+        .map(Zeroizing::new)
+        .map_err(|_| {
+            PayjoinError::new(
+                ErrorCode::SerializationFailed,
+                ErrorStage::Persistence,
+                format!("{}: wrong key or tampered export", context),
+            )
+            .with_kind("DecryptionFailed")
+        })?;
+    check_state(state, &export.state, context)?;
+    if export.format == FORMAT_VERSION {
+        return open_current(state, &plaintext, context);
+    }
+
+    // Earlier formats are migrated through a `Value`, which is not zeroized.
+    let plaintext = std::str::from_utf8(&plaintext).or_payjoin(
+        ErrorCode::SerializationFailed,
+        ErrorStage::Persistence,
+        context,
+    )?;
+    open(state, plaintext, context)
+}
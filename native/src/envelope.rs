@@ -0,0 +1,144 @@
+use crate::error::{ErrorCode, ErrorStage, PayjoinError, ResultExt};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// Version of the persisted format. Bump it whenever the serialized form of
+/// any state changes, including through a payjoin upgrade, and teach
+/// `migrate` to bring the previous version forward.
+pub(crate) const FORMAT_VERSION: u64 = 1;
+
+const CRATE_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// What every `to_json` produces: the state's serialized form, tagged with
+/// what it is and which format and release wrote it.
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Envelope<T> {
+    format: u64,
+    crate_version: String,
+    state: String,
+    data: T,
+}
+
+/// Serialize `data` as a `state` in the current format.
+pub(crate) fn seal<T: Serialize>(state: &str, data: &T, context: &str) -> napi::Result<String> {
+    serde_json::to_string(&Envelope {
+        format: FORMAT_VERSION,
+        crate_version: CRATE_VERSION.to_string(),
+        state: state.to_string(),
+        data,
+    })
+    .or_payjoin(
+        ErrorCode::SerializationFailed,
+        ErrorStage::Persistence,
+        context,
+    )
+}
+
+/// Deserialize a `state` written by `seal` in this or any earlier format.
+pub(crate) fn open<T: DeserializeOwned>(state: &str, json: &str, context: &str) -> napi::Result<T> {
+    let value: Value = serde_json::from_str(json).or_payjoin(
+        ErrorCode::SerializationFailed,
+        ErrorStage::Persistence,
+        context,
+    )?;
+    let envelope = migrate(value, state, context)?;
+    check_state(state, &envelope.state, context)?;
+
+    serde_json::from_value(envelope.data).or_payjoin(
+        ErrorCode::SerializationFailed,
+        ErrorStage::Persistence,
+        context,
+    )
+}
+
+/// Deserialize a `state` that `seal` wrote in the current format straight
+/// from `json`. Unlike `open`, nothing is parsed into an intermediate `Value`
+/// first, so the data is never copied out of a buffer the caller zeroizes.
+pub(crate) fn open_current<T: DeserializeOwned>(
+    state: &str,
+    json: &[u8],
+    context: &str,
+) -> napi::Result<T> {
+    let envelope: Envelope<T> = serde_json::from_slice(json).or_payjoin(
+        ErrorCode::SerializationFailed,
+        ErrorStage::Persistence,
+        context,
+    )?;
+    if envelope.format != FORMAT_VERSION {
+        return Err(PayjoinError::new(
+            ErrorCode::SerializationFailed,
+            ErrorStage::Persistence,
+            format!("{}: expected format {}", context, FORMAT_VERSION),
+        )
+        .into());
+    }
+    check_state(state, &envelope.state, context)?;
+    Ok(envelope.data)
+}
+
+pub(crate) fn check_state(expected: &str, found: &str, context: &str) -> napi::Result<()> {
+    if found != expected {
+        return Err(PayjoinError::new(
+            ErrorCode::SerializationFailed,
+            ErrorStage::Persistence,
+            format!("{}: expected a {} but found a {}", context, expected, found),
+        )
+        .into());
+    }
+    Ok(())
+}
+
+/// The states released before the envelope existed, whose `to_json` wrote
+/// their bare serde output.
+const BARE_STATES: [&str; 2] = ["PayjoinReceiver", "PayjoinSender"];
+
+/// Bring an envelope of any earlier format up to `FORMAT_VERSION`, one
+/// version at a time.
+fn migrate(value: Value, state: &str, context: &str) -> napi::Result<Envelope<Value>> {
+    let mut envelope = match serde_json::from_value::<Envelope<Value>>(value.clone()) {
+        Ok(envelope) => envelope,
+        Err(_) if BARE_STATES.contains(&state) => Envelope {
+            format: 0,
+            crate_version: "unknown".to_string(),
+            state: state.to_string(),
+            data: value,
+        },
+        Err(e) => {
+            return Err(e).or_payjoin(
+                ErrorCode::SerializationFailed,
+                ErrorStage::Persistence,
+                context,
+            )
+        }
+    };
+
+    if envelope.format > FORMAT_VERSION {
+        return Err(PayjoinError::new(
+            ErrorCode::SerializationFailed,
+            ErrorStage::Persistence,
+            format!(
+                "{}: format {} was written by {} and is newer than format {}",
+                context, envelope.format, envelope.crate_version, FORMAT_VERSION
+            ),
+        )
+        .with_kind("UnsupportedFormat")
+        .into());
+    }
+
+    while envelope.format < FORMAT_VERSION {
+        envelope = match envelope.format {
+            0 => from_v0(envelope),
+            _ => unreachable!("every format below FORMAT_VERSION has a migration"),
+        };
+    }
+    Ok(envelope)
+}
+
+/// Format 0 is the bare serde output of a `BARE_STATES` state, which format
+/// 1 wraps unchanged.
+fn from_v0(mut envelope: Envelope<Value>) -> Envelope<Value> {
+    envelope.format = 1;
+    envelope
+}
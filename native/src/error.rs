@@ -0,0 +1,418 @@
+use napi::Status;
+use napi_derive::napi;
+use payjoin::send::ResponseError;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// Stable error codes. Every error thrown by the bindings carries one of these
+/// as its `code` property.
+#[napi(string_enum)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ErrorCode {
+    InvalidAddress,
+    InvalidAmount,
+    InvalidFeeRate,
+    InvalidInput,
+    InvalidOhttpKeys,
+    InvalidPsbt,
+    InvalidScript,
+    InvalidUri,
+    InvalidUrl,
+    InvalidOptions,
+    UnsupportedUri,
+    CreateRequestFailed,
+    SessionExpired,
+    MissingContext,
+    NetworkError,
+    HttpStatus,
+    InvalidResponse,
+    ProposalRejected,
+    /// BIP78 `unavailable`: the receiver cannot process payjoins right now.
+    ReceiverUnavailable,
+    /// BIP78 `not-enough-money`: the receiver could not bump the fee.
+    NotEnoughMoney,
+    /// BIP78 `version-unsupported`: retry with one of the `supported` versions.
+    VersionUnsupported,
+    /// BIP78 `original-psbt-rejected`: the receiver refused the original PSBT.
+    OriginalPsbtRejected,
+    /// The receiver replied with an error code BIP78 does not define.
+    UnrecognizedReceiverError,
+    ReceiverFailed,
+    CallbackFailed,
+    SerializationFailed,
+    Cancelled,
+    /// An error that did not originate from the bindings.
+    Unknown,
+}
+
+impl ErrorCode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ErrorCode::InvalidAddress => "InvalidAddress",
+            ErrorCode::InvalidAmount => "InvalidAmount",
+            ErrorCode::InvalidFeeRate => "InvalidFeeRate",
+            ErrorCode::InvalidInput => "InvalidInput",
+            ErrorCode::InvalidOhttpKeys => "InvalidOhttpKeys",
+            ErrorCode::InvalidPsbt => "InvalidPsbt",
+            ErrorCode::InvalidScript => "InvalidScript",
+            ErrorCode::InvalidUri => "InvalidUri",
+            ErrorCode::InvalidUrl => "InvalidUrl",
+            ErrorCode::InvalidOptions => "InvalidOptions",
+            ErrorCode::UnsupportedUri => "UnsupportedUri",
+            ErrorCode::CreateRequestFailed => "CreateRequestFailed",
+            ErrorCode::SessionExpired => "SessionExpired",
+            ErrorCode::MissingContext => "MissingContext",
+            ErrorCode::NetworkError => "NetworkError",
+            ErrorCode::HttpStatus => "HttpStatus",
+            ErrorCode::InvalidResponse => "InvalidResponse",
+            ErrorCode::ProposalRejected => "ProposalRejected",
+            ErrorCode::ReceiverUnavailable => "ReceiverUnavailable",
+            ErrorCode::NotEnoughMoney => "NotEnoughMoney",
+            ErrorCode::VersionUnsupported => "VersionUnsupported",
+            ErrorCode::OriginalPsbtRejected => "OriginalPsbtRejected",
+            ErrorCode::UnrecognizedReceiverError => "UnrecognizedReceiverError",
+            ErrorCode::ReceiverFailed => "ReceiverFailed",
+            ErrorCode::CallbackFailed => "CallbackFailed",
+            ErrorCode::SerializationFailed => "SerializationFailed",
+            ErrorCode::Cancelled => "Cancelled",
+            ErrorCode::Unknown => "Unknown",
+        }
+    }
+}
+
+/// The part of the payjoin flow an error originated from.
+#[napi(string_enum)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ErrorStage {
+    Uri,
+    OhttpKeys,
+    Http,
+    SenderBuilder,
+    Sender,
+    Receiver,
+    Proposal,
+    Persistence,
+}
+
+impl ErrorStage {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ErrorStage::Uri => "Uri",
+            ErrorStage::OhttpKeys => "OhttpKeys",
+            ErrorStage::Http => "Http",
+            ErrorStage::SenderBuilder => "SenderBuilder",
+            ErrorStage::Sender => "Sender",
+            ErrorStage::Receiver => "Receiver",
+            ErrorStage::Proposal => "Proposal",
+            ErrorStage::Persistence => "Persistence",
+        }
+    }
+}
+
+/// Names the underlying error type so callers can tell apart, say, a PSBT
+/// validation failure from an OHTTP decapsulation failure under one code.
+pub(crate) trait ErrorKind {
+    fn kind(&self) -> &'static str;
+}
+
+macro_rules! impl_error_kind {
+    ($($ty:ty => $kind:literal),* $(,)?) => {
+        $(
+            impl ErrorKind for $ty {
+                fn kind(&self) -> &'static str {
+                    $kind
+                }
+            }
+        )*
+    };
+}
+
+impl_error_kind!(
+    url::ParseError => "UrlParseError",
+    payjoin::bitcoin::address::ParseError => "AddressParseError",
+    payjoin::bitcoin::psbt::PsbtParseError => "PsbtParseError",
+    payjoin::bitcoin::consensus::encode::Error => "ConsensusDecodeError",
+    payjoin::bitcoin::hex::HexToArrayError => "HexError",
+    payjoin::bitcoin::hex::HexToBytesError => "HexError",
+    payjoin::bitcoin::key::FromSliceError => "KeyError",
+    payjoin::bitcoin::secp256k1::Error => "Secp256k1Error",
+    payjoin::bitcoin::ecdsa::Error => "SignatureError",
+    payjoin::bitcoin::taproot::SigFromSliceError => "SignatureError",
+    payjoin::bitcoin::taproot::TaprootError => "TaprootError",
+    payjoin::bitcoin::hashes::FromSliceError => "HashError",
+    payjoin::PjParseError => "PjParseError",
+    payjoin::send::CreateRequestError => "CreateRequestError",
+    payjoin::receive::PsbtInputError => "PsbtInputError",
+    payjoin::receive::SelectionError => "SelectionError",
+    payjoin::receive::InputContributionError => "InputContributionError",
+    payjoin::receive::OutputSubstitutionError => "OutputSubstitutionError",
+    payjoin::io::Error => "IoError",
+    std::io::Error => "IoError",
+    ohttp::Error => "OhttpError",
+    bhttp::Error => "BhttpError",
+    reqwest::Error => "ReqwestError",
+    serde_json::Error => "SerdeJsonError",
+    base64::DecodeError => "Base64Error",
+    std::str::Utf8Error => "Utf8Error",
+    scrypt::errors::InvalidParams => "KdfError",
+    scrypt::errors::InvalidOutputLen => "KdfError",
+);
+
+impl ErrorKind for ResponseError {
+    fn kind(&self) -> &'static str {
+        match self {
+            ResponseError::WellKnown(_) => "WellKnownError",
+            ResponseError::Unrecognized { .. } => "UnrecognizedError",
+            ResponseError::Validation(_) => "ValidationError",
+        }
+    }
+}
+
+impl ErrorKind for payjoin::receive::Error {
+    fn kind(&self) -> &'static str {
+        match self {
+            payjoin::receive::Error::BadRequest(_) => "BadRequest",
+            payjoin::receive::Error::Server(_) => "Server",
+        }
+    }
+}
+
+/// Error raised by the bindings. Its code, stage, kind and details become
+/// properties of the JavaScript error once `toPayjoinError` reads them. A
+/// `cause`, such as the exception a JavaScript callback threw, becomes the
+/// `cause` of the JavaScript error.
+#[derive(Debug)]
+pub(crate) struct PayjoinError {
+    code: ErrorCode,
+    stage: ErrorStage,
+    kind: Option<&'static str>,
+    details: Option<serde_json::Value>,
+    message: String,
+    cause: Option<napi::Error>,
+}
+
+impl PayjoinError {
+    pub(crate) fn new(code: ErrorCode, stage: ErrorStage, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            stage,
+            kind: None,
+            details: None,
+            message: message.into(),
+            cause: None,
+        }
+    }
+
+    pub(crate) fn with_kind(mut self, kind: &'static str) -> Self {
+        self.kind = Some(kind);
+        self
+    }
+
+    pub(crate) fn with_details(mut self, details: serde_json::Value) -> Self {
+        self.details = Some(details);
+        self
+    }
+
+    pub(crate) fn with_cause(mut self, cause: napi::Error) -> Self {
+        self.cause = Some(cause);
+        self
+    }
+
+    /// Map an error reply from the receiver. BIP78 well-known errors get their
+    /// own code and carry the receiver's `errorCode`, `message` and, for
+    /// `version-unsupported`, the `supported` versions as details.
+    ///
+    /// Only a v1 reply can be a well-known or unrecognized error: a v2 reply
+    /// is encrypted, and payjoin reports one it cannot read as a proposal as a
+    /// validation error. `response` is the reply as received, from which the
+    /// `supported` versions are read since payjoin does not export them.
+    pub(crate) fn from_response(
+        error: ResponseError,
+        response: &[u8],
+        stage: ErrorStage,
+        context: &str,
+    ) -> Self {
+        let kind = error.kind();
+        let message = format!("{}: {}", context, error);
+        let (code, details) = match &error {
+            ResponseError::WellKnown(known) => {
+                let code = match known.error_code() {
+                    "unavailable" => ErrorCode::ReceiverUnavailable,
+                    "not-enough-money" => ErrorCode::NotEnoughMoney,
+                    "version-unsupported" => ErrorCode::VersionUnsupported,
+                    "original-psbt-rejected" => ErrorCode::OriginalPsbtRejected,
+                    _ => ErrorCode::UnrecognizedReceiverError,
+                };
+                let mut details = serde_json::json!({
+                    "errorCode": known.error_code(),
+                    "receiverMessage": known.message(),
+                });
+                if code == ErrorCode::VersionUnsupported {
+                    details["supported"] = supported_versions(response).into();
+                }
+                (code, Some(details))
+            }
+            ResponseError::Unrecognized {
+                error_code,
+                message,
+            } => (
+                ErrorCode::UnrecognizedReceiverError,
+                Some(serde_json::json!({
+                    "errorCode": error_code,
+                    "receiverMessage": message,
+                })),
+            ),
+            ResponseError::Validation(_) => (ErrorCode::InvalidResponse, None),
+        };
+
+        let error = Self::new(code, stage, message).with_kind(kind);
+        match details {
+            Some(details) => error.with_details(details),
+            None => error,
+        }
+    }
+}
+
+/// The `supported` versions of a BIP78 `version-unsupported` reply, read the
+/// way payjoin reads them.
+fn supported_versions(response: &[u8]) -> Vec<u64> {
+    serde_json::from_slice::<serde_json::Value>(response)
+        .ok()
+        .and_then(|reply| {
+            reply["supported"]
+                .as_array()
+                .map(|versions| versions.iter().filter_map(|v| v.as_u64()).collect())
+        })
+        .unwrap_or_default()
+}
+
+impl fmt::Display for PayjoinError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+/// Marks the start and end of the classification a `PayjoinError` carries at
+/// the front of its `napi::Error` reason, as napi builds the JavaScript error
+/// with no way to add properties. `toPayjoinError` moves it onto the error.
+/// It cannot occur in a message.
+const MARK: char = '\u{1}';
+
+/// How a `PayjoinError` is classified, as set on the JavaScript error.
+#[napi(object, object_from_js = false)]
+#[derive(Clone, Serialize, Deserialize)]
+pub struct ErrorInfo {
+    pub code: ErrorCode,
+    pub stage: Option<ErrorStage>,
+    /// The underlying payjoin error type, when known.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub kind: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub details: Option<serde_json::Value>,
+    #[serde(skip)]
+    pub message: String,
+}
+
+impl ErrorInfo {
+    /// Read back the classification of an error raised as a `PayjoinError`.
+    /// Any other error is `Unknown`.
+    pub(crate) fn of(error: &napi::Error) -> Self {
+        let classified = error
+            .reason
+            .strip_prefix(MARK)
+            .and_then(|rest| rest.split_once(MARK))
+            .and_then(|(info, message)| {
+                let info: ErrorInfo = serde_json::from_str(info).ok()?;
+                Some(ErrorInfo {
+                    message: message.to_string(),
+                    ..info
+                })
+            });
+        classified.unwrap_or_else(|| ErrorInfo {
+            code: ErrorCode::Unknown,
+            stage: None,
+            kind: None,
+            details: None,
+            message: error.reason.clone(),
+        })
+    }
+}
+
+/// The message of `error`, without the classification a `PayjoinError`
+/// carries.
+pub(crate) fn message(error: &napi::Error) -> &str {
+    error
+        .reason
+        .strip_prefix(MARK)
+        .and_then(|rest| rest.split_once(MARK))
+        .map_or(&error.reason, |(_, message)| message)
+}
+
+impl From<PayjoinError> for napi::Error {
+    fn from(error: PayjoinError) -> Self {
+        let status = match error.code {
+            ErrorCode::Cancelled => Status::Cancelled,
+            _ => Status::GenericFailure,
+        };
+        let info = ErrorInfo {
+            code: error.code,
+            stage: Some(error.stage),
+            kind: error.kind.map(str::to_string),
+            details: error.details,
+            message: String::new(),
+        };
+        let info = serde_json::to_string(&info).expect("error info is serializable");
+        let reason = format!("{}{}{}{}", MARK, info, MARK, error.message);
+        let mut napi_error = napi::Error::new(status, reason);
+        if let Some(cause) = error.cause {
+            napi_error.set_cause(cause);
+        }
+        napi_error
+    }
+}
+
+/// Map a foreign error into a `PayjoinError`, prefixing its message with
+/// `context`.
+pub(crate) trait ResultExt<T, E> {
+    /// Record the error's own kind.
+    fn or_payjoin(self, code: ErrorCode, stage: ErrorStage, context: &str) -> napi::Result<T>
+    where
+        E: ErrorKind;
+
+    /// Record an explicit kind, for error types payjoin does not export.
+    fn or_payjoin_kind(
+        self,
+        code: ErrorCode,
+        stage: ErrorStage,
+        kind: &'static str,
+        context: &str,
+    ) -> napi::Result<T>;
+}
+
+impl<T, E: fmt::Display> ResultExt<T, E> for Result<T, E> {
+    fn or_payjoin(self, code: ErrorCode, stage: ErrorStage, context: &str) -> napi::Result<T>
+    where
+        E: ErrorKind,
+    {
+        self.map_err(|e| {
+            let kind = e.kind();
+            PayjoinError::new(code, stage, format!("{}: {}", context, e))
+                .with_kind(kind)
+                .into()
+        })
+    }
+
+    fn or_payjoin_kind(
+        self,
+        code: ErrorCode,
+        stage: ErrorStage,
+        kind: &'static str,
+        context: &str,
+    ) -> napi::Result<T> {
+        self.map_err(|e| {
+            PayjoinError::new(code, stage, format!("{}: {}", context, e))
+                .with_kind(kind)
+                .into()
+        })
+    }
+}
@@ -1,3 +1,5 @@
+use crate::cancel::{with_cancellation, CancellationToken};
+use crate::error::{ErrorCode, ErrorStage, ResultExt};
 use napi::bindgen_prelude::Uint8Array;
 use napi::Result;
 use napi_derive::napi;
@@ -20,25 +22,41 @@ impl PayjoinOhttpKeys {
     pub async fn fetch(
         ohttp_relay: String,
         payjoin_directory: String,
+        cancellation_token: Option<&CancellationToken>,
     ) -> napi::Result<PayjoinOhttpKeys> {
-        let relay_url = Url::from_str(&ohttp_relay)
-            .map_err(|e| napi::Error::from_reason(format!("Invalid relay URL: {}", e)))?;
+        let token = cancellation_token.cloned();
+        let relay_url = Url::from_str(&ohttp_relay).or_payjoin(
+            ErrorCode::InvalidUrl,
+            ErrorStage::OhttpKeys,
+            "Invalid relay URL",
+        )?;
 
-        let directory_url = Url::from_str(&payjoin_directory)
-            .map_err(|e| napi::Error::from_reason(format!("Invalid directory URL: {}", e)))?;
+        let directory_url = Url::from_str(&payjoin_directory).or_payjoin(
+            ErrorCode::InvalidUrl,
+            ErrorStage::OhttpKeys,
+            "Invalid directory URL",
+        )?;
 
-        payjoin::io::fetch_ohttp_keys(relay_url, directory_url)
-            .await
-            .map(|keys| PayjoinOhttpKeys { inner: keys })
-            .map_err(|e| napi::Error::from_reason(format!("Failed to fetch OHTTP keys: {}", e)))
+        with_cancellation(token, ErrorStage::OhttpKeys, async move {
+            payjoin::io::fetch_ohttp_keys(relay_url, directory_url)
+                .await
+                .map(|keys| PayjoinOhttpKeys { inner: keys })
+                .or_payjoin(
+                    ErrorCode::NetworkError,
+                    ErrorStage::OhttpKeys,
+                    "Failed to fetch OHTTP keys",
+                )
+        })
+        .await
     }
 
     #[napi(ts_return_type = "Uint8Array")]
     pub fn to_bytes(&self) -> napi::Result<Uint8Array> {
-        let bytes = self
-            .inner
-            .encode()
-            .map_err(|e| napi::Error::from_reason(format!("Failed to encode OHTTP keys: {}", e)))?;
+        let bytes = self.inner.encode().or_payjoin(
+            ErrorCode::InvalidOhttpKeys,
+            ErrorStage::OhttpKeys,
+            "Failed to encode OHTTP keys",
+        )?;
         Ok(Uint8Array::new(bytes))
     }
 
@@ -47,6 +65,10 @@ impl PayjoinOhttpKeys {
         let vec_bytes: Vec<u8> = bytes.to_vec();
         OhttpKeys::decode(&vec_bytes)
             .map(|keys| PayjoinOhttpKeys { inner: keys })
-            .map_err(|e| napi::Error::from_reason(format!("Invalid OHTTP keys: {}", e)))
+            .or_payjoin(
+                ErrorCode::InvalidOhttpKeys,
+                ErrorStage::OhttpKeys,
+                "Invalid OHTTP keys",
+            )
     }
 }
@@ -0,0 +1,510 @@
+use crate::callback::Replay;
+use crate::envelope::{open, seal};
+use crate::error::{ErrorCode, ErrorStage, PayjoinError, ResultExt};
+use crate::original::{OriginalPsbt, SealedProposal};
+use payjoin::bitcoin::secp256k1::rand::{self, seq::SliceRandom, Rng};
+use payjoin::bitcoin::{
+    psbt::Input, psbt::Psbt, FeeRate, OutPoint, ScriptBuf, Transaction, TxIn, TxOut,
+};
+use payjoin::receive::v2::{
+    MaybeInputsOwned, MaybeInputsSeen, OutputsUnknown, PayjoinProposal, ProvisionalProposal,
+    Receiver, UncheckedProposal, WantsInputs, WantsOutputs,
+};
+use payjoin::receive::InputPair;
+use serde::{Deserialize, Serialize};
+
+/// A transition taken by a proposal, with whatever the wallet answered or
+/// supplied along the way.
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(tag = "step", rename_all = "camelCase")]
+pub(crate) enum Step {
+    #[serde(rename_all = "camelCase")]
+    CheckBroadcastSuitability {
+        min_fee_rate_sat_per_kwu: Option<u64>,
+        answers: Vec<bool>,
+    },
+    AssumeInteractiveReceiver,
+    CheckInputsNotOwned {
+        answers: Vec<bool>,
+    },
+    CheckNoInputsSeenBefore {
+        answers: Vec<bool>,
+    },
+    IdentifyReceiverOutputs {
+        answers: Vec<bool>,
+    },
+    SubstituteReceiverScript {
+        script: ScriptBuf,
+    },
+    /// `order` is the order the outputs were shuffled into, by script.
+    #[serde(rename_all = "camelCase")]
+    ReplaceReceiverOutputs {
+        outputs: Vec<TxOut>,
+        drain_script: ScriptBuf,
+        order: Vec<ScriptBuf>,
+    },
+    CommitOutputs,
+    /// `order` is the order the inputs were shuffled into, by outpoint.
+    TryContributeInputs {
+        candidates: Vec<(TxIn, Input)>,
+        order: Vec<OutPoint>,
+    },
+    ContributeInputs {
+        inputs: Vec<(TxIn, Input)>,
+        order: Vec<OutPoint>,
+    },
+    CommitInputs,
+    #[serde(rename_all = "camelCase")]
+    FinalizeProposal {
+        min_fee_rate_sat_per_kwu: Option<u64>,
+        max_fee_rate_sat_per_kwu: u64,
+        psbt: Psbt,
+    },
+}
+
+/// One of payjoin's receiver typestates, as rebuilt from a journal.
+pub(crate) enum Typestate {
+    UncheckedProposal(UncheckedProposal),
+    MaybeInputsOwned(MaybeInputsOwned),
+    MaybeInputsSeen(MaybeInputsSeen),
+    OutputsUnknown(OutputsUnknown),
+    WantsOutputs(WantsOutputs),
+    WantsInputs(WantsInputs),
+    ProvisionalProposal(ProvisionalProposal),
+    PayjoinProposal(PayjoinProposal),
+}
+
+/// A typestate that can be persisted, tagged with its name.
+pub(crate) trait Persisted: Sized {
+    const STATE: &'static str;
+
+    fn from_typestate(state: Typestate) -> Option<Self>;
+}
+
+macro_rules! impl_persisted {
+    ($($ty:ident),* $(,)?) => {
+        impl Typestate {
+            fn name(&self) -> &'static str {
+                match self {
+                    $(Typestate::$ty(_) => stringify!($ty),)*
+                }
+            }
+        }
+
+        $(
+            impl Persisted for $ty {
+                const STATE: &'static str = stringify!($ty);
+
+                fn from_typestate(state: Typestate) -> Option<Self> {
+                    match state {
+                        Typestate::$ty(inner) => Some(inner),
+                        _ => None,
+                    }
+                }
+            }
+        )*
+    };
+}
+
+impl_persisted!(
+    UncheckedProposal,
+    MaybeInputsOwned,
+    MaybeInputsSeen,
+    OutputsUnknown,
+    WantsOutputs,
+    WantsInputs,
+    ProvisionalProposal,
+    PayjoinProposal,
+);
+
+fn fee_rate(sat_per_kwu: Option<u64>) -> Option<FeeRate> {
+    sat_per_kwu.map(FeeRate::from_sat_per_kwu)
+}
+
+pub(crate) fn input_pairs(
+    parts: &[(TxIn, Input)],
+) -> Result<Vec<InputPair>, payjoin::receive::PsbtInputError> {
+    parts
+        .iter()
+        .map(|(txin, psbtin)| InputPair::new(txin.clone(), psbtin.clone()))
+        .collect()
+}
+
+impl Step {
+    fn name(&self) -> &'static str {
+        match self {
+            Step::CheckBroadcastSuitability { .. } => "checkBroadcastSuitability",
+            Step::AssumeInteractiveReceiver => "assumeInteractiveReceiver",
+            Step::CheckInputsNotOwned { .. } => "checkInputsNotOwned",
+            Step::CheckNoInputsSeenBefore { .. } => "checkNoInputsSeenBefore",
+            Step::IdentifyReceiverOutputs { .. } => "identifyReceiverOutputs",
+            Step::SubstituteReceiverScript { .. } => "substituteReceiverScript",
+            Step::ReplaceReceiverOutputs { .. } => "replaceReceiverOutputs",
+            Step::CommitOutputs => "commitOutputs",
+            Step::TryContributeInputs { .. } => "tryContributeInputs",
+            Step::ContributeInputs { .. } => "contributeInputs",
+            Step::CommitInputs => "commitInputs",
+            Step::FinalizeProposal { .. } => "finalizeProposal",
+        }
+    }
+
+    /// Take this step again from `state`. The same answers lead to the same
+    /// typestate, except that payjoin shuffles contributed inputs and
+    /// replaced outputs at random, so a replayed typestate may hold them in
+    /// another order. The order first drawn is recorded with the step and
+    /// applied by `Journal::arrange` before the wallet sees the PSBT.
+    fn replay(&self, state: Typestate) -> napi::Result<Typestate> {
+        let code = ErrorCode::SerializationFailed;
+        let stage = ErrorStage::Persistence;
+        let context = "Failed to replay proposal";
+
+        match (self, state) {
+            (
+                Step::CheckBroadcastSuitability {
+                    min_fee_rate_sat_per_kwu,
+                    answers,
+                },
+                Typestate::UncheckedProposal(proposal),
+            ) => {
+                let replay = Replay::new(answers);
+                proposal
+                    .check_broadcast_suitability(fee_rate(*min_fee_rate_sat_per_kwu), |_| {
+                        replay.answer()
+                    })
+                    .map(Typestate::MaybeInputsOwned)
+                    .or_payjoin(code, stage, context)
+            }
+            (Step::AssumeInteractiveReceiver, Typestate::UncheckedProposal(proposal)) => Ok(
+                Typestate::MaybeInputsOwned(proposal.assume_interactive_receiver()),
+            ),
+            (Step::CheckInputsNotOwned { answers }, Typestate::MaybeInputsOwned(proposal)) => {
+                let replay = Replay::new(answers);
+                proposal
+                    .check_inputs_not_owned(|_| replay.answer())
+                    .map(Typestate::MaybeInputsSeen)
+                    .or_payjoin(code, stage, context)
+            }
+            (Step::CheckNoInputsSeenBefore { answers }, Typestate::MaybeInputsSeen(proposal)) => {
+                let replay = Replay::new(answers);
+                proposal
+                    .check_no_inputs_seen_before(|_| replay.answer())
+                    .map(Typestate::OutputsUnknown)
+                    .or_payjoin(code, stage, context)
+            }
+            (Step::IdentifyReceiverOutputs { answers }, Typestate::OutputsUnknown(proposal)) => {
+                let replay = Replay::new(answers);
+                proposal
+                    .identify_receiver_outputs(|_| replay.answer())
+                    .map(Typestate::WantsOutputs)
+                    .or_payjoin(code, stage, context)
+            }
+            (Step::SubstituteReceiverScript { script }, Typestate::WantsOutputs(proposal)) => {
+                proposal
+                    .substitute_receiver_script(script)
+                    .map(Typestate::WantsOutputs)
+                    .or_payjoin(code, stage, context)
+            }
+            (
+                Step::ReplaceReceiverOutputs {
+                    outputs,
+                    drain_script,
+                    ..
+                },
+                Typestate::WantsOutputs(proposal),
+            ) => proposal
+                .replace_receiver_outputs(outputs.clone(), drain_script)
+                .map(Typestate::WantsOutputs)
+                .or_payjoin(code, stage, context),
+            (Step::CommitOutputs, Typestate::WantsOutputs(proposal)) => {
+                Ok(Typestate::WantsInputs(proposal.commit_outputs()))
+            }
+            (Step::TryContributeInputs { candidates, .. }, Typestate::WantsInputs(proposal)) => {
+                let candidates = input_pairs(candidates).or_payjoin(code, stage, context)?;
+                let selected = proposal
+                    .try_preserving_privacy(candidates)
+                    .or_payjoin(code, stage, context)?;
+                proposal
+                    .contribute_inputs(vec![selected])
+                    .map(|proposal| Typestate::ProvisionalProposal(proposal.commit_inputs()))
+                    .or_payjoin(code, stage, context)
+            }
+            (Step::ContributeInputs { inputs, .. }, Typestate::WantsInputs(proposal)) => {
+                let inputs = input_pairs(inputs).or_payjoin(code, stage, context)?;
+                proposal
+                    .contribute_inputs(inputs)
+                    .map(Typestate::WantsInputs)
+                    .or_payjoin(code, stage, context)
+            }
+            (Step::CommitInputs, Typestate::WantsInputs(proposal)) => {
+                Ok(Typestate::ProvisionalProposal(proposal.commit_inputs()))
+            }
+            (
+                Step::FinalizeProposal {
+                    min_fee_rate_sat_per_kwu,
+                    max_fee_rate_sat_per_kwu,
+                    psbt,
+                },
+                Typestate::ProvisionalProposal(proposal),
+            ) => proposal
+                .finalize_proposal(
+                    |_| Ok(psbt.clone()),
+                    fee_rate(*min_fee_rate_sat_per_kwu),
+                    FeeRate::from_sat_per_kwu(*max_fee_rate_sat_per_kwu),
+                )
+                .map(Typestate::PayjoinProposal)
+                .or_payjoin(code, stage, context),
+            (step, state) => Err(PayjoinError::new(
+                code,
+                stage,
+                format!("{}: cannot {} from {}", context, step.name(), state.name()),
+            )
+            .into()),
+        }
+    }
+}
+
+/// How a proposal reached its typestate: the sealed proposal the directory
+/// delivered and every step taken since. payjoin's typestates cannot be
+/// serialized, so they are persisted as this journal and rebuilt by replaying
+/// it against the session.
+#[derive(Clone, Serialize, Deserialize)]
+pub(crate) struct Journal {
+    proposal: SealedProposal,
+    steps: Vec<Step>,
+}
+
+impl Journal {
+    pub(crate) fn new(proposal: SealedProposal) -> Self {
+        Self {
+            proposal,
+            steps: Vec::new(),
+        }
+    }
+
+    /// The journal of the typestate reached by taking `step`.
+    pub(crate) fn then(&self, step: Step) -> Self {
+        let mut journal = self.clone();
+        journal.steps.push(step);
+        journal
+    }
+
+    /// The input and output orders recorded by the last steps that shuffled
+    /// them. Substituting the receiver's script rebuilds the outputs in their
+    /// original order, so it drops any recorded output order.
+    fn order(&self) -> (Option<&[OutPoint]>, Option<&[ScriptBuf]>) {
+        let mut inputs = None;
+        let mut outputs = None;
+        for step in &self.steps {
+            match step {
+                Step::SubstituteReceiverScript { .. } => outputs = None,
+                Step::ReplaceReceiverOutputs { order, .. } => outputs = Some(order.as_slice()),
+                Step::TryContributeInputs { order, .. } | Step::ContributeInputs { order, .. } => {
+                    inputs = Some(order.as_slice())
+                }
+                _ => {}
+            }
+        }
+        (inputs, outputs)
+    }
+
+    /// The receiver's outputs in the original transaction, as answered to
+    /// `identify_receiver_outputs`, which asks about every output in order.
+    fn owned_vouts(&self) -> Vec<usize> {
+        self.steps
+            .iter()
+            .rev()
+            .find_map(|step| match step {
+                Step::IdentifyReceiverOutputs { answers } => Some(
+                    answers
+                        .iter()
+                        .enumerate()
+                        .filter(|(_, owned)| **owned)
+                        .map(|(vout, _)| vout)
+                        .collect(),
+                ),
+                _ => None,
+            })
+            .unwrap_or_default()
+    }
+
+    /// Draw the input order after contributing `contributed` to a proposal
+    /// built on `original`. As in payjoin, each is inserted at a random
+    /// position among the inputs there were before the contribution.
+    pub(crate) fn draw_input_order(
+        &self,
+        original: &Transaction,
+        contributed: impl IntoIterator<Item = OutPoint>,
+    ) -> Vec<OutPoint> {
+        let mut order = match self.order().0 {
+            Some(order) => order.to_vec(),
+            None => original
+                .input
+                .iter()
+                .map(|txin| txin.previous_output)
+                .collect(),
+        };
+        let len = order.len();
+        let mut rng = rand::thread_rng();
+        for outpoint in contributed {
+            order.insert(rng.gen_range(0..=len), outpoint);
+        }
+        order
+    }
+
+    /// Draw the output order after replacing the receiver's outputs in
+    /// `original` with `replacements`. As in payjoin, each receiver output
+    /// takes the replacement with its script, or else a random one, and the
+    /// rest are shuffled in among the outputs without moving the sender's.
+    /// Only called once payjoin has accepted the replacements, so there are
+    /// enough of them for every receiver output.
+    pub(crate) fn draw_output_order(
+        &self,
+        original: &Transaction,
+        replacements: &[TxOut],
+    ) -> Vec<ScriptBuf> {
+        let owned = self.owned_vouts();
+        let mut rng = rand::thread_rng();
+        let mut remaining: Vec<ScriptBuf> = replacements
+            .iter()
+            .map(|txout| txout.script_pubkey.clone())
+            .collect();
+        let mut placed = Vec::with_capacity(remaining.len() + original.output.len());
+        for (vout, txout) in original.output.iter().enumerate() {
+            if !owned.contains(&vout) {
+                placed.push(txout.script_pubkey.clone());
+                continue;
+            }
+            let index = remaining
+                .iter()
+                .position(|script| *script == txout.script_pubkey)
+                .unwrap_or_else(|| rng.gen_range(0..remaining.len()));
+            placed.push(remaining.swap_remove(index));
+        }
+        remaining.shuffle(&mut rng);
+
+        let mut placed = placed.into_iter().peekable();
+        let mut remaining = remaining.into_iter().peekable();
+        let mut order = Vec::new();
+        while let Some(next) = match (placed.peek(), remaining.peek()) {
+            (Some(_), Some(_)) if rng.gen_bool(0.5) => placed.next(),
+            (Some(_), None) => placed.next(),
+            _ => remaining.next(),
+        } {
+            order.push(next);
+        }
+        order
+    }
+
+    /// `psbt` with its inputs and outputs in the recorded order, in place of
+    /// whatever order payjoin's own shuffle left them in, so a restored
+    /// proposal hands the wallet the PSBT the original would have. `None` if
+    /// the recorded order does not list exactly the PSBT's inputs or outputs.
+    pub(crate) fn arrange(&self, psbt: &Psbt) -> Option<Psbt> {
+        let (inputs, outputs) = self.order();
+        let mut psbt = psbt.clone();
+        if let Some(order) = inputs {
+            let pairs = psbt.unsigned_tx.input.drain(..).zip(psbt.inputs.drain(..));
+            let pairs = reorder(pairs.collect(), order, |(txin, _)| &txin.previous_output)?;
+            (psbt.unsigned_tx.input, psbt.inputs) = pairs.into_iter().unzip();
+        }
+        if let Some(order) = outputs {
+            let pairs = psbt
+                .unsigned_tx
+                .output
+                .drain(..)
+                .zip(psbt.outputs.drain(..));
+            let pairs = reorder(pairs.collect(), order, |(txout, _)| &txout.script_pubkey)?;
+            (psbt.unsigned_tx.output, psbt.outputs) = pairs.into_iter().unzip();
+        }
+        Some(psbt)
+    }
+
+    fn replay(&self, session: &mut Receiver) -> napi::Result<(Typestate, OriginalPsbt)> {
+        let (proposal, original) = self.proposal.unchecked(session)?;
+        let state = self
+            .steps
+            .iter()
+            .try_fold(Typestate::UncheckedProposal(proposal), |state, step| {
+                step.replay(state)
+            })?;
+        Ok((state, original))
+    }
+}
+
+/// `items` in `order`, each key taking the first item with that key not yet
+/// taken. `None` unless every item is taken exactly once.
+fn reorder<T, K: PartialEq>(items: Vec<T>, order: &[K], key: impl Fn(&T) -> &K) -> Option<Vec<T>> {
+    if items.len() != order.len() {
+        return None;
+    }
+    let mut items: Vec<Option<T>> = items.into_iter().map(Some).collect();
+    order
+        .iter()
+        .map(|wanted| {
+            items
+                .iter_mut()
+                .find(|item| item.as_ref().is_some_and(|item| key(item) == wanted))?
+                .take()
+        })
+        .collect()
+}
+
+#[derive(Serialize)]
+struct PersistedRef<'a> {
+    session: &'a Receiver,
+    #[serde(flatten)]
+    journal: &'a Journal,
+}
+
+#[derive(Deserialize)]
+struct PersistedOwned {
+    session: Receiver,
+    #[serde(flatten)]
+    journal: Journal,
+}
+
+/// Serialize a proposal in typestate `T`, tagged with the typestate's name.
+pub(crate) fn persist<T: Persisted>(session: &Receiver, journal: &Journal) -> napi::Result<String> {
+    seal(
+        T::STATE,
+        &PersistedRef { session, journal },
+        "Failed to serialize proposal",
+    )
+}
+
+/// A proposal rebuilt from its serialized form.
+pub(crate) struct Restored<T> {
+    pub(crate) inner: T,
+    pub(crate) session: Receiver,
+    pub(crate) original: OriginalPsbt,
+    pub(crate) journal: Journal,
+}
+
+/// Rebuild a proposal serialized by `persist`, checking it was persisted in
+/// typestate `T`.
+pub(crate) fn restore<T: Persisted>(json: &str) -> napi::Result<Restored<T>> {
+    let persisted: PersistedOwned = open(T::STATE, json, "Failed to deserialize proposal")?;
+
+    let mut session = persisted.session;
+    let (state, original) = persisted.journal.replay(&mut session)?;
+    let name = state.name();
+    let inner = T::from_typestate(state).ok_or_else(|| -> napi::Error {
+        PayjoinError::new(
+            ErrorCode::SerializationFailed,
+            ErrorStage::Persistence,
+            format!(
+                "Failed to deserialize proposal: expected a {} but replayed to a {}",
+                T::STATE,
+                name
+            ),
+        )
+        .into()
+    })?;
+
+    Ok(Restored {
+        inner,
+        session,
+        original,
+        journal: persisted.journal,
+    })
+}
@@ -1,13 +1,36 @@
 #![deny(clippy::all)]
 
+mod amount;
+mod callback;
+mod cancel;
+mod encrypt;
+mod envelope;
+mod error;
 mod io;
+mod journal;
+mod logging;
+mod manager;
+mod original;
+mod poll;
 mod receive;
+mod reply;
 mod request;
 mod send;
+mod store;
 mod uri;
 
+pub use callback::*;
+pub use cancel::*;
+pub use encrypt::*;
+pub use error::*;
 pub use io::*;
+pub use logging::*;
+pub use manager::*;
+pub use original::*;
+pub use poll::*;
 pub use receive::*;
+pub use reply::*;
 pub use request::*;
 pub use send::*;
+pub use store::*;
 pub use uri::*;
@@ -0,0 +1,171 @@
+use log::{LevelFilter, Log, Metadata, Record};
+use napi::bindgen_prelude::Unknown;
+use napi::threadsafe_function::{ThreadsafeFunction, ThreadsafeFunctionCallMode};
+use napi::Status;
+use napi_derive::napi;
+use payjoin::bitcoin::hashes::{sha256, Hash};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::RwLock;
+
+/// Verbosity of the diagnostics forwarded to JavaScript.
+#[napi(string_enum)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LogLevel {
+    Off,
+    Error,
+    Warn,
+    Info,
+    Debug,
+    Trace,
+}
+
+impl From<LogLevel> for LevelFilter {
+    fn from(level: LogLevel) -> Self {
+        match level {
+            LogLevel::Off => LevelFilter::Off,
+            LogLevel::Error => LevelFilter::Error,
+            LogLevel::Warn => LevelFilter::Warn,
+            LogLevel::Info => LevelFilter::Info,
+            LogLevel::Debug => LevelFilter::Debug,
+            LogLevel::Trace => LevelFilter::Trace,
+        }
+    }
+}
+
+impl From<log::Level> for LogLevel {
+    fn from(level: log::Level) -> Self {
+        match level {
+            log::Level::Error => LogLevel::Error,
+            log::Level::Warn => LogLevel::Warn,
+            log::Level::Info => LogLevel::Info,
+            log::Level::Debug => LogLevel::Debug,
+            log::Level::Trace => LogLevel::Trace,
+        }
+    }
+}
+
+/// How much of PSBTs, transactions, txids, scripts and OHTTP payloads reaches
+/// the logger.
+#[napi(string_enum)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LogRedaction {
+    /// Replace them with a short prefix and a hash. The default.
+    Redacted,
+    /// Log them in full. Only meant for local debugging.
+    Full,
+}
+
+/// A single diagnostic emitted by the bindings or by rust-payjoin.
+#[napi(object)]
+pub struct LogRecord {
+    pub level: LogLevel,
+    /// The Rust module the record came from, e.g. `payjoin::receive::v2`.
+    pub target: String,
+    pub message: String,
+}
+
+// Weak so an installed logger never keeps the Node.js process alive.
+type LogCallback = ThreadsafeFunction<LogRecord, Unknown<'static>, LogRecord, Status, false, true>;
+
+static CALLBACK: RwLock<Option<LogCallback>> = RwLock::new(None);
+static FULL_DIAGNOSTICS: AtomicBool = AtomicBool::new(false);
+static JS_LOGGER: JsLogger = JsLogger;
+
+struct JsLogger;
+
+impl Log for JsLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= log::max_level()
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        let callback = CALLBACK.read().unwrap_or_else(|e| e.into_inner());
+        if let Some(callback) = callback.as_ref() {
+            callback.call(
+                LogRecord {
+                    level: record.level().into(),
+                    target: record.target().to_string(),
+                    message: redact(&record.args().to_string()),
+                },
+                ThreadsafeFunctionCallMode::NonBlocking,
+            );
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+// Long enough to spare words and amounts, short enough to catch a 20-byte
+// script hash in hex.
+const MIN_SENSITIVE_LEN: usize = 32;
+const PREFIX_LEN: usize = 8;
+
+/// Replace every long hex, base64 or bech32 run in `message` with its first
+/// characters and a truncated SHA-256, unless full diagnostics are enabled.
+/// This covers PSBTs, transactions, txids, scripts and keys whether they come
+/// from the bindings or from rust-payjoin.
+fn redact(message: &str) -> String {
+    if FULL_DIAGNOSTICS.load(Ordering::Relaxed) {
+        return message.to_string();
+    }
+
+    let is_token = |c: char| c.is_ascii_alphanumeric() || matches!(c, '+' | '/' | '=');
+    let mut redacted = String::with_capacity(message.len());
+    let mut rest = message;
+    while let Some(start) = rest.find(is_token) {
+        redacted.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let end = rest.find(|c: char| !is_token(c)).unwrap_or(rest.len());
+        let token = &rest[..end];
+        if token.len() >= MIN_SENSITIVE_LEN {
+            let hash = sha256::Hash::hash(token.as_bytes()).to_string();
+            redacted.push_str(&format!(
+                "{}…[{} chars, sha256:{}]",
+                &token[..PREFIX_LEN],
+                token.len(),
+                &hash[..8]
+            ));
+        } else {
+            redacted.push_str(token);
+        }
+        rest = &rest[end..];
+    }
+    redacted.push_str(rest);
+    redacted
+}
+
+/// Forward diagnostics at `level` and above (default `Info`) to `callback`.
+/// Nothing is logged until a callback is installed, and the bindings never
+/// write log files themselves. Sensitive values are redacted unless
+/// `setLogRedaction('Full')` was called.
+#[napi(ts_args_type = "callback: (record: LogRecord) => void, level?: LogLevel | undefined | null")]
+pub fn set_logger(callback: LogCallback, level: Option<LogLevel>) {
+    // Fails when a logger is already installed: either ours from an earlier
+    // call, or the host's, in which case records keep going there.
+    let _ = log::set_logger(&JS_LOGGER);
+
+    *CALLBACK.write().unwrap_or_else(|e| e.into_inner()) = Some(callback);
+    log::set_max_level(level.unwrap_or(LogLevel::Info).into());
+}
+
+/// Choose whether sensitive values are redacted from log records.
+#[napi]
+pub fn set_log_redaction(redaction: LogRedaction) {
+    FULL_DIAGNOSTICS.store(redaction == LogRedaction::Full, Ordering::Relaxed);
+}
+
+/// Change the verbosity of the installed logger.
+#[napi]
+pub fn set_log_level(level: LogLevel) {
+    log::set_max_level(level.into());
+}
+
+/// Stop forwarding diagnostics to JavaScript.
+#[napi]
+pub fn clear_logger() {
+    log::set_max_level(LevelFilter::Off);
+    *CALLBACK.write().unwrap_or_else(|e| e.into_inner()) = None;
+}
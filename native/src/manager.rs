@@ -0,0 +1,279 @@
+use crate::cancel::CancellationToken;
+use crate::error::{ErrorCode, ErrorInfo, ErrorStage, PayjoinError};
+use crate::poll::{Backoff, PollOptions};
+use crate::receive::{poll_once, PayjoinReceiver, UncheckedProposalWrapper};
+use crate::reply::{session_keys, short_id};
+use napi::bindgen_prelude::Unknown;
+use napi::threadsafe_function::{ThreadsafeFunction, ThreadsafeFunctionCallMode};
+use napi::Status;
+use napi_derive::napi;
+use payjoin::receive::v2::Receiver;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+use tokio::sync::{Notify, Semaphore};
+use tokio::task::{JoinError, JoinSet};
+
+const DEFAULT_MAX_CONCURRENT_POLLS: u32 = 16;
+
+/// How a `ReceiverManager` polls its sessions.
+#[napi(object)]
+#[derive(Clone, Default)]
+pub struct ReceiverManagerOptions {
+    /// Most polls in flight at once across all sessions. Defaults to 16.
+    pub max_concurrent_polls: Option<u32>,
+    /// Schedule each session is polled on. `timeoutMs` is ignored: sessions
+    /// are polled until they yield a proposal, expire or are removed.
+    pub poll: Option<PollOptions>,
+}
+
+/// What happened to a managed session.
+#[napi(string_enum)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReceiverEventKind {
+    /// A sender posted an original PSBT. The session leaves the manager.
+    Proposal,
+    /// The session expired before a proposal arrived and was retired.
+    Expired,
+    /// A poll failed. The session stays and is polled again, unless its
+    /// poller itself failed, in which case it is retired.
+    Error,
+}
+
+/// Reported to the callback given to `ReceiverManager::run`.
+#[napi(object, object_from_js = false)]
+pub struct ReceiverEvent {
+    pub kind: ReceiverEventKind,
+    /// The session's ID, as returned by `add`.
+    pub session_id: String,
+    /// The sender's proposal, for `Proposal` events.
+    pub proposal: Option<UncheckedProposalWrapper>,
+    /// The failure, for `Error` events.
+    pub error: Option<ErrorInfo>,
+}
+
+type EventCallback =
+    ThreadsafeFunction<ReceiverEvent, Unknown<'static>, ReceiverEvent, Status, false>;
+
+struct Session {
+    receiver: Receiver,
+    expiry: SystemTime,
+}
+
+type Sessions = Arc<Mutex<HashMap<String, Session>>>;
+
+/// Owns many receiver sessions, e.g. one per invoice, and polls them all
+/// concurrently while `run` is pending.
+#[napi]
+pub struct ReceiverManager {
+    sessions: Sessions,
+    added: Arc<Notify>,
+    limit: Arc<Semaphore>,
+    poll: PollOptions,
+}
+
+#[napi]
+impl ReceiverManager {
+    #[napi(constructor)]
+    pub fn new(options: Option<ReceiverManagerOptions>) -> napi::Result<Self> {
+        let options = options.unwrap_or_default();
+        let max_concurrent_polls = options
+            .max_concurrent_polls
+            .unwrap_or(DEFAULT_MAX_CONCURRENT_POLLS);
+        if max_concurrent_polls == 0 {
+            return Err(PayjoinError::new(
+                ErrorCode::InvalidOptions,
+                ErrorStage::Receiver,
+                "Concurrency limit must be at least 1",
+            )
+            .into());
+        }
+
+        let poll = PollOptions {
+            timeout_ms: None,
+            ..options.poll.unwrap_or_default()
+        };
+        // Fail here rather than once per session while running.
+        Backoff::new(Some(poll.clone()), None, ErrorStage::Receiver)?;
+
+        Ok(Self {
+            sessions: Arc::default(),
+            added: Arc::default(),
+            limit: Arc::new(Semaphore::new(max_concurrent_polls as usize)),
+            poll,
+        })
+    }
+
+    /// Start managing `receiver` and return its ID, which is its mailbox on
+    /// the directory. Adding the same session again keeps a single copy.
+    #[napi]
+    pub fn add(&self, receiver: &PayjoinReceiver) -> napi::Result<String> {
+        let receiver = receiver.session().clone();
+        let keys = session_keys(&receiver)?;
+        let id = short_id(keys.s.public_key());
+
+        self.sessions.lock().unwrap().insert(
+            id.clone(),
+            Session {
+                receiver,
+                expiry: keys.expiry,
+            },
+        );
+        self.added.notify_one();
+        Ok(id)
+    }
+
+    /// Stop managing a session. Returns whether it was managed.
+    #[napi]
+    pub fn remove(&self, id: String) -> bool {
+        self.sessions.lock().unwrap().remove(&id).is_some()
+    }
+
+    /// IDs of the sessions still being managed.
+    #[napi]
+    pub fn session_ids(&self) -> Vec<String> {
+        self.sessions.lock().unwrap().keys().cloned().collect()
+    }
+
+    /// Poll every session until the token is cancelled, reporting proposals,
+    /// expiries and failed polls to `on_event`. Sessions added while running
+    /// are picked up right away. Resolves once cancelled.
+    #[napi(
+        ts_args_type = "onEvent: (event: ReceiverEvent) => void, cancellationToken?: CancellationToken | undefined | null"
+    )]
+    pub async fn run(
+        &self,
+        on_event: EventCallback,
+        cancellation_token: Option<&CancellationToken>,
+    ) -> napi::Result<()> {
+        let token = cancellation_token.cloned().unwrap_or_default();
+        let on_event = Arc::new(on_event);
+        let mut tasks = JoinSet::new();
+        // Session polled by each task, so a task that panicked can still be
+        // traced back to its session.
+        let mut polling = HashMap::new();
+
+        loop {
+            let idle: Vec<String> = self
+                .sessions
+                .lock()
+                .unwrap()
+                .keys()
+                .filter(|id| !polling.values().any(|polled| polled == *id))
+                .cloned()
+                .collect();
+            for id in idle {
+                let task = tasks.spawn(poll_session(
+                    id.clone(),
+                    self.sessions.clone(),
+                    self.limit.clone(),
+                    Backoff::new(Some(self.poll.clone()), None, ErrorStage::Receiver)?,
+                    on_event.clone(),
+                ));
+                polling.insert(task.id(), id);
+            }
+
+            tokio::select! {
+                _ = token.cancelled() => break,
+                _ = self.added.notified() => {}
+                Some(joined) = tasks.join_next_with_id() => match joined {
+                    Ok((task, _)) => {
+                        polling.remove(&task);
+                    }
+                    Err(e) => {
+                        if let Some(id) = polling.remove(&e.id()) {
+                            self.retire_failed(id, e, &on_event);
+                        }
+                    }
+                },
+            }
+        }
+
+        tasks.abort_all();
+        Ok(())
+    }
+}
+
+impl ReceiverManager {
+    /// Retire a session whose poller panicked rather than poll it again with
+    /// whatever state the panic left behind.
+    fn retire_failed(&self, id: String, failure: JoinError, on_event: &EventCallback) {
+        log::error!("Poller of session {} failed: {}", id, failure);
+        self.sessions.lock().unwrap().remove(&id);
+        let error: napi::Error = PayjoinError::new(
+            ErrorCode::ReceiverFailed,
+            ErrorStage::Receiver,
+            format!("Polling the session failed: {}", failure),
+        )
+        .into();
+        on_event.call(
+            ReceiverEvent {
+                kind: ReceiverEventKind::Error,
+                session_id: id,
+                proposal: None,
+                error: Some(ErrorInfo::of(&error)),
+            },
+            ThreadsafeFunctionCallMode::NonBlocking,
+        );
+    }
+}
+
+/// Poll one session until it yields a proposal, expires or is removed, and
+/// resolve with its ID.
+async fn poll_session(
+    id: String,
+    sessions: Sessions,
+    limit: Arc<Semaphore>,
+    mut backoff: Backoff,
+    on_event: Arc<EventCallback>,
+) -> String {
+    let emit = |kind, proposal, error| {
+        on_event.call(
+            ReceiverEvent {
+                kind,
+                session_id: id.clone(),
+                proposal,
+                error,
+            },
+            ThreadsafeFunctionCallMode::NonBlocking,
+        );
+    };
+
+    loop {
+        let Some((mut receiver, expiry)) = sessions
+            .lock()
+            .unwrap()
+            .get(&id)
+            .map(|session| (session.receiver.clone(), session.expiry))
+        else {
+            return id;
+        };
+        if SystemTime::now() >= expiry {
+            log::debug!("Retiring expired session {}", id);
+            sessions.lock().unwrap().remove(&id);
+            emit(ReceiverEventKind::Expired, None, None);
+            return id;
+        }
+
+        let result = {
+            let _permit = limit.acquire().await.expect("semaphore is never closed");
+            poll_once(&mut receiver).await
+        };
+        match result {
+            Ok(Some(proposal)) => {
+                // Unless it was removed while the poll was in flight.
+                if sessions.lock().unwrap().remove(&id).is_some() {
+                    emit(ReceiverEventKind::Proposal, Some(proposal), None);
+                }
+                return id;
+            }
+            Ok(None) => {
+                if let Some(session) = sessions.lock().unwrap().get_mut(&id) {
+                    session.receiver = receiver;
+                }
+            }
+            Err(e) => emit(ReceiverEventKind::Error, None, Some(ErrorInfo::of(&e))),
+        }
+        backoff.wait().await;
+    }
+}
@@ -0,0 +1,379 @@
+use crate::error::{ErrorCode, ErrorStage, PayjoinError, ResultExt};
+use crate::reply::session_keys;
+use hpke::aead::ChaCha20Poly1305;
+use hpke::kdf::HkdfSha256;
+use hpke::kem::SecpK256HkdfSha256;
+use hpke::{Deserializable, OpModeR};
+use napi::bindgen_prelude::BigInt;
+use napi_derive::napi;
+use payjoin::bitcoin::address::NetworkUnchecked;
+use payjoin::bitcoin::consensus::encode::serialize_hex;
+use payjoin::bitcoin::secp256k1::{ellswift::ElligatorSwift, PublicKey};
+use payjoin::bitcoin::{
+    psbt::Psbt, Address, Amount, Network, OutPoint, Script, Transaction, TxOut,
+};
+use payjoin::receive::v2::{Receiver, UncheckedProposal};
+use payjoin::HpkeKeyPair;
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+
+// Layout of BIP77 message A: an ElligatorSwift-encoded ephemeral key, then
+// the ciphertext of the sender's reply key followed by the padded payload.
+const INFO_A: &[u8; 8] = b"PjV2MsgA";
+const ELLSWIFT_BYTES: usize = 64;
+const REPLY_KEY_BYTES: usize = 65;
+
+/// One of the sender's inputs, as passed to the input checks.
+#[napi(object)]
+pub struct InputContext {
+    /// The spent output's script, hex encoded.
+    pub script: String,
+    /// The script's address on the receiver's network, when it has one.
+    pub address: Option<String>,
+    /// The spent outpoint, as `txid:vout`.
+    pub outpoint: String,
+    /// The spent output's value in satoshis.
+    pub value: BigInt,
+}
+
+/// One of the original transaction's outputs, as passed to
+/// `identify_receiver_outputs`.
+#[napi(object)]
+pub struct OutputContext {
+    /// The output's script, hex encoded.
+    pub script: String,
+    /// The script's address on the receiver's network, when it has one.
+    pub address: Option<String>,
+    pub vout: u32,
+    /// The output's value in satoshis.
+    pub value: BigInt,
+}
+
+/// The sender's original transaction, as passed to
+/// `check_broadcast_suitability`.
+#[napi(object)]
+pub struct TxContext {
+    /// The signed transaction, hex encoded.
+    pub tx_hex: String,
+    pub txid: String,
+    pub vsize: u32,
+    /// The fee paid by the sender, in satoshis.
+    pub fee: BigInt,
+    pub fee_rate_sat_per_vb: f64,
+}
+
+/// A proposal as the directory delivered it, after OHTTP decapsulation. The
+/// sender's message inside is still encrypted to the session key, so this is
+/// what gets persisted.
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(transparent)]
+pub(crate) struct SealedProposal {
+    #[serde(with = "base64_bytes")]
+    bhttp: Vec<u8>,
+}
+
+mod base64_bytes {
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub(super) fn serialize<S: Serializer>(bytes: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&base64::encode(bytes))
+    }
+
+    pub(super) fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Vec<u8>, D::Error> {
+        let encoded = String::deserialize(deserializer)?;
+        base64::decode(encoded).map_err(serde::de::Error::custom)
+    }
+}
+
+impl SealedProposal {
+    /// Decapsulate a polling response. `None` when the mailbox was empty.
+    pub(crate) fn open(
+        response: &[u8],
+        ohttp_ctx: ohttp::ClientResponse,
+    ) -> napi::Result<Option<Self>> {
+        let bhttp = ohttp_ctx.decapsulate(response).or_payjoin(
+            ErrorCode::InvalidResponse,
+            ErrorStage::Receiver,
+            "Failed to process response",
+        )?;
+        let sealed = Self { bhttp };
+        Ok((!sealed.body()?.is_empty()).then_some(sealed))
+    }
+
+    fn body(&self) -> napi::Result<Vec<u8>> {
+        bhttp::Message::read_bhttp(&mut std::io::Cursor::new(&self.bhttp))
+            .map(|message| message.content().to_vec())
+            .or_payjoin_kind(
+                ErrorCode::InvalidResponse,
+                ErrorStage::Receiver,
+                "BhttpError",
+                "Failed to process response",
+            )
+    }
+
+    /// Hand the proposal to payjoin, along with the original PSBT it carries.
+    /// `Receiver::process_res` only accepts an OHTTP response, so the
+    /// plaintext is encapsulated again under a throwaway key that never
+    /// leaves this process.
+    pub(crate) fn unchecked(
+        &self,
+        session: &mut Receiver,
+    ) -> napi::Result<(UncheckedProposal, OriginalPsbt)> {
+        let (response, ohttp_ctx) = reencapsulate(&self.bhttp).or_payjoin(
+            ErrorCode::ReceiverFailed,
+            ErrorStage::Receiver,
+            "Failed to reencapsulate response",
+        )?;
+
+        let proposal = session
+            .process_res(&response, ohttp_ctx)
+            .or_payjoin(
+                ErrorCode::InvalidResponse,
+                ErrorStage::Receiver,
+                "Failed to process response",
+            )?
+            .ok_or_else(|| -> napi::Error {
+                PayjoinError::new(
+                    ErrorCode::InvalidResponse,
+                    ErrorStage::Receiver,
+                    "Response carried no proposal",
+                )
+                .into()
+            })?;
+        let original = OriginalPsbt::of(&proposal, self, session)?;
+        Ok((proposal, original))
+    }
+}
+
+fn reencapsulate(bhttp: &[u8]) -> Result<(Vec<u8>, ohttp::ClientResponse), ohttp::Error> {
+    let config = ohttp::KeyConfig::new(
+        0,
+        ohttp::hpke::Kem::K256Sha256,
+        vec![ohttp::SymmetricSuite::new(
+            ohttp::hpke::Kdf::HkdfSha256,
+            ohttp::hpke::Aead::ChaCha20Poly1305,
+        )],
+    )?;
+    let mut client_config = ohttp::KeyConfig::decode(&config.encode()?)?;
+    let server = ohttp::Server::new(config)?;
+
+    let (request, ohttp_ctx) =
+        ohttp::ClientRequest::from_config(&mut client_config)?.encapsulate(&[])?;
+    let (_, server_ctx) = server.decapsulate(&request)?;
+    Ok((server_ctx.encapsulate(bhttp)?, ohttp_ctx))
+}
+
+/// What the receiver's callbacks are told about the sender's original
+/// transaction. The transaction, and the scripts payjoin asks about, come from
+/// the typestate. payjoin 0.22 does not expose the outputs the transaction
+/// spends, so those alone are read from the original PSBT in the sealed
+/// proposal, to give the callbacks input values and the fee.
+#[derive(Clone)]
+pub(crate) struct OriginalPsbt {
+    tx: Transaction,
+    spent: Vec<TxOut>,
+    network: Network,
+}
+
+impl OriginalPsbt {
+    /// The original transaction `proposal` holds and the outputs it spends.
+    /// payjoin has already decrypted the sender's message but offers no way
+    /// to read the spent outputs back, so it is decrypted again here, and
+    /// only used when its transaction is the one `proposal` holds.
+    fn of(
+        proposal: &UncheckedProposal,
+        sealed: &SealedProposal,
+        session: &Receiver,
+    ) -> napi::Result<Self> {
+        let tx = proposal.extract_tx_to_schedule_broadcast();
+        let keys = session_keys(session)?;
+
+        // v1 senders post a plaintext payload; v2 senders encrypt it to the
+        // session key.
+        let payload = match String::from_utf8(sealed.body()?) {
+            Ok(payload) => payload,
+            Err(e) => decrypt_message_a(e.as_bytes(), &keys.s)?,
+        };
+        let (psbt, _query) = payload.split_once('\n').unwrap_or((&payload, ""));
+        let psbt = Psbt::from_str(psbt.trim_end_matches('\0')).or_payjoin(
+            ErrorCode::InvalidPsbt,
+            ErrorStage::Proposal,
+            "Failed to parse original PSBT",
+        )?;
+        if psbt.clone().extract_tx_unchecked_fee_rate() != tx {
+            return Err(PayjoinError::new(
+                ErrorCode::InvalidPsbt,
+                ErrorStage::Proposal,
+                "Original PSBT does not match the proposal",
+            )
+            .into());
+        }
+        let spent = psbt
+            .unsigned_tx
+            .input
+            .iter()
+            .zip(&psbt.inputs)
+            .map(|(txin, input)| {
+                input.witness_utxo.clone().or_else(|| {
+                    input
+                        .non_witness_utxo
+                        .as_ref()?
+                        .output
+                        .get(txin.previous_output.vout as usize)
+                        .cloned()
+                })
+            })
+            .collect::<Option<Vec<_>>>()
+            .ok_or_else(|| missing("Original PSBT is missing a spent output"))?;
+
+        Ok(Self {
+            tx,
+            spent,
+            network: network_of(session.pj_uri_builder().build().address.as_unchecked()),
+        })
+    }
+
+    /// The original transaction, as the typestate holds it.
+    pub(crate) fn transaction(&self) -> &Transaction {
+        &self.tx
+    }
+
+    fn address(&self, script: &Script) -> Option<String> {
+        Address::from_script(script, self.network)
+            .ok()
+            .map(|address| address.to_string())
+    }
+
+    fn input_context(&self, index: usize, script: &Script) -> Option<InputContext> {
+        let outpoint = self.tx.input.get(index)?.previous_output;
+        Some(InputContext {
+            script: script.to_hex_string(),
+            address: self.address(script),
+            outpoint: outpoint.to_string(),
+            value: BigInt::from(self.spent.get(index)?.value.to_sat()),
+        })
+    }
+
+    /// The input at `index`, spending `script`. payjoin asks about inputs in
+    /// order, so the nth question is about the nth input even when two of
+    /// them spend the same script.
+    pub(crate) fn input(&self, index: usize, script: &Script) -> napi::Result<InputContext> {
+        self.input_context(index, script)
+            .ok_or_else(|| missing("Original transaction has no such input"))
+    }
+
+    pub(crate) fn input_by_outpoint(&self, outpoint: &OutPoint) -> napi::Result<InputContext> {
+        self.tx
+            .input
+            .iter()
+            .position(|txin| txin.previous_output == *outpoint)
+            .and_then(|index| self.input_context(index, &self.spent.get(index)?.script_pubkey))
+            .ok_or_else(|| missing("Original transaction has no matching input"))
+    }
+
+    /// The output at `vout`, paying to `script`. payjoin asks about every
+    /// output in order.
+    pub(crate) fn output(&self, vout: usize, script: &Script) -> napi::Result<OutputContext> {
+        self.tx
+            .output
+            .get(vout)
+            .map(|txout| OutputContext {
+                script: script.to_hex_string(),
+                address: self.address(script),
+                vout: vout as u32,
+                value: BigInt::from(txout.value.to_sat()),
+            })
+            .ok_or_else(|| missing("Original transaction has no such output"))
+    }
+
+    pub(crate) fn tx(&self, tx: &Transaction) -> napi::Result<TxContext> {
+        let spent = self
+            .spent
+            .iter()
+            .try_fold(Amount::ZERO, |total, txout| total.checked_add(txout.value));
+        let paid = tx
+            .output
+            .iter()
+            .try_fold(Amount::ZERO, |total, txout| total.checked_add(txout.value));
+        let fee = spent
+            .zip(paid)
+            .and_then(|(spent, paid)| spent.checked_sub(paid))
+            .ok_or_else(|| missing("Original transaction pays more than it spends"))?;
+        let vsize = tx.vsize();
+
+        Ok(TxContext {
+            tx_hex: serialize_hex(tx),
+            txid: tx.compute_txid().to_string(),
+            vsize: vsize as u32,
+            fee: BigInt::from(fee.to_sat()),
+            fee_rate_sat_per_vb: fee.to_sat() as f64 / vsize as f64,
+        })
+    }
+}
+
+fn missing(message: &str) -> napi::Error {
+    PayjoinError::new(ErrorCode::InvalidPsbt, ErrorStage::Proposal, message).into()
+}
+
+/// BIP77 message A: base-mode HPKE from the sender's ephemeral key to the
+/// receiver's session key. Returns the payload with its padding.
+fn decrypt_message_a(message_a: &[u8], receiver_keypair: &HpkeKeyPair) -> napi::Result<String> {
+    let hpke_error = |e: hpke::HpkeError| {
+        PayjoinError::new(
+            ErrorCode::InvalidResponse,
+            ErrorStage::Receiver,
+            format!("Failed to decrypt original PSBT: {}", e),
+        )
+        .with_kind("HpkeError")
+    };
+
+    if message_a.len() < ELLSWIFT_BYTES {
+        return Err(PayjoinError::new(
+            ErrorCode::InvalidResponse,
+            ErrorStage::Receiver,
+            "Failed to decrypt original PSBT: message is too short",
+        )
+        .into());
+    }
+    let (ellswift, ciphertext) = message_a.split_at(ELLSWIFT_BYTES);
+    let ellswift = ElligatorSwift::from_array(
+        ellswift
+            .try_into()
+            .expect("split at the ElligatorSwift length"),
+    );
+    let encapsulated_key = <SecpK256HkdfSha256 as hpke::Kem>::EncappedKey::from_bytes(
+        &PublicKey::from_ellswift(ellswift).serialize_uncompressed(),
+    )
+    .map_err(hpke_error)?;
+
+    let plaintext = hpke::single_shot_open::<ChaCha20Poly1305, HkdfSha256, SecpK256HkdfSha256>(
+        &OpModeR::Base,
+        &receiver_keypair.secret_key().0,
+        &encapsulated_key,
+        INFO_A,
+        ciphertext,
+        &[],
+    )
+    .map_err(hpke_error)?;
+
+    let payload = plaintext.get(REPLY_KEY_BYTES..).unwrap_or_default();
+    Ok(String::from_utf8_lossy(payload).into_owned())
+}
+
+/// The network the receiver's address is encoded for, which is the one its
+/// callbacks' addresses are shown on. payjoin only keeps the address, and an
+/// address names no network of its own; testnet and signet share an encoding,
+/// so either gives the same addresses.
+fn network_of(address: &Address<NetworkUnchecked>) -> Network {
+    [
+        Network::Bitcoin,
+        Network::Testnet,
+        Network::Signet,
+        Network::Regtest,
+    ]
+    .into_iter()
+    .find(|network| address.is_valid_for_network(*network))
+    .unwrap_or(Network::Bitcoin)
+}
@@ -0,0 +1,109 @@
+use crate::error::{ErrorCode, ErrorStage, PayjoinError};
+use napi_derive::napi;
+use std::future::Future;
+use std::time::{Duration, Instant, SystemTime};
+
+const DEFAULT_INTERVAL_MS: u32 = 5_000;
+const DEFAULT_MAX_INTERVAL_MS: u32 = 60_000;
+const DEFAULT_BACKOFF_MULTIPLIER: f64 = 1.5;
+pub(crate) const DEFAULT_SEND_TIMEOUT: Duration = Duration::from_secs(10 * 60);
+
+/// Polling schedule used by the native send and receive loops.
+#[napi(object)]
+#[derive(Clone, Default)]
+pub struct PollOptions {
+    /// Delay before the first re-poll, in milliseconds. Defaults to 5000.
+    pub interval_ms: Option<u32>,
+    /// Upper bound for the delay between polls, in milliseconds. Defaults to 60000.
+    pub max_interval_ms: Option<u32>,
+    /// Factor the delay grows by after every empty poll. Defaults to 1.5.
+    pub backoff_multiplier: Option<f64>,
+    /// Overall time budget for the loop, in milliseconds, including requests
+    /// still in flight. Defaults to 10 minutes when sending; receivers poll
+    /// until their session expires.
+    pub timeout_ms: Option<u32>,
+}
+
+/// Tracks the delay between polls and the overall deadline of a polling loop.
+pub(crate) struct Backoff {
+    delay: Duration,
+    max_delay: Duration,
+    multiplier: f64,
+    deadline: Option<Instant>,
+}
+
+impl Backoff {
+    pub(crate) fn new(
+        options: Option<PollOptions>,
+        default_timeout: Option<Duration>,
+        stage: ErrorStage,
+    ) -> napi::Result<Self> {
+        let options = options.unwrap_or_default();
+        let multiplier = options
+            .backoff_multiplier
+            .unwrap_or(DEFAULT_BACKOFF_MULTIPLIER);
+        if !multiplier.is_finite() || multiplier < 1.0 {
+            return Err(PayjoinError::new(
+                ErrorCode::InvalidOptions,
+                stage,
+                "Backoff multiplier must be a finite number >= 1",
+            )
+            .into());
+        }
+
+        let delay =
+            Duration::from_millis(options.interval_ms.unwrap_or(DEFAULT_INTERVAL_MS) as u64);
+        let max_delay = Duration::from_millis(
+            options.max_interval_ms.unwrap_or(DEFAULT_MAX_INTERVAL_MS) as u64,
+        )
+        .max(delay);
+        let timeout = options
+            .timeout_ms
+            .map(|ms| Duration::from_millis(ms as u64))
+            .or(default_timeout);
+
+        Ok(Self {
+            delay,
+            max_delay,
+            multiplier,
+            deadline: timeout.map(|timeout| Instant::now() + timeout),
+        })
+    }
+
+    /// Bring the deadline forward to `expiry`, such as a session's, when it
+    /// comes first.
+    pub(crate) fn expire_at(&mut self, expiry: SystemTime) {
+        let remaining = expiry.duration_since(SystemTime::now()).unwrap_or_default();
+        let expiry = Instant::now() + remaining;
+        self.deadline = Some(
+            self.deadline
+                .map_or(expiry, |deadline| deadline.min(expiry)),
+        );
+    }
+
+    /// Run one request of the loop, abandoning it once the deadline passes.
+    /// `None` when it did not complete in time.
+    pub(crate) async fn bounded<F: Future>(&self, request: F) -> Option<F::Output> {
+        match self.deadline {
+            Some(deadline) => tokio::time::timeout_at(deadline.into(), request).await.ok(),
+            None => Some(request.await),
+        }
+    }
+
+    pub(crate) fn is_timed_out(&self) -> bool {
+        self.deadline
+            .is_some_and(|deadline| Instant::now() >= deadline)
+    }
+
+    /// Sleep for the current delay, clamped to the deadline, then grow the delay.
+    pub(crate) async fn wait(&mut self) {
+        let delay = match self.deadline {
+            Some(deadline) => self
+                .delay
+                .min(deadline.saturating_duration_since(Instant::now())),
+            None => self.delay,
+        };
+        tokio::time::sleep(delay).await;
+        self.delay = self.delay.mul_f64(self.multiplier).min(self.max_delay);
+    }
+}
@@ -1,4 +1,14 @@
-use crate::request::PayjoinRequest;
+use crate::amount::{to_amount, to_fee_rate, to_u64};
+use crate::callback::{call_async, queried, Answers, AsyncCallback, Recording, Thrown};
+use crate::cancel::{with_cancellation, CancellationToken};
+use crate::encrypt::{open_encrypted, seal_encrypted, ExportKey, Secret};
+use crate::envelope::{open, seal};
+use crate::error::{self, ErrorCode, ErrorKind, ErrorStage, PayjoinError, ResultExt};
+use crate::journal::{self, persist, restore, Journal, Step};
+use crate::original::{InputContext, OriginalPsbt, OutputContext, SealedProposal, TxContext};
+use crate::poll::{Backoff, PollOptions};
+use crate::reply::{extract_error_req, impl_error_reply, session_keys, WellKnownErrorCode};
+use crate::request::{post_request, PayjoinRequest};
 use crate::uri::PayjoinUriBuilder;
 use napi::bindgen_prelude::*;
 use napi::bindgen_prelude::{BigInt, Uint8Array};
@@ -7,8 +17,15 @@ use napi_derive::napi;
 use ohttp::ClientResponse;
 use payjoin::{
     bitcoin::{
-        consensus::Decodable, psbt::Input, psbt::Psbt, Address, Amount, FeeRate, OutPoint, Script,
-        ScriptBuf, Sequence, Transaction, TxIn, TxOut, Witness,
+        bip32::{ChildNumber, DerivationPath, Fingerprint, KeySource},
+        consensus::Decodable,
+        ecdsa,
+        hashes::Hash,
+        psbt::{Input, Psbt, PsbtSighashType},
+        secp256k1::PublicKey as Secp256k1PublicKey,
+        taproot::{self, ControlBlock, LeafVersion, TapLeafHash, TapNodeHash},
+        Address, Amount, FeeRate, OutPoint, PublicKey, ScriptBuf, Sequence, Transaction, TxIn,
+        TxOut, Witness, XOnlyPublicKey,
     },
     receive::v2::{
         MaybeInputsOwned, MaybeInputsSeen, OutputsUnknown, PayjoinProposal, ProvisionalProposal,
@@ -17,7 +34,12 @@ use payjoin::{
     receive::InputPair,
     OhttpKeys,
 };
-use std::{str::FromStr, time::Duration};
+use std::{
+    cell::Cell,
+    fmt,
+    str::FromStr,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
 use url::Url;
 
 #[napi]
@@ -36,6 +58,39 @@ pub struct PayjoinReceiver {
     inner: Receiver,
 }
 
+impl PayjoinReceiver {
+    pub(crate) fn from_session(inner: Receiver) -> Self {
+        Self { inner }
+    }
+
+    pub(crate) fn session(&self) -> &Receiver {
+        &self.inner
+    }
+}
+
+/// Read how long a new session lasts. The session's URI carries its expiry as
+/// 32-bit seconds since the epoch, so it cannot fall after 2106.
+fn lifetime(seconds: &BigInt) -> napi::Result<Duration> {
+    let context = "Invalid expiry";
+    let lifetime = Duration::from_secs(to_u64(
+        seconds,
+        ErrorCode::InvalidOptions,
+        ErrorStage::Receiver,
+        context,
+    )?);
+
+    let latest = UNIX_EPOCH + Duration::from_secs(u32::MAX.into());
+    match SystemTime::now().checked_add(lifetime) {
+        Some(expiry) if expiry <= latest => Ok(lifetime),
+        _ => Err(PayjoinError::new(
+            ErrorCode::InvalidOptions,
+            ErrorStage::Receiver,
+            format!("{}: the session would expire after 2106", context),
+        )
+        .into()),
+    }
+}
+
 #[napi]
 impl PayjoinReceiver {
     #[napi(constructor)]
@@ -46,22 +101,31 @@ impl PayjoinReceiver {
         ohttp_relay: String,
         expiry_seconds: Option<BigInt>,
     ) -> napi::Result<Self> {
-        let address = Address::from_str(&address)
-            .map_err(|e| napi::Error::from_reason(format!("Invalid address: {}", e)))?;
-
-        let directory = Url::parse(&directory)
-            .map_err(|e| napi::Error::from_reason(format!("Invalid directory URL: {}", e)))?;
-
-        let ohttp_relay = Url::parse(&ohttp_relay)
-            .map_err(|e| napi::Error::from_reason(format!("Invalid relay URL: {}", e)))?;
-
-        let ohttp_keys = OhttpKeys::decode(ohttp_keys.as_ref())
-            .map_err(|e| napi::Error::from_reason(format!("Invalid OHTTP keys: {}", e)))?;
-
-        let expire_after = expiry_seconds.map(|seconds| {
-            let (_sign, value, _overflow) = seconds.get_u64();
-            Duration::from_secs(value)
-        });
+        let address = Address::from_str(&address).or_payjoin(
+            ErrorCode::InvalidAddress,
+            ErrorStage::Receiver,
+            "Invalid address",
+        )?;
+
+        let directory = Url::parse(&directory).or_payjoin(
+            ErrorCode::InvalidUrl,
+            ErrorStage::Receiver,
+            "Invalid directory URL",
+        )?;
+
+        let ohttp_relay = Url::parse(&ohttp_relay).or_payjoin(
+            ErrorCode::InvalidUrl,
+            ErrorStage::Receiver,
+            "Invalid relay URL",
+        )?;
+
+        let ohttp_keys = OhttpKeys::decode(ohttp_keys.as_ref()).or_payjoin(
+            ErrorCode::InvalidOhttpKeys,
+            ErrorStage::Receiver,
+            "Invalid OHTTP keys",
+        )?;
+
+        let expire_after = expiry_seconds.as_ref().map(lifetime).transpose()?;
 
         Ok(Self {
             inner: Receiver::new(
@@ -76,17 +140,45 @@ impl PayjoinReceiver {
 
     #[napi]
     pub fn to_json(&self) -> napi::Result<String> {
-        // Serialize the internal state to JSON
-        serde_json::to_string(&self.inner)
-            .map_err(|e| napi::Error::from_reason(format!("Failed to serialize receiver: {}", e)))
+        seal(
+            "PayjoinReceiver",
+            &self.inner,
+            "Failed to serialize receiver",
+        )
     }
 
     #[napi(factory)]
     pub fn from_json(json_str: String) -> napi::Result<Self> {
-        // Deserialize from JSON to create a new receiver
-        let inner: Receiver = serde_json::from_str(&json_str).map_err(|e| {
-            napi::Error::from_reason(format!("Failed to deserialize receiver: {}", e))
-        })?;
+        let inner: Receiver = open(
+            "PayjoinReceiver",
+            &json_str,
+            "Failed to deserialize receiver",
+        )?;
+
+        Ok(Self { inner })
+    }
+
+    /// Like `to_json`, encrypted under `key` for storage outside the process.
+    /// The receiver's session secret never leaves it in plaintext.
+    #[napi]
+    pub fn to_encrypted_json(&self, key: ExportKey) -> napi::Result<String> {
+        seal_encrypted(
+            "PayjoinReceiver",
+            &self.inner,
+            &Secret::try_from(key)?,
+            "Failed to serialize receiver",
+        )
+    }
+
+    /// Restore a receiver exported by `to_encrypted_json` with the same key.
+    #[napi(factory)]
+    pub fn from_encrypted_json(json_str: String, key: ExportKey) -> napi::Result<Self> {
+        let inner: Receiver = open_encrypted(
+            "PayjoinReceiver",
+            &json_str,
+            &Secret::try_from(key)?,
+            "Failed to deserialize receiver",
+        )?;
 
         Ok(Self { inner })
     }
@@ -104,16 +196,16 @@ impl PayjoinReceiver {
 
     #[napi]
     pub fn extract_request(&mut self) -> napi::Result<PayjoinRequest> {
+        log::trace!("Polling directory for {}", self.inner.pj_url());
         let (request, ohttp_ctx) = self
             .inner
             .extract_req()
-            .map_err(|e| napi::Error::from_reason(format!("Failed to extract request: {}", e)))?;
+            .map_err(|e| session_error(e, "Failed to extract request"))?;
 
         Ok(PayjoinRequest::new(
             request.url.to_string(),
             request.body.to_vec(),
             Some(ohttp_ctx),
-            None,
         ))
     }
 
@@ -123,38 +215,163 @@ impl PayjoinReceiver {
         response: Uint8Array,
         request: &mut PayjoinRequest,
     ) -> napi::Result<Option<UncheckedProposalWrapper>> {
-        let ohttp_ctx = request
-            .get_ohttp_ctx()
-            .ok_or_else(|| napi::Error::from_reason("Missing OHTTP context"))?;
+        let ohttp_ctx = request.get_ohttp_ctx().ok_or_else(|| {
+            PayjoinError::new(
+                ErrorCode::MissingContext,
+                ErrorStage::Receiver,
+                "Missing OHTTP context",
+            )
+        })?;
 
-        let response_vec = response.as_ref();
+        unchecked_proposal(&mut self.inner, response.as_ref(), ohttp_ctx)
+    }
 
-        self.inner
-            .process_res(response_vec, ohttp_ctx)
-            .map(|proposal| proposal.map(|p| UncheckedProposalWrapper { inner: p }))
-            .map_err(|e| napi::Error::from_reason(format!("Failed to process response: {}", e)))
+    /// Poll the directory through the relay until a sender posts an original
+    /// PSBT. Resolves with `null` once the polling budget runs out and rejects
+    /// when the session expires or the token is cancelled. A request still in
+    /// flight at either deadline is abandoned.
+    #[napi(ts_return_type = "Promise<UncheckedProposalWrapper | null>")]
+    pub async fn poll_for_proposal(
+        &self,
+        options: Option<PollOptions>,
+        cancellation_token: Option<&CancellationToken>,
+    ) -> napi::Result<Option<UncheckedProposalWrapper>> {
+        let token = cancellation_token.cloned();
+        let mut receiver = self.inner.clone();
+        let mut backoff = Backoff::new(options, None, ErrorStage::Receiver)?;
+        let expiry = session_keys(&receiver)?.expiry;
+        backoff.expire_at(expiry);
+
+        with_cancellation(token, ErrorStage::Receiver, async move {
+            loop {
+                let Some(proposal) = backoff.bounded(poll_once(&mut receiver)).await else {
+                    break;
+                };
+                let proposal = proposal?;
+                if proposal.is_some() {
+                    return Ok(proposal);
+                }
+                if backoff.is_timed_out() {
+                    break;
+                }
+                backoff.wait().await;
+            }
+
+            if SystemTime::now() >= expiry {
+                return Err(PayjoinError::new(
+                    ErrorCode::SessionExpired,
+                    ErrorStage::Receiver,
+                    "Session expired while polling for a proposal",
+                )
+                .into());
+            }
+            Ok(None)
+        })
+        .await
     }
 }
 
-fn log_debug(message: &str) {
-    use std::fs::OpenOptions;
-    use std::io::Write;
+/// Poll the directory once for the sender's original PSBT.
+pub(crate) async fn poll_once(
+    receiver: &mut Receiver,
+) -> napi::Result<Option<UncheckedProposalWrapper>> {
+    let (request, ohttp_ctx) = receiver
+        .extract_req()
+        .map_err(|e| session_error(e, "Failed to extract request"))?;
+    let response = post_request(request.url.as_str(), request.content_type, request.body).await?;
+    unchecked_proposal(receiver, &response, ohttp_ctx)
+}
+
+/// Open a polling response and, when it carries the sender's original PSBT,
+/// wrap it together with the context its callbacks are given.
+fn unchecked_proposal(
+    receiver: &mut Receiver,
+    response: &[u8],
+    ohttp_ctx: ClientResponse,
+) -> napi::Result<Option<UncheckedProposalWrapper>> {
+    let Some(sealed) = SealedProposal::open(response, ohttp_ctx)? else {
+        return Ok(None);
+    };
+    let (inner, original) = sealed.unchecked(receiver)?;
+
+    Ok(Some(UncheckedProposalWrapper {
+        inner,
+        session: receiver.clone(),
+        original,
+        journal: Journal::new(sealed),
+    }))
+}
+
+/// Fail a check whose value the original PSBT cannot describe. payjoin only
+/// asks about values it read from that PSBT, so this is not expected.
+fn context_error(error: napi::Error) -> payjoin::Error {
+    payjoin::Error::Server(error::message(&error).into())
+}
 
-    let mut file = OpenOptions::new()
-        .create(true)
-        .append(true)
-        .open("/tmp/payjoin-debug.log")
-        .unwrap();
-    writeln!(file, "{}", message).unwrap();
+/// `SessionError` is not exported by payjoin, so expiry is recognised by its
+/// message.
+fn session_error(error: impl fmt::Display, context: &str) -> napi::Error {
+    let message = error.to_string();
+    let code = if message.starts_with("Session expired") {
+        ErrorCode::SessionExpired
+    } else {
+        ErrorCode::CreateRequestFailed
+    };
+    PayjoinError::new(
+        code,
+        ErrorStage::Receiver,
+        format!("{}: {}", context, message),
+    )
+    .with_kind("SessionError")
+    .into()
 }
 
+fn parse_fee_rate(sat_per_vb: Option<f64>, message: &str) -> napi::Result<Option<FeeRate>> {
+    sat_per_vb
+        .map(|rate| to_fee_rate(rate, ErrorStage::Proposal, message))
+        .transpose()
+}
+
+impl_error_reply!(
+    UncheckedProposalWrapper,
+    MaybeInputsOwnedWrapper,
+    MaybeInputsSeenWrapper,
+    OutputsUnknownWrapper,
+    WantsOutputsWrapper,
+    WantsInputsWrapper,
+    ProvisionalProposalWrapper,
+);
+
 #[napi]
 pub struct UncheckedProposalWrapper {
     inner: UncheckedProposal,
+    session: Receiver,
+    original: OriginalPsbt,
+    journal: Journal,
 }
 
 #[napi]
 impl UncheckedProposalWrapper {
+    /// Serialize the proposal with its session, so it can be resumed after a
+    /// restart with `fromJson`.
+    #[napi]
+    pub fn to_json(&self) -> napi::Result<String> {
+        persist::<UncheckedProposal>(&self.session, &self.journal)
+    }
+
+    /// Restore a proposal serialized by `toJson`. Fails if it was persisted
+    /// in a different state.
+    #[napi(factory)]
+    pub fn from_json(json_str: String) -> napi::Result<Self> {
+        let restored = restore::<UncheckedProposal>(&json_str)?;
+        Ok(Self {
+            inner: restored.inner,
+            session: restored.session,
+            original: restored.original,
+            journal: restored.journal,
+        })
+    }
+
     #[napi]
     pub fn original_tx(&self) -> String {
         let tx = self.inner.extract_tx_to_schedule_broadcast();
@@ -165,57 +382,123 @@ impl UncheckedProposalWrapper {
     pub fn check_broadcast_suitability(
         &mut self,
         min_fee_rate: Option<f64>,
-        can_broadcast: Function<String, bool>,
+        can_broadcast: Function<TxContext, bool>,
     ) -> napi::Result<MaybeInputsOwnedWrapper> {
-        log_debug("check_broadcast_suitability: Entered function");
+        log::trace!("check_broadcast_suitability: Entered function");
 
-        // Parse the minimum fee rate
-        let min_fee_rate = min_fee_rate
-            .map(|rate| {
-                FeeRate::from_sat_per_vb(rate as u64)
-                    .ok_or_else(|| napi::Error::from_reason("Invalid fee rate"))
-            })
-            .transpose()?;
+        let min_fee_rate = parse_fee_rate(min_fee_rate, "Invalid fee rate")?;
 
-        log_debug(&format!(
+        log::trace!(
             "check_broadcast_suitability: Parsed min_fee_rate: {:?}",
             min_fee_rate
-        ));
+        );
 
+        let thrown = Thrown::default();
+        let recording = Recording::default();
         let result = self
             .inner
             .clone()
             .check_broadcast_suitability(min_fee_rate, |tx| {
-                let tx_hex = payjoin::bitcoin::consensus::encode::serialize_hex(tx);
-                log_debug(&format!(
+                log::trace!(
                     "check_broadcast_suitability: Checking broadcast suitability for tx: {}",
-                    tx_hex
-                ));
-
-                let result = can_broadcast.call(tx_hex);
-
-                result.map_err(|e| {
-                    log_debug(&format!("check_broadcast_suitability: Error: {}", e));
-                    payjoin::Error::Server(format!("Failed to check broadcast: {}", e).into())
-                })
+                    tx.compute_txid()
+                );
+
+                let tx = self.original.tx(tx).map_err(context_error)?;
+                recording.record(can_broadcast.call(tx).map_err(|e| {
+                    log::debug!("check_broadcast_suitability: Error: {}", e);
+                    thrown.catch(e, "Failed to check broadcast")
+                }))
+            })
+            .map(|m| MaybeInputsOwnedWrapper {
+                inner: m,
+                session: self.session.clone(),
+                original: self.original.clone(),
+                journal: self.journal.then(Step::CheckBroadcastSuitability {
+                    min_fee_rate_sat_per_kwu: min_fee_rate.map(FeeRate::to_sat_per_kwu),
+                    answers: recording.take(),
+                }),
             })
-            .map(|m| MaybeInputsOwnedWrapper { inner: m })
             .map_err(|e| {
-                log_debug(&format!(
+                log::debug!(
                     "check_broadcast_suitability: Error in check_broadcast_suitability: {}",
                     e
-                ));
-                napi::Error::from_reason(format!("Failed checking broadcast: {}", e))
-            });
+                );
+                e
+            })
+            .or_payjoin(
+                ErrorCode::ProposalRejected,
+                ErrorStage::Proposal,
+                "Failed checking broadcast",
+            );
+
+        log::trace!("check_broadcast_suitability: Exiting function");
+        thrown.rethrow(result, "Failed to check broadcast")
+    }
 
-        log_debug("check_broadcast_suitability: Exiting function");
-        result
+    /// Like `check_broadcast_suitability`, for wallets that answer with a
+    /// Promise, e.g. a `testmempoolaccept` RPC call.
+    #[napi(
+        ts_args_type = "minFeeRate: number | undefined | null, canBroadcast: (tx: TxContext) => Promise<boolean>, cancellationToken?: CancellationToken | undefined | null",
+        ts_return_type = "Promise<MaybeInputsOwnedWrapper>"
+    )]
+    pub async fn check_broadcast_suitability_async(
+        &self,
+        min_fee_rate: Option<f64>,
+        can_broadcast: AsyncCallback<TxContext, bool>,
+        cancellation_token: Option<&CancellationToken>,
+    ) -> napi::Result<MaybeInputsOwnedWrapper> {
+        let min_fee_rate = parse_fee_rate(min_fee_rate, "Invalid fee rate")?;
+        let proposal = self.inner.clone();
+        let session = self.session.clone();
+        let original = self.original.clone();
+        let journal = self.journal.clone();
+
+        with_cancellation(
+            cancellation_token.cloned(),
+            ErrorStage::Proposal,
+            async move {
+                let txs = queried(|can_broadcast| {
+                    let _ = proposal
+                        .clone()
+                        .check_broadcast_suitability(min_fee_rate, can_broadcast);
+                });
+                let answers = Answers::collect(
+                    &can_broadcast,
+                    txs,
+                    |_, tx| original.tx(tx),
+                    "Failed to check broadcast",
+                )
+                .await?;
+
+                proposal
+                    .check_broadcast_suitability(min_fee_rate, |tx| answers.get(tx))
+                    .map(|inner| MaybeInputsOwnedWrapper {
+                        inner,
+                        session,
+                        original,
+                        journal: journal.then(Step::CheckBroadcastSuitability {
+                            min_fee_rate_sat_per_kwu: min_fee_rate.map(FeeRate::to_sat_per_kwu),
+                            answers: answers.given(),
+                        }),
+                    })
+                    .or_payjoin(
+                        ErrorCode::ProposalRejected,
+                        ErrorStage::Proposal,
+                        "Failed checking broadcast",
+                    )
+            },
+        )
+        .await
     }
 
     #[napi]
     pub fn assume_interactive_receiver(&mut self) -> MaybeInputsOwnedWrapper {
         MaybeInputsOwnedWrapper {
             inner: self.inner.clone().assume_interactive_receiver(),
+            session: self.session.clone(),
+            original: self.original.clone(),
+            journal: self.journal.then(Step::AssumeInteractiveReceiver),
         }
     }
 }
@@ -223,89 +506,466 @@ impl UncheckedProposalWrapper {
 #[napi]
 pub struct MaybeInputsOwnedWrapper {
     inner: MaybeInputsOwned,
+    session: Receiver,
+    original: OriginalPsbt,
+    journal: Journal,
 }
 
 #[napi]
 impl MaybeInputsOwnedWrapper {
+    /// Serialize the proposal with its session, so it can be resumed after a
+    /// restart with `fromJson`.
+    #[napi]
+    pub fn to_json(&self) -> napi::Result<String> {
+        persist::<MaybeInputsOwned>(&self.session, &self.journal)
+    }
+
+    /// Restore a proposal serialized by `toJson`. Fails if it was persisted
+    /// in a different state.
+    #[napi(factory)]
+    pub fn from_json(json_str: String) -> napi::Result<Self> {
+        let restored = restore::<MaybeInputsOwned>(&json_str)?;
+        Ok(Self {
+            inner: restored.inner,
+            session: restored.session,
+            original: restored.original,
+            journal: restored.journal,
+        })
+    }
+
     #[napi]
     pub fn check_inputs_not_owned(
         &mut self,
-        is_owned: Function<String, bool>,
+        is_owned: Function<InputContext, bool>,
     ) -> napi::Result<MaybeInputsSeenWrapper> {
-        self.inner
+        let thrown = Thrown::default();
+        let recording = Recording::default();
+        let asked = Cell::new(0);
+        let result = self
+            .inner
             .clone()
             .check_inputs_not_owned(|script| {
-                log_debug("check_inputs_not_owned: Checking inputs not owned");
+                log::trace!("check_inputs_not_owned: Checking inputs not owned");
+
+                let input = self
+                    .original
+                    .input(asked.replace(asked.get() + 1), script)
+                    .map_err(context_error)?;
+                recording.record(is_owned.call(input).map_err(|e| {
+                    log::debug!("check_inputs_not_owned: Error: {}", e);
+                    thrown.catch(e, "Failed to check inputs")
+                }))
+            })
+            .map(|m| MaybeInputsSeenWrapper {
+                inner: m,
+                session: self.session.clone(),
+                original: self.original.clone(),
+                journal: self.journal.then(Step::CheckInputsNotOwned {
+                    answers: recording.take(),
+                }),
+            })
+            .or_payjoin(
+                ErrorCode::ProposalRejected,
+                ErrorStage::Proposal,
+                "Failed to check inputs",
+            );
 
-                let script_hex = script.to_hex_string();
+        thrown.rethrow(result, "Failed to check inputs")
+    }
 
-                let result = is_owned.call(script_hex);
+    /// Like `check_inputs_not_owned`, for wallets that answer with a Promise.
+    #[napi(
+        ts_args_type = "isOwned: (input: InputContext) => Promise<boolean>, cancellationToken?: CancellationToken | undefined | null",
+        ts_return_type = "Promise<MaybeInputsSeenWrapper>"
+    )]
+    pub async fn check_inputs_not_owned_async(
+        &self,
+        is_owned: AsyncCallback<InputContext, bool>,
+        cancellation_token: Option<&CancellationToken>,
+    ) -> napi::Result<MaybeInputsSeenWrapper> {
+        let proposal = self.inner.clone();
+        let session = self.session.clone();
+        let original = self.original.clone();
+        let journal = self.journal.clone();
+
+        with_cancellation(
+            cancellation_token.cloned(),
+            ErrorStage::Proposal,
+            async move {
+                let scripts = queried(|is_owned| {
+                    let _ = proposal.clone().check_inputs_not_owned(is_owned);
+                });
+                let answers = Answers::collect(
+                    &is_owned,
+                    scripts,
+                    |index, script: &ScriptBuf| original.input(index, script),
+                    "Failed to check inputs",
+                )
+                .await?;
+
+                proposal
+                    .check_inputs_not_owned(|script| answers.get(script))
+                    .map(|inner| MaybeInputsSeenWrapper {
+                        inner,
+                        session,
+                        original,
+                        journal: journal.then(Step::CheckInputsNotOwned {
+                            answers: answers.given(),
+                        }),
+                    })
+                    .or_payjoin(
+                        ErrorCode::ProposalRejected,
+                        ErrorStage::Proposal,
+                        "Failed to check inputs",
+                    )
+            },
+        )
+        .await
+    }
 
-                result.map_err(|e| {
-                    log_debug(&format!("check_inputs_not_owned: Error: {}", e));
-                    payjoin::Error::Server(format!("Failed to check inputs: {}", e).into())
-                })
-            })
-            .map(|m| MaybeInputsSeenWrapper { inner: m })
-            .map_err(|e| napi::Error::from_reason(format!("Failed to check inputs: {}", e)))
+    /// Like `check_inputs_not_owned_async`, but asks about every input's
+    /// script in a single call. `are_owned` resolves with one answer per
+    /// script, in order.
+    #[napi(
+        ts_args_type = "areOwned: (inputs: InputContext[]) => Promise<boolean[]>, cancellationToken?: CancellationToken | undefined | null",
+        ts_return_type = "Promise<MaybeInputsSeenWrapper>"
+    )]
+    pub async fn check_inputs_not_owned_batch(
+        &self,
+        are_owned: AsyncCallback<Vec<InputContext>, Vec<bool>>,
+        cancellation_token: Option<&CancellationToken>,
+    ) -> napi::Result<MaybeInputsSeenWrapper> {
+        let proposal = self.inner.clone();
+        let session = self.session.clone();
+        let original = self.original.clone();
+        let journal = self.journal.clone();
+
+        with_cancellation(
+            cancellation_token.cloned(),
+            ErrorStage::Proposal,
+            async move {
+                let scripts = queried(|is_owned| {
+                    let _ = proposal.clone().check_inputs_not_owned(is_owned);
+                });
+                let answers = Answers::collect_batch(
+                    &are_owned,
+                    scripts,
+                    |index, script: &ScriptBuf| original.input(index, script),
+                    "Failed to check inputs",
+                )
+                .await?;
+
+                proposal
+                    .check_inputs_not_owned(|script| answers.get(script))
+                    .map(|inner| MaybeInputsSeenWrapper {
+                        inner,
+                        session,
+                        original,
+                        journal: journal.then(Step::CheckInputsNotOwned {
+                            answers: answers.given(),
+                        }),
+                    })
+                    .or_payjoin(
+                        ErrorCode::ProposalRejected,
+                        ErrorStage::Proposal,
+                        "Failed to check inputs",
+                    )
+            },
+        )
+        .await
     }
 }
 
 #[napi]
 pub struct MaybeInputsSeenWrapper {
     inner: MaybeInputsSeen,
+    session: Receiver,
+    original: OriginalPsbt,
+    journal: Journal,
 }
 
 #[napi]
 impl MaybeInputsSeenWrapper {
+    /// Serialize the proposal with its session, so it can be resumed after a
+    /// restart with `fromJson`.
+    #[napi]
+    pub fn to_json(&self) -> napi::Result<String> {
+        persist::<MaybeInputsSeen>(&self.session, &self.journal)
+    }
+
+    /// Restore a proposal serialized by `toJson`. Fails if it was persisted
+    /// in a different state.
+    #[napi(factory)]
+    pub fn from_json(json_str: String) -> napi::Result<Self> {
+        let restored = restore::<MaybeInputsSeen>(&json_str)?;
+        Ok(Self {
+            inner: restored.inner,
+            session: restored.session,
+            original: restored.original,
+            journal: restored.journal,
+        })
+    }
+
     #[napi]
     pub fn check_no_inputs_seen_before(
         &mut self,
-        is_known: Function<String, bool>,
+        is_known: Function<InputContext, bool>,
     ) -> napi::Result<OutputsUnknownWrapper> {
-        self.inner
+        let thrown = Thrown::default();
+        let recording = Recording::default();
+        let result = self
+            .inner
             .clone()
             .check_no_inputs_seen_before(|outpoint| {
-                let outpoint_str = outpoint.to_string();
+                let input = self
+                    .original
+                    .input_by_outpoint(outpoint)
+                    .map_err(context_error)?;
+                recording.record(is_known.call(input).map_err(|e| {
+                    log::debug!("check_no_inputs_seen_before: Error: {}", e);
+                    thrown.catch(e, "Failed to check inputs")
+                }))
+            })
+            .map(|o| OutputsUnknownWrapper {
+                inner: o,
+                session: self.session.clone(),
+                original: self.original.clone(),
+                journal: self.journal.then(Step::CheckNoInputsSeenBefore {
+                    answers: recording.take(),
+                }),
+            })
+            .or_payjoin(
+                ErrorCode::ProposalRejected,
+                ErrorStage::Proposal,
+                "Failed to check inputs",
+            );
 
-                let result = is_known.call(outpoint_str);
+        thrown.rethrow(result, "Failed to check inputs")
+    }
 
-                result.map_err(|e| {
-                    log_debug(&format!("check_no_inputs_seen_before: Error: {}", e));
-                    payjoin::Error::Server(format!("Failed to check inputs: {}", e).into())
-                })
-            })
-            .map(|o| OutputsUnknownWrapper { inner: o })
-            .map_err(|e| napi::Error::from_reason(format!("Failed to check inputs: {}", e)))
+    /// Like `check_no_inputs_seen_before`, for wallets that answer with a
+    /// Promise.
+    #[napi(
+        ts_args_type = "isKnown: (input: InputContext) => Promise<boolean>, cancellationToken?: CancellationToken | undefined | null",
+        ts_return_type = "Promise<OutputsUnknownWrapper>"
+    )]
+    pub async fn check_no_inputs_seen_before_async(
+        &self,
+        is_known: AsyncCallback<InputContext, bool>,
+        cancellation_token: Option<&CancellationToken>,
+    ) -> napi::Result<OutputsUnknownWrapper> {
+        let proposal = self.inner.clone();
+        let session = self.session.clone();
+        let original = self.original.clone();
+        let journal = self.journal.clone();
+
+        with_cancellation(
+            cancellation_token.cloned(),
+            ErrorStage::Proposal,
+            async move {
+                let outpoints = queried(|is_known| {
+                    let _ = proposal.clone().check_no_inputs_seen_before(is_known);
+                });
+                let answers = Answers::collect(
+                    &is_known,
+                    outpoints,
+                    |_, outpoint| original.input_by_outpoint(outpoint),
+                    "Failed to check inputs",
+                )
+                .await?;
+
+                proposal
+                    .check_no_inputs_seen_before(|outpoint| answers.get(outpoint))
+                    .map(|inner| OutputsUnknownWrapper {
+                        inner,
+                        session,
+                        original,
+                        journal: journal.then(Step::CheckNoInputsSeenBefore {
+                            answers: answers.given(),
+                        }),
+                    })
+                    .or_payjoin(
+                        ErrorCode::ProposalRejected,
+                        ErrorStage::Proposal,
+                        "Failed to check inputs",
+                    )
+            },
+        )
+        .await
+    }
+
+    /// Like `check_no_inputs_seen_before_async`, but asks about every
+    /// input's outpoint in a single call. `are_known` resolves with one
+    /// answer per outpoint, in order.
+    #[napi(
+        ts_args_type = "areKnown: (inputs: InputContext[]) => Promise<boolean[]>, cancellationToken?: CancellationToken | undefined | null",
+        ts_return_type = "Promise<OutputsUnknownWrapper>"
+    )]
+    pub async fn check_no_inputs_seen_before_batch(
+        &self,
+        are_known: AsyncCallback<Vec<InputContext>, Vec<bool>>,
+        cancellation_token: Option<&CancellationToken>,
+    ) -> napi::Result<OutputsUnknownWrapper> {
+        let proposal = self.inner.clone();
+        let session = self.session.clone();
+        let original = self.original.clone();
+        let journal = self.journal.clone();
+
+        with_cancellation(
+            cancellation_token.cloned(),
+            ErrorStage::Proposal,
+            async move {
+                let outpoints = queried(|is_known| {
+                    let _ = proposal.clone().check_no_inputs_seen_before(is_known);
+                });
+                let answers = Answers::collect_batch(
+                    &are_known,
+                    outpoints,
+                    |_, outpoint| original.input_by_outpoint(outpoint),
+                    "Failed to check inputs",
+                )
+                .await?;
+
+                proposal
+                    .check_no_inputs_seen_before(|outpoint| answers.get(outpoint))
+                    .map(|inner| OutputsUnknownWrapper {
+                        inner,
+                        session,
+                        original,
+                        journal: journal.then(Step::CheckNoInputsSeenBefore {
+                            answers: answers.given(),
+                        }),
+                    })
+                    .or_payjoin(
+                        ErrorCode::ProposalRejected,
+                        ErrorStage::Proposal,
+                        "Failed to check inputs",
+                    )
+            },
+        )
+        .await
     }
 }
 
 #[napi]
 pub struct OutputsUnknownWrapper {
     inner: OutputsUnknown,
+    session: Receiver,
+    original: OriginalPsbt,
+    journal: Journal,
 }
 
 #[napi]
 impl OutputsUnknownWrapper {
+    /// Serialize the proposal with its session, so it can be resumed after a
+    /// restart with `fromJson`.
+    #[napi]
+    pub fn to_json(&self) -> napi::Result<String> {
+        persist::<OutputsUnknown>(&self.session, &self.journal)
+    }
+
+    /// Restore a proposal serialized by `toJson`. Fails if it was persisted
+    /// in a different state.
+    #[napi(factory)]
+    pub fn from_json(json_str: String) -> napi::Result<Self> {
+        let restored = restore::<OutputsUnknown>(&json_str)?;
+        Ok(Self {
+            inner: restored.inner,
+            session: restored.session,
+            original: restored.original,
+            journal: restored.journal,
+        })
+    }
+
     #[napi]
     pub fn identify_receiver_outputs(
         &mut self,
-        is_receiver_output: Function<String, bool>,
+        is_receiver_output: Function<OutputContext, bool>,
     ) -> napi::Result<WantsOutputsWrapper> {
-        self.inner
+        let thrown = Thrown::default();
+        let recording = Recording::default();
+        let asked = Cell::new(0);
+        let result = self
+            .inner
             .clone()
             .identify_receiver_outputs(|script| {
-                let script_hex = script.to_hex_string();
+                let output = self
+                    .original
+                    .output(asked.replace(asked.get() + 1), script)
+                    .map_err(context_error)?;
+                recording.record(is_receiver_output.call(output).map_err(|e| {
+                    log::debug!("identify_receiver_outputs: Error: {}", e);
+                    thrown.catch(e, "Failed to identify outputs")
+                }))
+            })
+            .map(|w| WantsOutputsWrapper {
+                inner: w,
+                session: self.session.clone(),
+                original: self.original.clone(),
+                journal: self.journal.then(Step::IdentifyReceiverOutputs {
+                    answers: recording.take(),
+                }),
+            })
+            .or_payjoin(
+                ErrorCode::ProposalRejected,
+                ErrorStage::Proposal,
+                "Failed to identify outputs",
+            );
 
-                let result = is_receiver_output.call(script_hex);
+        thrown.rethrow(result, "Failed to identify outputs")
+    }
 
-                result.map_err(|e| {
-                    log_debug(&format!("identify_receiver_outputs: Error: {}", e));
-                    payjoin::Error::Server(format!("Failed to identify outputs: {}", e).into())
-                })
-            })
-            .map(|w| WantsOutputsWrapper { inner: w })
-            .map_err(|e| napi::Error::from_reason(format!("Failed to identify outputs: {}", e)))
+    /// Like `identify_receiver_outputs`, for wallets that answer with a
+    /// Promise.
+    #[napi(
+        ts_args_type = "isReceiverOutput: (output: OutputContext) => Promise<boolean>, cancellationToken?: CancellationToken | undefined | null",
+        ts_return_type = "Promise<WantsOutputsWrapper>"
+    )]
+    pub async fn identify_receiver_outputs_async(
+        &self,
+        is_receiver_output: AsyncCallback<OutputContext, bool>,
+        cancellation_token: Option<&CancellationToken>,
+    ) -> napi::Result<WantsOutputsWrapper> {
+        let proposal = self.inner.clone();
+        let session = self.session.clone();
+        let original = self.original.clone();
+        let journal = self.journal.clone();
+
+        with_cancellation(
+            cancellation_token.cloned(),
+            ErrorStage::Proposal,
+            async move {
+                let scripts = queried(|is_receiver_output| {
+                    let _ = proposal
+                        .clone()
+                        .identify_receiver_outputs(is_receiver_output);
+                });
+                let answers = Answers::collect(
+                    &is_receiver_output,
+                    scripts,
+                    |vout, script: &ScriptBuf| original.output(vout, script),
+                    "Failed to identify outputs",
+                )
+                .await?;
+
+                proposal
+                    .identify_receiver_outputs(|script| answers.get(script))
+                    .map(|inner| WantsOutputsWrapper {
+                        inner,
+                        session,
+                        original,
+                        journal: journal.then(Step::IdentifyReceiverOutputs {
+                            answers: answers.given(),
+                        }),
+                    })
+                    .or_payjoin(
+                        ErrorCode::ProposalRejected,
+                        ErrorStage::Proposal,
+                        "Failed to identify outputs",
+                    )
+            },
+        )
+        .await
     }
 }
 
@@ -318,10 +978,33 @@ pub struct ReplacementOutput {
 #[napi]
 pub struct WantsOutputsWrapper {
     inner: WantsOutputs,
+    session: Receiver,
+    original: OriginalPsbt,
+    journal: Journal,
 }
 
 #[napi]
 impl WantsOutputsWrapper {
+    /// Serialize the proposal with its session, so it can be resumed after a
+    /// restart with `fromJson`.
+    #[napi]
+    pub fn to_json(&self) -> napi::Result<String> {
+        persist::<WantsOutputs>(&self.session, &self.journal)
+    }
+
+    /// Restore a proposal serialized by `toJson`. Fails if it was persisted
+    /// in a different state.
+    #[napi(factory)]
+    pub fn from_json(json_str: String) -> napi::Result<Self> {
+        let restored = restore::<WantsOutputs>(&json_str)?;
+        Ok(Self {
+            inner: restored.inner,
+            session: restored.session,
+            original: restored.original,
+            journal: restored.journal,
+        })
+    }
+
     #[napi]
     pub fn is_output_substitution_disabled(&self) -> bool {
         self.inner.is_output_substitution_disabled()
@@ -332,11 +1015,21 @@ impl WantsOutputsWrapper {
         &mut self,
         output_script: Vec<u8>,
     ) -> napi::Result<WantsOutputsWrapper> {
+        let script = ScriptBuf::from_bytes(output_script);
         self.inner
             .clone()
-            .substitute_receiver_script(Script::from_bytes(&output_script))
-            .map(|w| WantsOutputsWrapper { inner: w })
-            .map_err(|e| napi::Error::from_reason(format!("Failed to substitute script: {}", e)))
+            .substitute_receiver_script(&script)
+            .map(|w| WantsOutputsWrapper {
+                inner: w,
+                session: self.session.clone(),
+                original: self.original.clone(),
+                journal: self.journal.then(Step::SubstituteReceiverScript { script }),
+            })
+            .or_payjoin(
+                ErrorCode::InvalidScript,
+                ErrorStage::Proposal,
+                "Failed to substitute script",
+            )
     }
 
     #[napi]
@@ -348,25 +1041,47 @@ impl WantsOutputsWrapper {
         let outputs = replacement_outputs
             .into_iter()
             .map(|output| {
-                let (_, value, _) = output.value.get_u64();
-                TxOut {
+                Ok(TxOut {
                     script_pubkey: ScriptBuf::from_bytes(output.script),
-                    value: Amount::from_sat(value),
-                }
+                    value: to_amount(
+                        &output.value,
+                        ErrorStage::Proposal,
+                        "Invalid replacement output value",
+                    )?,
+                })
             })
-            .collect();
+            .collect::<napi::Result<Vec<_>>>()?;
+        let drain_script = ScriptBuf::from_bytes(drain_script);
 
         self.inner
             .clone()
-            .replace_receiver_outputs(outputs, Script::from_bytes(&drain_script))
-            .map(|w| WantsOutputsWrapper { inner: w })
-            .map_err(|e| napi::Error::from_reason(format!("Failed to replace outputs: {}", e)))
+            .replace_receiver_outputs(outputs.clone(), &drain_script)
+            .map(|w| WantsOutputsWrapper {
+                inner: w,
+                session: self.session.clone(),
+                original: self.original.clone(),
+                journal: self.journal.then(Step::ReplaceReceiverOutputs {
+                    order: self
+                        .journal
+                        .draw_output_order(self.original.transaction(), &outputs),
+                    outputs,
+                    drain_script,
+                }),
+            })
+            .or_payjoin(
+                ErrorCode::InvalidScript,
+                ErrorStage::Proposal,
+                "Failed to replace outputs",
+            )
     }
 
     #[napi]
     pub fn commit_outputs(&mut self) -> WantsInputsWrapper {
         WantsInputsWrapper {
             inner: self.inner.clone().commit_outputs(),
+            session: self.session.clone(),
+            original: self.original.clone(),
+            journal: self.journal.then(Step::CommitOutputs),
         }
     }
 }
@@ -380,13 +1095,16 @@ pub struct PartialSigData {
 #[napi(object)]
 pub struct Bip32DerivationData {
     pub pubkey: Vec<u8>,
+    /// The master key fingerprint, followed by any path steps before `child`
+    /// as little-endian u32s, as in a PSBT key source.
     pub fingerprint_path: Vec<u8>,
+    /// The final path step. Hardened steps have the high bit set.
     pub child: u32,
 }
 
 #[napi(object)]
 pub struct WitnessUtxoData {
-    pub amount: f64,            // Amount in BTC
+    pub amount: BigInt,         // Amount in satoshis
     pub script_pub_key: String, // Script in hex format
 }
 
@@ -401,9 +1119,49 @@ pub struct PsbtInputData {
     pub bip32_derivation: Option<Vec<Bip32DerivationData>>,
     pub final_script_sig: Option<Vec<u8>>,
     pub final_script_witness: Option<Vec<Vec<u8>>>,
+    /// Schnorr signature for a key path spend, with its sighash byte when
+    /// not the default.
+    pub tap_key_sig: Option<Vec<u8>>,
+    pub tap_script_sigs: Option<Vec<TapScriptSigData>>,
+    pub tap_scripts: Option<Vec<TapLeafScriptData>>,
+    pub tap_bip32_derivation: Option<Vec<TapBip32DerivationData>>,
+    /// The 32-byte x-only internal key.
+    pub tap_internal_key: Option<Vec<u8>>,
+    /// The 32-byte root of the script tree.
+    pub tap_merkle_root: Option<Vec<u8>>,
     //pub proprietary: Vec<(Vec<u8>, Vec<u8>)>, // (identifier, value)
 }
 
+/// A Schnorr signature for a script path spend.
+#[napi(object)]
+pub struct TapScriptSigData {
+    /// The 32-byte x-only key that signed.
+    pub pubkey: Vec<u8>,
+    /// The 32-byte hash of the leaf the signature is for.
+    pub leaf_hash: Vec<u8>,
+    pub signature: Vec<u8>,
+}
+
+/// A leaf script with the control block proving it is in the tree.
+#[napi(object)]
+pub struct TapLeafScriptData {
+    pub control_block: Vec<u8>,
+    pub script: Vec<u8>,
+    /// Defaults to 0xc0, tapscript.
+    pub leaf_version: Option<u32>,
+}
+
+#[napi(object)]
+pub struct TapBip32DerivationData {
+    /// The 32-byte x-only key.
+    pub pubkey: Vec<u8>,
+    /// Hashes of the leaves the key appears in. Empty for the internal key.
+    pub leaf_hashes: Vec<Vec<u8>>,
+    /// As in `Bip32DerivationData`.
+    pub fingerprint_path: Vec<u8>,
+    pub child: u32,
+}
+
 #[napi(object)]
 pub struct TxOutpoint {
     pub txid: String, // Transaction ID as hex string
@@ -419,20 +1177,39 @@ pub struct InputPairRequest {
     pub psbt_data: PsbtInputData,      // PSBT input data
 }
 
+/// Check that `input` maps to a valid PSBT input and can be contributed,
+/// before any proposal needs it.
+#[napi]
+pub fn validate_input_pair(input: InputPairRequest) -> napi::Result<()> {
+    input.into_input_pair().map(|_| ())
+}
+
 impl InputPairRequest {
     pub fn into_input_pair(self) -> napi::Result<InputPair> {
+        let (txin, psbtin) = self.into_parts()?;
+        InputPair::new(txin, psbtin).or_payjoin(
+            ErrorCode::InvalidInput,
+            ErrorStage::Proposal,
+            "Failed to create InputPair",
+        )
+    }
+
+    fn into_parts(self) -> napi::Result<(TxIn, Input)> {
         // Create txid directly from the hex string
-        let txid = payjoin::bitcoin::Txid::from_str(&self.prevout.txid)
-            .map_err(|e| napi::Error::from_reason(format!("Invalid txid hex: {}", e)))?;
+        let txid = payjoin::bitcoin::Txid::from_str(&self.prevout.txid).or_payjoin(
+            ErrorCode::InvalidInput,
+            ErrorStage::Proposal,
+            "Invalid txid hex",
+        )?;
 
         let outpoint = OutPoint {
             txid,
             vout: self.prevout.vout,
         };
-        log_debug(&format!(
+        log::trace!(
             "InputPairRequest::into_input_pair: outpoint: {:?}",
             outpoint
-        ));
+        );
 
         let txin = TxIn {
             previous_output: outpoint,
@@ -440,168 +1217,534 @@ impl InputPairRequest {
             sequence: Sequence::from_consensus(self.sequence.unwrap_or(0xffffffff)),
             witness: Witness::from(self.witness.unwrap_or_default()),
         };
-        log_debug(&format!(
-            "InputPairRequest::into_input_pair: txin: {:?}",
-            txin
-        ));
+        log::trace!("InputPairRequest::into_input_pair: txin: {:?}", txin);
 
         let mut psbtin = Input::default();
 
         // Handle non-witness UTXO
         if let Some(utxo) = self.psbt_data.non_witness_utxo {
             psbtin.non_witness_utxo =
-                Some(Transaction::consensus_decode(&mut &utxo[..]).map_err(|e| {
-                    napi::Error::from_reason(format!("Invalid non-witness UTXO: {}", e))
-                })?);
-        }
-
-        if let Some(witness_utxo) = &self.psbt_data.witness_utxo {
-            log_debug(&format!(
-                "InputPairRequest::into_input_pair: psbt_data_witness_utxo.amount: {:?}",
-                witness_utxo.amount
-            ));
-            log_debug(&format!(
-                "InputPairRequest::into_input_pair: psbt_data_witness_utxo.script_pub_key: {:?}",
-                witness_utxo.script_pub_key
-            ));
-        } else {
-            log_debug("InputPairRequest::into_input_pair: No witness_utxo provided");
+                Some(Transaction::consensus_decode(&mut &utxo[..]).or_payjoin(
+                    ErrorCode::InvalidInput,
+                    ErrorStage::Proposal,
+                    "Invalid non-witness UTXO",
+                )?);
         }
 
         // Handle witness UTXO
         if let Some(utxo) = self.psbt_data.witness_utxo {
-            // Convert amount from BTC to satoshis
-            let amount_sat = (utxo.amount * 100_000_000.0) as u64;
-            log_debug(&format!(
-                "InputPairRequest::into_input_pair: amount_sat: {:?}",
-                amount_sat
-            ));
-            log_debug(&format!(
+            log::trace!(
                 "InputPairRequest::into_input_pair: script_pub_key: {:?}",
                 utxo.script_pub_key
-            ));
-            // Create TxOut with the amount and script
+            );
             psbtin.witness_utxo = Some(TxOut {
-                value: Amount::from_sat(amount_sat),
-                script_pubkey: ScriptBuf::from_hex(&utxo.script_pub_key)
-                    .map_err(|e| napi::Error::from_reason(format!("Invalid script hex: {}", e)))?,
+                value: to_amount(
+                    &utxo.amount,
+                    ErrorStage::Proposal,
+                    "Invalid witness UTXO amount",
+                )?,
+                script_pubkey: ScriptBuf::from_hex(&utxo.script_pub_key).or_payjoin(
+                    ErrorCode::InvalidScript,
+                    ErrorStage::Proposal,
+                    "Invalid script hex",
+                )?,
             });
+        } else {
+            log::trace!("InputPairRequest::into_input_pair: No witness_utxo provided");
         }
 
         // Handle partial signatures
-        // if let Some(sigs) = self.psbt_data.partial_sigs {
-        //     for sig_data in sigs {
-        //         psbtin.partial_sigs.insert(
-        //             PublicKey::from_slice(&sig_data.pubkey)
-        //                 .map_err(|e| napi::Error::from_reason(format!("Invalid pubkey: {}", e)))?,
-        //             Signature::from_slice(&sig_data.signature).map_err(|e| {
-        //                 napi::Error::from_reason(format!("Invalid signature: {}", e))
-        //             })?,
-        //         );
-        //     }
-        // }
+        for sig_data in self.psbt_data.partial_sigs.unwrap_or_default() {
+            let pubkey = PublicKey::from_slice(&sig_data.pubkey).or_payjoin(
+                ErrorCode::InvalidInput,
+                ErrorStage::Proposal,
+                "Invalid partial signature pubkey",
+            )?;
+            let signature = ecdsa::Signature::from_slice(&sig_data.signature).or_payjoin(
+                ErrorCode::InvalidInput,
+                ErrorStage::Proposal,
+                "Invalid partial signature",
+            )?;
+            psbtin.partial_sigs.insert(pubkey, signature);
+        }
 
         // Handle sighash type
-        // if let Some(sighash) = self.psbt_data.sighash_type {
-        //     psbtin.sighash_type = Some(PsbtSighashType::from_u32(sighash));
-        // }
-
-        // Handle redeem script
-        // if let Some(script) = self.psbt_data.redeem_script {
-        //     psbtin.redeem_script = Some(ScriptBuf::from_bytes(script));
-        // }
+        if let Some(sighash) = self.psbt_data.sighash_type {
+            let sighash_type = PsbtSighashType::from_u32(sighash);
+            if sighash_type.ecdsa_hash_ty().is_err() && sighash_type.taproot_hash_ty().is_err() {
+                return Err(PayjoinError::new(
+                    ErrorCode::InvalidInput,
+                    ErrorStage::Proposal,
+                    format!("Non-standard sighash type: {:#x}", sighash),
+                )
+                .into());
+            }
+            psbtin.sighash_type = Some(sighash_type);
+        }
 
-        // Handle witness script
-        // if let Some(script) = self.psbt_data.witness_script {
-        //     psbtin.witness_script = Some(ScriptBuf::from_bytes(script));
-        // }
+        // Handle redeem and witness scripts
+        psbtin.redeem_script = self.psbt_data.redeem_script.map(ScriptBuf::from_bytes);
+        psbtin.witness_script = self.psbt_data.witness_script.map(ScriptBuf::from_bytes);
 
         // Handle BIP32 derivation paths
-        // if let Some(derivation_data) = self.psbt_data.bip32_derivation {
-        //     for data in derivation_data {
-        //         let pubkey = Secp256k1PublicKey::from_slice(&data.pubkey)
-        //             .map_err(|e| napi::Error::from_reason(format!("Invalid pubkey: {}", e)))?;
-
-        //         // Convert the first 4 bytes to a fingerprint
-        //         let mut fingerprint_bytes = [0u8; 4];
-        //         fingerprint_bytes.copy_from_slice(&data.fingerprint_path[0..4]);
-        //         let fingerprint = Fingerprint::from(fingerprint_bytes);
-
-        //         // Convert child number to a derivation path
-        //         let child_number = ChildNumber::from_normal_idx(data.child).map_err(|e| {
-        //             napi::Error::from_reason(format!("Invalid child number: {}", e))
-        //         })?;
-        //         let path = DerivationPath::from(vec![child_number]);
-
-        //         psbtin.bip32_derivation.insert(pubkey, (fingerprint, path));
-        //     }
-        // }
-
-        // Handle final scriptSig
-        // if let Some(script_sig) = self.psbt_data.final_script_sig {
-        //     psbtin.final_script_sig = Some(ScriptBuf::from_bytes(script_sig));
-        // }
-
-        // Handle final scriptWitness
-        // if let Some(witness_stack) = self.psbt_data.final_script_witness {
-        //     psbtin.final_script_witness = Some(Witness::from_slice(&witness_stack));
-        // }
-
-        log_debug(&format!(
-            "InputPairRequest::into_input_pair: psbtin: {:?}",
+        for data in self.psbt_data.bip32_derivation.unwrap_or_default() {
+            let pubkey = Secp256k1PublicKey::from_slice(&data.pubkey).or_payjoin(
+                ErrorCode::InvalidInput,
+                ErrorStage::Proposal,
+                "Invalid BIP32 derivation pubkey",
+            )?;
             psbtin
-        ));
-        InputPair::new(txin, psbtin)
-            .map_err(|e| napi::Error::from_reason(format!("Failed to create InputPair: {}", e)))
+                .bip32_derivation
+                .insert(pubkey, key_source(&data.fingerprint_path, data.child)?);
+        }
+
+        // Handle final scriptSig and scriptWitness
+        psbtin.final_script_sig = self.psbt_data.final_script_sig.map(ScriptBuf::from_bytes);
+        psbtin.final_script_witness = self
+            .psbt_data
+            .final_script_witness
+            .map(|stack| Witness::from_slice(&stack));
+
+        // Handle taproot key and script path data
+        if let Some(sig) = self.psbt_data.tap_key_sig {
+            psbtin.tap_key_sig = Some(taproot::Signature::from_slice(&sig).or_payjoin(
+                ErrorCode::InvalidInput,
+                ErrorStage::Proposal,
+                "Invalid taproot key signature",
+            )?);
+        }
+        for sig_data in self.psbt_data.tap_script_sigs.unwrap_or_default() {
+            let pubkey = x_only_key(&sig_data.pubkey, "Invalid taproot script signature pubkey")?;
+            let leaf_hash = leaf_hash(&sig_data.leaf_hash)?;
+            let signature = taproot::Signature::from_slice(&sig_data.signature).or_payjoin(
+                ErrorCode::InvalidInput,
+                ErrorStage::Proposal,
+                "Invalid taproot script signature",
+            )?;
+            psbtin
+                .tap_script_sigs
+                .insert((pubkey, leaf_hash), signature);
+        }
+        for leaf in self.psbt_data.tap_scripts.unwrap_or_default() {
+            let control_block = ControlBlock::decode(&leaf.control_block).or_payjoin(
+                ErrorCode::InvalidInput,
+                ErrorStage::Proposal,
+                "Invalid taproot control block",
+            )?;
+            let leaf_version = match leaf.leaf_version {
+                None => LeafVersion::TapScript,
+                Some(version) => u8::try_from(version)
+                    .ok()
+                    .and_then(|version| LeafVersion::from_consensus(version).ok())
+                    .ok_or_else(|| {
+                        PayjoinError::new(
+                            ErrorCode::InvalidInput,
+                            ErrorStage::Proposal,
+                            format!("Invalid taproot leaf version: {:#x}", version),
+                        )
+                    })?,
+            };
+            psbtin.tap_scripts.insert(
+                control_block,
+                (ScriptBuf::from_bytes(leaf.script), leaf_version),
+            );
+        }
+        for data in self.psbt_data.tap_bip32_derivation.unwrap_or_default() {
+            let pubkey = x_only_key(&data.pubkey, "Invalid taproot BIP32 derivation pubkey")?;
+            let leaf_hashes = data
+                .leaf_hashes
+                .iter()
+                .map(|hash| leaf_hash(hash))
+                .collect::<napi::Result<Vec<_>>>()?;
+            psbtin.tap_key_origins.insert(
+                pubkey,
+                (leaf_hashes, key_source(&data.fingerprint_path, data.child)?),
+            );
+        }
+        if let Some(key) = self.psbt_data.tap_internal_key {
+            psbtin.tap_internal_key = Some(x_only_key(&key, "Invalid taproot internal key")?);
+        }
+        if let Some(root) = self.psbt_data.tap_merkle_root {
+            psbtin.tap_merkle_root = Some(TapNodeHash::from_slice(&root).or_payjoin(
+                ErrorCode::InvalidInput,
+                ErrorStage::Proposal,
+                "Invalid taproot merkle root",
+            )?);
+        }
+
+        log::trace!("InputPairRequest::into_input_pair: psbtin: {:?}", psbtin);
+        Ok((txin, psbtin))
     }
 }
 
+/// What `contribute_inputs_with_fallback` does when no candidate avoids the
+/// unnecessary input heuristics.
+#[napi(string_enum)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SelectionFallback {
+    /// Contribute the candidate with the largest value anyway. payjoin does
+    /// not expose how close a candidate comes to avoiding the heuristics, so
+    /// candidates are ranked by value alone.
+    LargestInput,
+    /// Contribute nothing and still return a proposal.
+    NoInput,
+    /// Fail as `try_contribute_inputs` does.
+    Abort,
+}
+
+/// Which inputs `contribute_inputs_with_fallback` ended up contributing.
+#[napi(string_enum)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ContributionBranch {
+    /// The privacy preserving selection succeeded.
+    PrivacyPreserving,
+    /// Selection failed and the largest candidate was contributed.
+    LargestInput,
+    /// Selection failed and no input was contributed.
+    NoInput,
+}
+
+#[napi(object, object_from_js = false)]
+pub struct ContributionOutcome {
+    pub proposal: ProvisionalProposalWrapper,
+    pub branch: ContributionBranch,
+    /// Index of the contributed input in the candidates, unless none was.
+    pub selected_index: Option<u32>,
+}
+
+/// The value a candidate spends, if its PSBT data says.
+fn candidate_value((txin, psbtin): &(TxIn, Input)) -> Option<Amount> {
+    match (&psbtin.witness_utxo, &psbtin.non_witness_utxo) {
+        (Some(utxo), _) => Some(utxo.value),
+        (None, Some(tx)) => tx
+            .output
+            .get(txin.previous_output.vout as usize)
+            .map(|output| output.value),
+        (None, None) => None,
+    }
+}
+
+fn x_only_key(bytes: &[u8], context: &str) -> napi::Result<XOnlyPublicKey> {
+    XOnlyPublicKey::from_slice(bytes).or_payjoin(
+        ErrorCode::InvalidInput,
+        ErrorStage::Proposal,
+        context,
+    )
+}
+
+fn leaf_hash(bytes: &[u8]) -> napi::Result<TapLeafHash> {
+    TapLeafHash::from_slice(bytes).or_payjoin(
+        ErrorCode::InvalidInput,
+        ErrorStage::Proposal,
+        "Invalid taproot leaf hash",
+    )
+}
+
+/// Read a BIP32 key source laid out as in a PSBT: the master key fingerprint
+/// followed by the path's steps as little-endian u32s, with `child` appended
+/// as the final step.
+fn key_source(fingerprint_path: &[u8], child: u32) -> napi::Result<KeySource> {
+    if fingerprint_path.len() < 4 || !fingerprint_path.len().is_multiple_of(4) {
+        return Err(PayjoinError::new(
+            ErrorCode::InvalidInput,
+            ErrorStage::Proposal,
+            format!(
+                "Invalid BIP32 key source: expected a 4-byte fingerprint and 4 bytes per step, got {} bytes",
+                fingerprint_path.len()
+            ),
+        )
+        .into());
+    }
+
+    let (fingerprint, steps) = fingerprint_path.split_at(4);
+    let fingerprint = Fingerprint::from(<[u8; 4]>::try_from(fingerprint).expect("4 bytes"));
+    let path = steps
+        .chunks_exact(4)
+        .map(|step| u32::from_le_bytes(step.try_into().expect("4 bytes")))
+        .chain(std::iter::once(child))
+        .map(ChildNumber::from)
+        .collect::<DerivationPath>();
+    Ok((fingerprint, path))
+}
+
 #[napi]
 pub struct WantsInputsWrapper {
     inner: WantsInputs,
+    session: Receiver,
+    original: OriginalPsbt,
+    journal: Journal,
 }
 
 #[napi]
 impl WantsInputsWrapper {
+    /// Serialize the proposal with its session, so it can be resumed after a
+    /// restart with `fromJson`.
+    #[napi]
+    pub fn to_json(&self) -> napi::Result<String> {
+        persist::<WantsInputs>(&self.session, &self.journal)
+    }
+
+    /// Restore a proposal serialized by `toJson`. Fails if it was persisted
+    /// in a different state.
+    #[napi(factory)]
+    pub fn from_json(json_str: String) -> napi::Result<Self> {
+        let restored = restore::<WantsInputs>(&json_str)?;
+        Ok(Self {
+            inner: restored.inner,
+            session: restored.session,
+            original: restored.original,
+            journal: restored.journal,
+        })
+    }
+
+    /// Pick the candidate that best preserves privacy, following the
+    /// unnecessary input heuristic, and return its index in
+    /// `candidate_inputs`. Nothing is contributed until `contribute_inputs`.
+    #[napi]
+    pub fn select_privacy_preserving_input(
+        &self,
+        candidate_inputs: Vec<InputPairRequest>,
+    ) -> napi::Result<u32> {
+        let (_, inputs) = input_pairs(candidate_inputs)?;
+        self.inner
+            .try_preserving_privacy(inputs.clone())
+            .or_payjoin(
+                ErrorCode::InvalidInput,
+                ErrorStage::Proposal,
+                "Failed to make privacy preserving selection",
+            )?;
+
+        let index = selected_index(&self.inner, &inputs)?;
+        Ok(index as u32)
+    }
+
+    /// Add `inputs` to the proposal. Can be called again to contribute more
+    /// before `commit_inputs`.
+    #[napi]
+    pub fn contribute_inputs(
+        &self,
+        inputs: Vec<InputPairRequest>,
+    ) -> napi::Result<WantsInputsWrapper> {
+        let (parts, inputs) = input_pairs(inputs)?;
+        let inner = self.inner.clone().contribute_inputs(inputs).or_payjoin(
+            ErrorCode::InvalidInput,
+            ErrorStage::Proposal,
+            "Failed to contribute inputs",
+        )?;
+
+        Ok(WantsInputsWrapper {
+            inner,
+            session: self.session.clone(),
+            original: self.original.clone(),
+            journal: self.journal.then(Step::ContributeInputs {
+                order: self.journal.draw_input_order(
+                    self.original.transaction(),
+                    parts.iter().map(|(txin, _)| txin.previous_output),
+                ),
+                inputs: parts,
+            }),
+        })
+    }
+
+    /// Stop contributing inputs. Committing without contributing any is
+    /// allowed, e.g. when the wallet has nothing suitable to offer.
+    #[napi]
+    pub fn commit_inputs(&self) -> ProvisionalProposalWrapper {
+        ProvisionalProposalWrapper {
+            inner: self.inner.clone().commit_inputs(),
+            session: self.session.clone(),
+            journal: self.journal.then(Step::CommitInputs),
+        }
+    }
+
+    /// Select one privacy preserving input from `candidate_inputs`,
+    /// contribute it and commit, in a single call.
     #[napi]
     pub fn try_contribute_inputs(
         &mut self,
         candidate_inputs: Vec<InputPairRequest>,
     ) -> napi::Result<ProvisionalProposalWrapper> {
-        // Convert inputs
-        let inputs = candidate_inputs
-            .into_iter()
-            .map(|input| input.into_input_pair())
-            .collect::<Result<Vec<_>, _>>()?;
+        // Keep the candidates' parts to replay the selection
+        let (candidates, inputs) = input_pairs(candidate_inputs)?;
 
         // First try to select a privacy preserving input
-        let selected_input = self.inner.try_preserving_privacy(inputs).map_err(|e| {
-            napi::Error::from_reason(format!(
-                "Failed to make privacy preserving selection: {}",
-                e
-            ))
-        })?;
+        let selected_input = self
+            .inner
+            .try_preserving_privacy(inputs.clone())
+            .or_payjoin(
+                ErrorCode::InvalidInput,
+                ErrorStage::Proposal,
+                "Failed to make privacy preserving selection",
+            )?;
+        let selected = candidates[selected_index(&self.inner, &inputs)?]
+            .0
+            .previous_output;
 
         // Then contribute the selected input and immediately commit
         let result = self
             .inner
             .clone()
             .contribute_inputs(vec![selected_input])
-            .map_err(|e| napi::Error::from_reason(format!("Failed to contribute inputs: {}", e)))?
+            .or_payjoin(
+                ErrorCode::InvalidInput,
+                ErrorStage::Proposal,
+                "Failed to contribute inputs",
+            )?
             .commit_inputs();
 
         // Return the proposal
-        Ok(ProvisionalProposalWrapper { inner: result })
+        Ok(ProvisionalProposalWrapper {
+            inner: result,
+            session: self.session.clone(),
+            journal: self.journal.then(Step::TryContributeInputs {
+                order: self
+                    .journal
+                    .draw_input_order(self.original.transaction(), [selected]),
+                candidates,
+            }),
+        })
     }
+
+    /// Like `try_contribute_inputs`, but when no candidate avoids the
+    /// unnecessary input heuristics, `fallback` decides what happens instead
+    /// of failing. The outcome reports which branch was taken.
+    #[napi]
+    pub fn contribute_inputs_with_fallback(
+        &self,
+        candidate_inputs: Vec<InputPairRequest>,
+        fallback: SelectionFallback,
+    ) -> napi::Result<ContributionOutcome> {
+        let (candidates, inputs) = input_pairs(candidate_inputs)?;
+
+        let (branch, selected_index) = match self.inner.try_preserving_privacy(inputs.clone()) {
+            Ok(_) => {
+                let index = selected_index(&self.inner, &inputs)?;
+                (ContributionBranch::PrivacyPreserving, Some(index))
+            }
+            Err(e) => {
+                log::debug!("contribute_inputs_with_fallback: {:?}: {}", fallback, e);
+                match fallback {
+                    SelectionFallback::LargestInput if !candidates.is_empty() => {
+                        let index = (0..candidates.len())
+                            .max_by_key(|&i| candidate_value(&candidates[i]))
+                            .expect("candidates are not empty");
+                        (ContributionBranch::LargestInput, Some(index))
+                    }
+                    SelectionFallback::NoInput => (ContributionBranch::NoInput, None),
+                    _ => {
+                        return Err(e).or_payjoin(
+                            ErrorCode::InvalidInput,
+                            ErrorStage::Proposal,
+                            "Failed to make privacy preserving selection",
+                        )
+                    }
+                }
+            }
+        };
+
+        let wants_inputs = match selected_index {
+            Some(index) => WantsInputsWrapper {
+                inner: self
+                    .inner
+                    .clone()
+                    .contribute_inputs(vec![inputs[index].clone()])
+                    .or_payjoin(
+                        ErrorCode::InvalidInput,
+                        ErrorStage::Proposal,
+                        "Failed to contribute inputs",
+                    )?,
+                session: self.session.clone(),
+                original: self.original.clone(),
+                journal: self.journal.then(Step::ContributeInputs {
+                    order: self.journal.draw_input_order(
+                        self.original.transaction(),
+                        [candidates[index].0.previous_output],
+                    ),
+                    inputs: vec![candidates[index].clone()],
+                }),
+            },
+            None => WantsInputsWrapper {
+                inner: self.inner.clone(),
+                session: self.session.clone(),
+                original: self.original.clone(),
+                journal: self.journal.clone(),
+            },
+        };
+
+        Ok(ContributionOutcome {
+            proposal: wants_inputs.commit_inputs(),
+            branch,
+            selected_index: selected_index.map(|index| index as u32),
+        })
+    }
+}
+
+/// Find which of `inputs` the privacy preserving selection returned.
+/// `InputPair` keeps its `TxIn` private, but payjoin judges each candidate on
+/// its own and returns the first that passes, so the selection is the first
+/// input that would also be selected when offered alone.
+fn selected_index(proposal: &WantsInputs, inputs: &[InputPair]) -> napi::Result<usize> {
+    inputs
+        .iter()
+        .position(|input| proposal.try_preserving_privacy([input.clone()]).is_ok())
+        .ok_or_else(|| {
+            PayjoinError::new(
+                ErrorCode::InvalidInput,
+                ErrorStage::Proposal,
+                "Selected input is not one of the candidates",
+            )
+            .into()
+        })
 }
 
+/// An input's unsigned `TxIn` and PSBT data, as recorded in the journal.
+type InputParts = Vec<(TxIn, Input)>;
+
+/// Convert inputs from JavaScript, keeping their parts for the journal.
+fn input_pairs(requests: Vec<InputPairRequest>) -> napi::Result<(InputParts, Vec<InputPair>)> {
+    let parts = requests
+        .into_iter()
+        .map(|input| input.into_parts())
+        .collect::<Result<Vec<_>, _>>()?;
+    let inputs = journal::input_pairs(&parts).or_payjoin(
+        ErrorCode::InvalidInput,
+        ErrorStage::Proposal,
+        "Failed to create InputPair",
+    )?;
+    Ok((parts, inputs))
+}
+
+/// Why a proposal could not be put back in its recorded order.
+const UNARRANGED: &str = "the proposal's inputs or outputs differ from the recorded ones";
+
 #[napi]
 pub struct ProvisionalProposalWrapper {
     inner: ProvisionalProposal,
+    session: Receiver,
+    journal: Journal,
 }
 
 #[napi]
 impl ProvisionalProposalWrapper {
+    /// Serialize the proposal with its session, so it can be resumed after a
+    /// restart with `fromJson`.
+    #[napi]
+    pub fn to_json(&self) -> napi::Result<String> {
+        persist::<ProvisionalProposal>(&self.session, &self.journal)
+    }
+
+    /// Restore a proposal serialized by `toJson`. Fails if it was persisted
+    /// in a different state.
+    #[napi(factory)]
+    pub fn from_json(json_str: String) -> napi::Result<Self> {
+        let restored = restore::<ProvisionalProposal>(&json_str)?;
+        Ok(Self {
+            inner: restored.inner,
+            session: restored.session,
+            journal: restored.journal,
+        })
+    }
+
     #[napi]
     pub fn finalize_proposal(
         &mut self,
@@ -609,56 +1752,185 @@ impl ProvisionalProposalWrapper {
         max_feerate_sat_per_vb: Option<f64>,
         wallet_process_psbt: Function<String, String>,
     ) -> napi::Result<PayjoinProposalWrapper> {
-        let min_fee_rate = min_feerate_sat_per_vb
-            .map(|rate| {
-                FeeRate::from_sat_per_vb(rate as u64)
-                    .ok_or_else(|| napi::Error::from_reason("Invalid min fee rate"))
-            })
-            .transpose()?;
+        let min_fee_rate = parse_fee_rate(min_feerate_sat_per_vb, "Invalid min fee rate")?;
+        let max_fee_rate = parse_fee_rate(max_feerate_sat_per_vb, "Invalid max fee rate")?
+            .unwrap_or(FeeRate::ZERO);
 
-        let max_fee_rate = max_feerate_sat_per_vb
-            .map(|rate| {
-                FeeRate::from_sat_per_vb(rate as u64)
-                    .ok_or_else(|| napi::Error::from_reason("Invalid max fee rate"))
-            })
-            .transpose()?;
-
-        self.inner
+        let thrown = Thrown::default();
+        let processed = std::cell::RefCell::new(None);
+        let result = self
+            .inner
             .clone()
             .finalize_proposal(
                 |psbt| {
+                    let psbt = self
+                        .journal
+                        .arrange(psbt)
+                        .ok_or_else(|| payjoin::Error::Server(UNARRANGED.into()))?;
                     let psbt_string = psbt.to_string();
-                    log_debug(&format!(
-                        "finalize_proposal: Finalizing PSBT: {}",
-                        psbt_string
-                    ));
-                    let result = wallet_process_psbt.call(psbt_string).map_err(|e| {
-                        payjoin::Error::Server(
-                            format!("Failed to call wallet_process_psbt: {}", e).into(),
-                        )
-                    })?;
+                    log::trace!("finalize_proposal: Finalizing PSBT: {}", psbt_string);
+                    let result = wallet_process_psbt
+                        .call(psbt_string)
+                        .map_err(|e| thrown.catch(e, "Failed to call wallet_process_psbt"))?;
 
-                    Psbt::from_str(&result).map_err(|e| {
+                    let psbt = Psbt::from_str(&result).map_err(|e| {
                         payjoin::Error::Server(
                             format!("Failed to parse finalized PSBT: {}", e).into(),
                         )
-                    })
+                    })?;
+                    processed.replace(Some(psbt.clone()));
+                    Ok(psbt)
                 },
                 min_fee_rate,
-                max_fee_rate.unwrap_or(FeeRate::ZERO),
+                max_fee_rate,
             )
-            .map(|p| PayjoinProposalWrapper { inner: p })
-            .map_err(|e| napi::Error::from_reason(format!("Failed to finalize proposal: {}", e)))
+            .map(|p| PayjoinProposalWrapper {
+                inner: p,
+                session: self.session.clone(),
+                journal: self.journal.then(Step::FinalizeProposal {
+                    min_fee_rate_sat_per_kwu: min_fee_rate.map(FeeRate::to_sat_per_kwu),
+                    max_fee_rate_sat_per_kwu: max_fee_rate.to_sat_per_kwu(),
+                    psbt: processed
+                        .take()
+                        .expect("finalize_proposal only succeeds with a processed PSBT"),
+                }),
+            })
+            .or_payjoin(
+                ErrorCode::ReceiverFailed,
+                ErrorStage::Proposal,
+                "Failed to finalize proposal",
+            );
+
+        thrown.rethrow(result, "Failed to call wallet_process_psbt")
+    }
+
+    /// Like `finalize_proposal`, for wallets that sign asynchronously, e.g.
+    /// through `walletprocesspsbt` over RPC or a hardware signer.
+    #[napi(
+        ts_args_type = "minFeerateSatPerVb: number | undefined | null, maxFeerateSatPerVb: number | undefined | null, walletProcessPsbt: (psbt: string) => Promise<string>, cancellationToken?: CancellationToken | undefined | null",
+        ts_return_type = "Promise<PayjoinProposalWrapper>"
+    )]
+    pub async fn finalize_proposal_async(
+        &self,
+        min_feerate_sat_per_vb: Option<f64>,
+        max_feerate_sat_per_vb: Option<f64>,
+        wallet_process_psbt: AsyncCallback<String, String>,
+        cancellation_token: Option<&CancellationToken>,
+    ) -> napi::Result<PayjoinProposalWrapper> {
+        let min_fee_rate = parse_fee_rate(min_feerate_sat_per_vb, "Invalid min fee rate")?;
+        let max_fee_rate = parse_fee_rate(max_feerate_sat_per_vb, "Invalid max fee rate")?
+            .unwrap_or(FeeRate::ZERO);
+        let proposal = self.inner.clone();
+        let session = self.session.clone();
+        let journal = self.journal.clone();
+
+        with_cancellation(
+            cancellation_token.cloned(),
+            ErrorStage::Proposal,
+            async move {
+                // Fee application is deterministic, so the PSBT handed to the
+                // wallet here is the one the real run below will ask for.
+                let unsigned = std::cell::RefCell::new(None);
+                let prepared = proposal
+                    .clone()
+                    .finalize_proposal(
+                        |psbt| {
+                            unsigned.replace(Some(psbt.clone()));
+                            Err(payjoin::Error::Server(
+                                "PSBT recorded for the wallet".into(),
+                            ))
+                        },
+                        min_fee_rate,
+                        max_fee_rate,
+                    )
+                    .or_payjoin(
+                        ErrorCode::ReceiverFailed,
+                        ErrorStage::Proposal,
+                        "Failed to finalize proposal",
+                    );
+                let Some(unsigned) = unsigned.into_inner() else {
+                    // Only reachable if payjoin stops asking the wallet, in
+                    // which case there is no PSBT to replay either.
+                    return prepared.and_then(|_| {
+                        Err(PayjoinError::new(
+                            ErrorCode::ReceiverFailed,
+                            ErrorStage::Proposal,
+                            "Failed to finalize proposal: the wallet was never asked to process the PSBT",
+                        )
+                        .into())
+                    });
+                };
+
+                let unsigned = journal.arrange(&unsigned).ok_or_else(|| -> napi::Error {
+                    PayjoinError::new(
+                        ErrorCode::ReceiverFailed,
+                        ErrorStage::Proposal,
+                        format!("Failed to finalize proposal: {}", UNARRANGED),
+                    )
+                    .into()
+                })?;
+                let processed = call_async(
+                    &wallet_process_psbt,
+                    unsigned.to_string(),
+                    "Failed to call wallet_process_psbt",
+                )
+                .await?;
+                let processed = Psbt::from_str(&processed).or_payjoin(
+                    ErrorCode::InvalidPsbt,
+                    ErrorStage::Proposal,
+                    "Failed to parse finalized PSBT",
+                )?;
+
+                proposal
+                    .finalize_proposal(|_| Ok(processed.clone()), min_fee_rate, max_fee_rate)
+                    .map(|p| PayjoinProposalWrapper {
+                        inner: p,
+                        session,
+                        journal: journal.then(Step::FinalizeProposal {
+                            min_fee_rate_sat_per_kwu: min_fee_rate.map(FeeRate::to_sat_per_kwu),
+                            max_fee_rate_sat_per_kwu: max_fee_rate.to_sat_per_kwu(),
+                            psbt: processed,
+                        }),
+                    })
+                    .or_payjoin(
+                        ErrorCode::ReceiverFailed,
+                        ErrorStage::Proposal,
+                        "Failed to finalize proposal",
+                    )
+            },
+        )
+        .await
     }
 }
 
 #[napi]
 pub struct PayjoinProposalWrapper {
     inner: PayjoinProposal,
+    session: Receiver,
+    journal: Journal,
 }
 
 #[napi]
 impl PayjoinProposalWrapper {
+    /// Serialize the proposal with its session, so it can be resumed after a
+    /// restart with `fromJson`.
+    #[napi]
+    pub fn to_json(&self) -> napi::Result<String> {
+        persist::<PayjoinProposal>(&self.session, &self.journal)
+    }
+
+    /// Restore a proposal serialized by `toJson`. Fails if it was persisted
+    /// in a different state.
+    #[napi(factory)]
+    pub fn from_json(json_str: String) -> napi::Result<Self> {
+        let restored = restore::<PayjoinProposal>(&json_str)?;
+        Ok(Self {
+            inner: restored.inner,
+            session: restored.session,
+            journal: restored.journal,
+        })
+    }
+
     #[napi]
     pub fn utxos_to_be_locked(&self) -> Vec<String> {
         self.inner
@@ -688,15 +1960,16 @@ impl PayjoinProposalWrapper {
 
     #[napi]
     pub fn extract_v2_req(&mut self) -> napi::Result<PayjoinRequest> {
-        let (request, ohttp_ctx) = self.inner.extract_v2_req().map_err(|e| {
-            napi::Error::from_reason(format!("Failed to extract v2 request: {}", e))
-        })?;
+        let (request, ohttp_ctx) = self.inner.extract_v2_req().or_payjoin(
+            ErrorCode::CreateRequestFailed,
+            ErrorStage::Receiver,
+            "Failed to extract v2 request",
+        )?;
 
         Ok(PayjoinRequest::new(
             request.url.to_string(),
             request.body.to_vec(),
             Some(ohttp_ctx),
-            None,
         ))
     }
 
@@ -706,24 +1979,29 @@ impl PayjoinProposalWrapper {
         response: Uint8Array,
         request: &mut PayjoinRequest,
     ) -> napi::Result<&Self> {
-        let ohttp_ctx = request
-            .get_ohttp_ctx()
-            .ok_or_else(|| napi::Error::from_reason("Missing OHTTP context"))?;
+        let ohttp_ctx = request.get_ohttp_ctx().ok_or_else(|| {
+            PayjoinError::new(
+                ErrorCode::MissingContext,
+                ErrorStage::Receiver,
+                "Missing OHTTP context",
+            )
+        })?;
 
         let response_vec = response.as_ref();
 
         match self.inner.process_res(response_vec, ohttp_ctx) {
             Ok(_) => {
-                log_debug("PayjoinProposalWrapper::process_res: Successfully processed response");
+                log::debug!("PayjoinProposalWrapper::process_res: Successfully processed response");
                 Ok(self)
             }
             Err(e) => {
                 let error_msg = format!("Failed to process response: {}", e);
-                log_debug(&format!(
-                    "PayjoinProposalWrapper::process_res: Error: {}",
-                    error_msg
-                ));
-                Err(napi::Error::from_reason(error_msg))
+                log::debug!("PayjoinProposalWrapper::process_res: Error: {}", error_msg);
+                Err(
+                    PayjoinError::new(ErrorCode::InvalidResponse, ErrorStage::Receiver, error_msg)
+                        .with_kind(e.kind())
+                        .into(),
+                )
             }
         }
     }
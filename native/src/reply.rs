@@ -0,0 +1,303 @@
+use crate::error::{ErrorCode, ErrorStage, PayjoinError, ResultExt};
+use crate::request::PayjoinRequest;
+use hpke::aead::ChaCha20Poly1305;
+use hpke::kdf::HkdfSha256;
+use hpke::kem::SecpK256HkdfSha256;
+use hpke::rand_core::OsRng;
+use hpke::{OpModeS, Serializable};
+use napi_derive::napi;
+use payjoin::bitcoin::address::NetworkUnchecked;
+use payjoin::bitcoin::bech32::{self, Hrp, NoChecksum};
+use payjoin::bitcoin::hashes::{sha256, Hash};
+use payjoin::bitcoin::secp256k1::ellswift::ElligatorSwift;
+use payjoin::bitcoin::Address;
+use payjoin::receive::v2::Receiver;
+use payjoin::{HpkeKeyPair, HpkePublicKey, OhttpKeys};
+use serde::Deserialize;
+use std::fmt::Write;
+use std::time::SystemTime;
+use url::Url;
+
+// payjoin 0.22 has no API to reply to a v2 sender with an error, and keeps
+// its HPKE and OHTTP helpers private, so message B and its encapsulation are
+// built here the way payjoin builds them. Cargo.toml pins payjoin exactly so a
+// new version cannot change them underneath.
+
+// Sizes fixed by BIP77 so every message on the wire looks the same.
+const ENCAPSULATED_MESSAGE_BYTES: usize = 8192;
+const PADDED_BHTTP_REQ_BYTES: usize = ENCAPSULATED_MESSAGE_BYTES - (65 + 16 + 7);
+const PADDED_PLAINTEXT_B_LENGTH: usize = 7168 - (64 + 16);
+const INFO_B: &[u8; 8] = b"PjV2MsgB";
+
+/// The versions a `version-unsupported` reply offers: the ones payjoin's
+/// receiver accepts, which it does not export.
+#[cfg(feature = "v2")]
+const SUPPORTED_VERSIONS: [u64; 2] = [1, 2];
+#[cfg(not(feature = "v2"))]
+const SUPPORTED_VERSIONS: [u64; 1] = [1];
+
+/// BIP78 well-known errors a receiver can reply with.
+#[napi(string_enum)]
+#[derive(Debug, PartialEq, Eq)]
+pub enum WellKnownErrorCode {
+    Unavailable,
+    NotEnoughMoney,
+    VersionUnsupported,
+    OriginalPsbtRejected,
+}
+
+impl WellKnownErrorCode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            WellKnownErrorCode::Unavailable => "unavailable",
+            WellKnownErrorCode::NotEnoughMoney => "not-enough-money",
+            WellKnownErrorCode::VersionUnsupported => "version-unsupported",
+            WellKnownErrorCode::OriginalPsbtRejected => "original-psbt-rejected",
+        }
+    }
+
+    fn default_message(&self) -> &'static str {
+        match self {
+            WellKnownErrorCode::Unavailable => "The payjoin endpoint is not available for now.",
+            WellKnownErrorCode::NotEnoughMoney => {
+                "The receiver added some inputs but could not bump the fee of the payjoin proposal."
+            }
+            WellKnownErrorCode::VersionUnsupported => "This version of payjoin is not supported.",
+            WellKnownErrorCode::OriginalPsbtRejected => "The receiver rejected the original PSBT.",
+        }
+    }
+}
+
+/// The parts of a serialized `Receiver` the bindings need beyond payjoin's
+/// API. payjoin keeps the session context private, so it is read back through
+/// serde. Unknown fields are refused so a change to the context fails here
+/// rather than being misread.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SessionSnapshot {
+    context: SessionKeys,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct SessionKeys {
+    #[serde(rename = "address")]
+    _address: Address<NetworkUnchecked>,
+    directory: Url,
+    #[serde(rename = "subdirectory")]
+    _subdirectory: Option<Url>,
+    ohttp_keys: OhttpKeys,
+    ohttp_relay: Url,
+    pub(crate) expiry: SystemTime,
+    pub(crate) s: HpkeKeyPair,
+    e: Option<HpkePublicKey>,
+}
+
+pub(crate) fn session_keys(session: &Receiver) -> napi::Result<SessionKeys> {
+    serde_json::to_value(session)
+        .and_then(serde_json::from_value::<SessionSnapshot>)
+        .map(|snapshot| snapshot.context)
+        .or_payjoin(
+            ErrorCode::SerializationFailed,
+            ErrorStage::Receiver,
+            "Failed to read session keys",
+        )
+}
+
+/// Build the request that delivers a well-known error to the sender of the
+/// session's original PSBT. v2 senders get the error encrypted to their reply
+/// key; v1 senders get it as the plaintext BIP78 JSON body.
+pub(crate) fn extract_error_req(
+    session: &Receiver,
+    code: WellKnownErrorCode,
+    message: Option<String>,
+) -> napi::Result<PayjoinRequest> {
+    let keys = session_keys(session)?;
+
+    let mut body = serde_json::json!({
+        "errorCode": code.as_str(),
+        "message": message.unwrap_or_else(|| code.default_message().to_string()),
+    });
+    if code == WellKnownErrorCode::VersionUnsupported {
+        body["supported"] = serde_json::json!(SUPPORTED_VERSIONS);
+    }
+    let body = body.to_string().into_bytes();
+
+    let (method, target, body) = match &keys.e {
+        Some(sender_key) => {
+            let target = keys.directory.join(&short_id(sender_key)).or_payjoin(
+                ErrorCode::InvalidUrl,
+                ErrorStage::Receiver,
+                "Invalid sender subdirectory",
+            )?;
+            (
+                "POST",
+                target,
+                encrypt_message_b(body, &keys.s, sender_key)?,
+            )
+        }
+        None => {
+            let mut target = session.pj_url();
+            target.set_fragment(None);
+            ("PUT", target, body)
+        }
+    };
+
+    let mut ohttp_keys = keys.ohttp_keys;
+    let (body, ohttp_ctx) = ohttp_encapsulate(&mut ohttp_keys, method, &target, &body)?;
+    Ok(PayjoinRequest::new(
+        keys.ohttp_relay.to_string(),
+        body,
+        Some(ohttp_ctx),
+    ))
+}
+
+/// Give each proposal typestate that can still be rejected an
+/// `extractErrorRequest` method.
+macro_rules! impl_error_reply {
+    ($($wrapper:ident),* $(,)?) => {
+        $(
+            #[napi]
+            impl $wrapper {
+                /// Build the request that tells the sender this proposal was
+                /// rejected with a BIP78 well-known error, so it can broadcast
+                /// the original right away. Post it through the OHTTP relay
+                /// with `PayjoinRequest::post`.
+                #[napi]
+                pub fn extract_error_request(
+                    &self,
+                    code: WellKnownErrorCode,
+                    message: Option<String>,
+                ) -> napi::Result<PayjoinRequest> {
+                    extract_error_req(&self.session, code, message)
+                }
+            }
+        )*
+    };
+}
+
+pub(crate) use impl_error_reply;
+
+/// The directory subdirectory a public key's mailbox lives under.
+pub(crate) fn short_id(pubkey: &HpkePublicKey) -> String {
+    short_hash(&pubkey.to_compressed_bytes())
+}
+
+/// A short, uppercase bech32 digest of `bytes`, as used for mailbox IDs.
+pub(crate) fn short_hash(bytes: &[u8]) -> String {
+    let hash = sha256::Hash::hash(bytes);
+    let hrp = Hrp::parse("ID").expect("valid human readable part");
+    let encoded = bech32::encode_upper::<NoChecksum>(hrp, &hash.as_byte_array()[..8])
+        .expect("bech32 encoding of short ID must succeed");
+    encoded["ID1".len()..].to_string()
+}
+
+/// BIP77 message B: authenticated HPKE from the receiver's session key to the
+/// sender's reply key, padded to a fixed length.
+fn encrypt_message_b(
+    mut plaintext: Vec<u8>,
+    receiver_keypair: &HpkeKeyPair,
+    sender_pk: &HpkePublicKey,
+) -> napi::Result<Vec<u8>> {
+    let hpke_error = |e: hpke::HpkeError| {
+        PayjoinError::new(
+            ErrorCode::ReceiverFailed,
+            ErrorStage::Receiver,
+            format!("Failed to encrypt error reply: {}", e),
+        )
+        .with_kind("HpkeError")
+    };
+
+    if plaintext.len() > PADDED_PLAINTEXT_B_LENGTH {
+        return Err(PayjoinError::new(
+            ErrorCode::InvalidOptions,
+            ErrorStage::Receiver,
+            "Error reply message is too long",
+        )
+        .into());
+    }
+    plaintext.resize(PADDED_PLAINTEXT_B_LENGTH, 0);
+
+    let (encapsulated_key, mut context) =
+        hpke::setup_sender::<ChaCha20Poly1305, HkdfSha256, SecpK256HkdfSha256, _>(
+            &OpModeS::Auth((
+                receiver_keypair.secret_key().0.clone(),
+                receiver_keypair.public_key().0.clone(),
+            )),
+            &sender_pk.0,
+            INFO_B,
+            &mut OsRng,
+        )
+        .map_err(hpke_error)?;
+    let ciphertext = context.seal(&plaintext, &[]).map_err(hpke_error)?;
+
+    let encapsulated_key = payjoin::bitcoin::secp256k1::PublicKey::from_slice(
+        &encapsulated_key.to_bytes(),
+    )
+    .map_err(|e| {
+        PayjoinError::new(
+            ErrorCode::ReceiverFailed,
+            ErrorStage::Receiver,
+            format!("Failed to encrypt error reply: {}", e),
+        )
+    })?;
+    let mut message_b = ElligatorSwift::from_pubkey(encapsulated_key)
+        .to_array()
+        .to_vec();
+    message_b.extend(ciphertext);
+    Ok(message_b)
+}
+
+/// Wrap a request for the directory in binary HTTP and OHTTP, padded to the
+/// size every BIP77 message uses.
+fn ohttp_encapsulate(
+    ohttp_keys: &mut OhttpKeys,
+    method: &str,
+    target: &Url,
+    body: &[u8],
+) -> napi::Result<(Vec<u8>, ohttp::ClientResponse)> {
+    let mut authority = target.host_str().unwrap_or_default().to_string();
+    if let Some(port) = target.port() {
+        write!(authority, ":{}", port).expect("writing to a String cannot fail");
+    }
+    let mut message = bhttp::Message::request(
+        method.as_bytes().to_vec(),
+        target.scheme().as_bytes().to_vec(),
+        authority.into_bytes(),
+        target.path().as_bytes().to_vec(),
+    );
+    message.write_content(body);
+
+    let mut bhttp_req = [0u8; PADDED_BHTTP_REQ_BYTES];
+    message
+        .write_bhttp(bhttp::Mode::KnownLength, &mut bhttp_req.as_mut_slice())
+        .or_payjoin(
+            ErrorCode::ReceiverFailed,
+            ErrorStage::Receiver,
+            "Failed to encode error reply",
+        )?;
+
+    let (encapsulated, ohttp_ctx) = ohttp::ClientRequest::from_config(&mut ohttp_keys.0)
+        .and_then(|request| request.encapsulate(&bhttp_req))
+        .or_payjoin(
+            ErrorCode::InvalidOhttpKeys,
+            ErrorStage::Receiver,
+            "Failed to encapsulate error reply",
+        )?;
+
+    if encapsulated.len() > ENCAPSULATED_MESSAGE_BYTES {
+        return Err(PayjoinError::new(
+            ErrorCode::ReceiverFailed,
+            ErrorStage::Receiver,
+            format!(
+                "Error reply is {} bytes once encapsulated, over the {} bytes of a BIP77 message",
+                encapsulated.len(),
+                ENCAPSULATED_MESSAGE_BYTES
+            ),
+        )
+        .into());
+    }
+    let mut buffer = vec![0u8; ENCAPSULATED_MESSAGE_BYTES];
+    buffer[..encapsulated.len()].copy_from_slice(&encapsulated);
+    Ok((buffer, ohttp_ctx))
+}
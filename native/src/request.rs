@@ -1,17 +1,26 @@
+use crate::cancel::{with_cancellation, CancellationToken};
+use crate::envelope::{open, seal};
+use crate::error::{ErrorCode, ErrorStage, PayjoinError, ResultExt};
 use napi::bindgen_prelude::Uint8Array;
 use napi_derive::napi;
 use ohttp::ClientResponse;
-use payjoin::send::{V1Context, V2GetContext, V2PostContext};
-use std::sync::RwLock;
+use payjoin::send::{Sender, V1Context, V2GetContext, V2PostContext};
+use payjoin::{V1_REQ_CONTENT_TYPE, V2_REQ_CONTENT_TYPE};
+use serde::{Deserialize, Serialize};
+use std::sync::{Arc, RwLock};
 use url::Url;
 
 #[napi]
 pub struct PayjoinRequest {
     url: String,
     body: Vec<u8>,
+    content_type: &'static str,
     context: Option<V1Context>,
     // #[cfg(feature = "v2")]
     v2_context: RwLock<Option<V2PostContext>>,
+    /// The sender a v2 POST was extracted from, so that the polling context
+    /// it leads to can be persisted.
+    sender: Option<Sender>,
     /// The OHTTP client response context needed for v2 protocol processing.
     /// This is stored but not directly accessed as it's handled internally
     /// by the payjoin library during request processing.
@@ -22,53 +31,66 @@ pub struct PayjoinRequest {
 
 #[napi]
 impl PayjoinRequest {
-    pub fn new(
-        url: String,
-        body: Vec<u8>,
-        ohttp_ctx: Option<ClientResponse>,
-        v2_context: Option<V2PostContext>,
-    ) -> Self {
+    pub fn new(url: String, body: Vec<u8>, ohttp_ctx: Option<ClientResponse>) -> Self {
         Self {
             url,
             body,
+            content_type: V2_REQ_CONTENT_TYPE,
             context: None,
-            v2_context: RwLock::new(v2_context),
+            v2_context: RwLock::new(None),
+            sender: None,
             ohttp_ctx,
         }
     }
 
+    pub fn new_v2(url: String, body: Vec<u8>, context: V2PostContext, sender: Sender) -> Self {
+        Self {
+            url,
+            body,
+            content_type: V2_REQ_CONTENT_TYPE,
+            context: None,
+            v2_context: RwLock::new(Some(context)),
+            sender: Some(sender),
+            ohttp_ctx: None,
+        }
+    }
+
+    pub fn new_v1(url: String, body: Vec<u8>, context: V1Context) -> Self {
+        Self {
+            url,
+            body,
+            content_type: V1_REQ_CONTENT_TYPE,
+            context: Some(context),
+            v2_context: RwLock::new(None),
+            sender: None,
+            ohttp_ctx: None,
+        }
+    }
+
     #[napi]
     pub fn url(&self) -> String {
         self.url.clone()
     }
 
+    #[napi]
+    pub fn content_type(&self) -> String {
+        self.content_type.to_string()
+    }
+
     #[napi(ts_return_type = "Uint8Array")]
     pub fn body(&self) -> napi::Result<Uint8Array> {
         Ok(Uint8Array::new(self.body.clone()))
     }
 
     #[napi(ts_return_type = "Promise<Uint8Array>")]
-    pub async fn post(&self) -> napi::Result<Uint8Array> {
-        let client = reqwest::Client::new();
-
-        let response = client
-            .post(&self.url)
-            .header("Content-Type", "message/ohttp-req")
-            .body(self.body.clone())
-            .send()
+    pub async fn post(
+        &self,
+        cancellation_token: Option<&CancellationToken>,
+    ) -> napi::Result<Uint8Array> {
+        let request = post_request(&self.url, self.content_type, self.body.clone());
+        with_cancellation(cancellation_token.cloned(), ErrorStage::Http, request)
             .await
-            .map_err(|e| match e.status() {
-                Some(status_code) => {
-                    napi::Error::from_reason(format!("HTTP request failed: {} {}", status_code, e))
-                }
-                None => napi::Error::from_reason(format!("No HTTP response: {}", e)),
-            })?;
-
-        let bytes = response.bytes().await.map_err(|e| {
-            napi::Error::from_reason(format!("Failed to read response body: {}", e))
-        })?;
-
-        Ok(Uint8Array::new(bytes.to_vec()))
+            .map(Uint8Array::new)
     }
 
     #[napi]
@@ -82,23 +104,84 @@ impl PayjoinRequest {
                 .process_response(&mut response_slice)
                 .map(|psbt| PayjoinResponse::new_v1(psbt.to_string()))
                 .map_err(|e| {
-                    napi::Error::from_reason(format!("Failed to process v1 response: {}", e))
+                    PayjoinError::from_response(
+                        e,
+                        &response_vec,
+                        ErrorStage::Sender,
+                        "Failed to process v1 response",
+                    )
+                    .into()
                 })
-        } else if let Some(context) = self.v2_context.write().unwrap().take() {
+        } else if let (Some(context), Some(sender)) = (self.take_v2_context(), self.sender.clone())
+        {
             context
                 .process_response(&response_vec)
-                .map(|ctx| PayjoinResponse::new_v2(PayjoinV2Context { inner: ctx }))
+                .map(|ctx| PayjoinResponse::new_v2(PayjoinV2Context::polling(ctx, sender)))
                 .map_err(|e| {
-                    napi::Error::from_reason(format!("Failed to process v2 response: {}", e))
+                    PayjoinError::from_response(
+                        e,
+                        &response_vec,
+                        ErrorStage::Sender,
+                        "Failed to process v2 response",
+                    )
+                    .into()
                 })
         } else {
-            Err(napi::Error::from_reason("No context available"))
+            Err(PayjoinError::new(
+                ErrorCode::MissingContext,
+                ErrorStage::Sender,
+                "No context available",
+            )
+            .into())
         }
     }
 
     pub fn get_ohttp_ctx(&mut self) -> Option<ClientResponse> {
         self.ohttp_ctx.take()
     }
+
+    fn take_v2_context(&self) -> Option<V2PostContext> {
+        self.v2_context.write().unwrap().take()
+    }
+}
+
+/// POST a request body and return the raw response bytes.
+pub(crate) async fn post_request(
+    url: &str,
+    content_type: &str,
+    body: Vec<u8>,
+) -> napi::Result<Vec<u8>> {
+    let client = reqwest::Client::new();
+
+    let response = client
+        .post(url)
+        .header("Content-Type", content_type)
+        .body(body)
+        .send()
+        .await
+        .map_err(|e| {
+            let error = match e.status() {
+                Some(status_code) => PayjoinError::new(
+                    ErrorCode::HttpStatus,
+                    ErrorStage::Http,
+                    format!("HTTP request failed: {} {}", status_code, e),
+                ),
+                None => PayjoinError::new(
+                    ErrorCode::NetworkError,
+                    ErrorStage::Http,
+                    format!("No HTTP response: {}", e),
+                ),
+            };
+            error.with_kind("ReqwestError")
+        })?;
+
+    let bytes = response.bytes().await.or_payjoin(
+        ErrorCode::NetworkError,
+        ErrorStage::Http,
+        "Failed to read response body",
+    )?;
+
+    Ok(bytes.to_vec())
 }
 
 #[napi]
@@ -146,31 +229,103 @@ impl PayjoinResponse {
     }
 }
 
+/// The sender's side of a v2 session once the original PSBT was posted.
 // #[cfg(feature = "v2")]
-#[derive(Clone, Debug)]
+#[derive(Clone)]
 #[napi]
 pub struct PayjoinV2Context {
-    inner: V2GetContext,
+    /// `None` for a restored context until the original PSBT has been posted
+    /// again.
+    inner: Arc<RwLock<Option<V2GetContext>>>,
+    sender: Sender,
+}
+
+/// `V2GetContext` cannot be serialized, but everything it holds is derived
+/// from the sender, including the reply key whose mailbox the receiver
+/// answers in.
+#[derive(Serialize, Deserialize)]
+struct PersistedV2Context {
+    sender: Sender,
+}
+
+fn relay_url(ohttp_relay: &str) -> napi::Result<Url> {
+    Url::parse(ohttp_relay).or_payjoin(
+        ErrorCode::InvalidUrl,
+        ErrorStage::Sender,
+        "Invalid relay URL",
+    )
+}
+
+fn not_polling() -> PayjoinError {
+    PayjoinError::new(
+        ErrorCode::MissingContext,
+        ErrorStage::Sender,
+        "Original PSBT has not been posted again, call extractPostRequest first",
+    )
+}
+
+impl PayjoinV2Context {
+    fn polling(inner: V2GetContext, sender: Sender) -> Self {
+        Self {
+            inner: Arc::new(RwLock::new(Some(inner))),
+            sender,
+        }
+    }
 }
 
 // #[cfg(feature = "v2")]
 #[napi]
 impl PayjoinV2Context {
+    /// Whether `extract_request` can poll for the proposal. A restored
+    /// context cannot until the original PSBT has been posted again with
+    /// `extract_post_request`.
+    #[napi]
+    pub fn is_polling(&self) -> bool {
+        self.inner.read().unwrap().is_some()
+    }
+
+    /// Post the original PSBT again, to resume polling a restored context
+    /// through the directory's acknowledgement. The receiver's reply mailbox
+    /// is the same, so a proposal it already sent is still found.
+    #[napi]
+    pub fn extract_post_request(&self, ohttp_relay: String) -> napi::Result<PayjoinRequest> {
+        let relay_url = relay_url(&ohttp_relay)?;
+        self.sender
+            .extract_v2(relay_url)
+            .map(|(request, context)| {
+                PayjoinRequest::new_v2(
+                    request.url.to_string(),
+                    request.body.to_vec(),
+                    context,
+                    self.sender.clone(),
+                )
+            })
+            .or_payjoin(
+                ErrorCode::CreateRequestFailed,
+                ErrorStage::Sender,
+                "Failed to extract v2 request",
+            )
+    }
+
+    /// Poll the receiver's reply mailbox for the proposal.
     #[napi]
     pub fn extract_request(&self, ohttp_relay: String) -> napi::Result<PayjoinRequest> {
-        let relay_url = Url::parse(&ohttp_relay)
-            .map_err(|e| napi::Error::from_reason(format!("Invalid relay URL: {}", e)))?;
+        let relay_url = relay_url(&ohttp_relay)?;
+        let inner = self.inner.read().unwrap().clone().ok_or_else(not_polling)?;
 
-        let (request, ohttp_ctx) = self
-            .inner
-            .extract_req(relay_url)
-            .map_err(|e| napi::Error::from_reason(format!("Failed to extract request: {}", e)))?;
+        let (request, ohttp_ctx) = inner.extract_req(relay_url).or_payjoin(
+            ErrorCode::CreateRequestFailed,
+            ErrorStage::Sender,
+            "Failed to extract request",
+        )?;
 
         Ok(PayjoinRequest {
             url: request.url.to_string(),
             body: request.body.to_vec(),
+            content_type: request.content_type,
             context: None,
             v2_context: RwLock::new(None),
+            sender: None,
             ohttp_ctx: Some(ohttp_ctx),
         })
     }
@@ -181,95 +336,84 @@ impl PayjoinV2Context {
         response: Uint8Array,
         request: &mut PayjoinRequest,
     ) -> napi::Result<Option<String>> {
-        use std::fs::OpenOptions;
-        use std::io::Write;
-
-        // Open log file
-        let mut log_file = OpenOptions::new()
-            .create(true)
-            .append(true)
-            .open("/tmp/payjoin_debug.log")
-            .map_err(|e| napi::Error::from_reason(format!("Failed to open log file: {}", e)))?;
-
-        writeln!(
-            log_file,
+        log::debug!(
             "V2Context.process_response called with response length: {}",
             response.len()
-        )
-        .map_err(|e| napi::Error::from_reason(format!("Failed to write to log: {}", e)))?;
-
-        let response_vec = response.as_ref();
-
-        writeln!(log_file, "Response vector length: {}", response_vec.len())
-            .map_err(|e| napi::Error::from_reason(format!("Failed to write to log: {}", e)))?;
-
-        // Preview the first few bytes of response
-        if !response_vec.is_empty() {
-            let preview_len = std::cmp::min(20, response_vec.len());
-            writeln!(
-                log_file,
-                "First {} bytes of response: {:?}",
-                preview_len,
-                &response_vec[..preview_len]
-            )
-            .map_err(|e| napi::Error::from_reason(format!("Failed to write to log: {}", e)))?;
+        );
+
+        if let Some(context) = request.take_v2_context() {
+            let inner = context.process_response(response.as_ref()).map_err(|e| {
+                PayjoinError::from_response(
+                    e,
+                    response.as_ref(),
+                    ErrorStage::Sender,
+                    "Failed to process v2 response",
+                )
+            })?;
+            log::debug!("Original PSBT posted again, resuming polling");
+            self.inner.write().unwrap().replace(inner);
+            return Ok(None);
         }
 
-        let ohttp_ctx = match request.get_ohttp_ctx() {
-            Some(ctx) => {
-                writeln!(log_file, "OHTTP context found").map_err(|e| {
-                    napi::Error::from_reason(format!("Failed to write to log: {}", e))
-                })?;
-                ctx
-            }
-            None => {
-                writeln!(log_file, "Missing OHTTP context").map_err(|e| {
-                    napi::Error::from_reason(format!("Failed to write to log: {}", e))
-                })?;
-                return Err(napi::Error::from_reason("Missing OHTTP context"));
-            }
-        };
-
-        writeln!(log_file, "About to call inner.process_response()")
-            .map_err(|e| napi::Error::from_reason(format!("Failed to write to log: {}", e)))?;
+        let inner = self.inner.read().unwrap().clone().ok_or_else(not_polling)?;
+        let ohttp_ctx = request.get_ohttp_ctx().ok_or_else(|| {
+            log::debug!("Missing OHTTP context");
+            PayjoinError::new(
+                ErrorCode::MissingContext,
+                ErrorStage::Sender,
+                "Missing OHTTP context",
+            )
+        })?;
 
-        let result = match self.inner.process_response(response_vec, ohttp_ctx) {
+        match inner.process_response(response.as_ref(), ohttp_ctx) {
             Ok(Some(psbt)) => {
-                writeln!(log_file, "Successfully processed PSBT").map_err(|e| {
-                    napi::Error::from_reason(format!("Failed to write to log: {}", e))
-                })?;
-
-                // Log the PSBT for debug purposes
+                log::debug!("Successfully processed PSBT");
                 let psbt_base64 = base64::encode(psbt.serialize());
-                writeln!(
-                    log_file,
-                    "PSBT (first 100 chars): {}",
-                    &psbt_base64[..std::cmp::min(100, psbt_base64.len())]
-                )
-                .map_err(|e| napi::Error::from_reason(format!("Failed to write to log: {}", e)))?;
-
+                log::trace!("Proposal PSBT: {}", psbt_base64);
                 Ok(Some(psbt_base64))
             }
             Ok(None) => {
-                writeln!(log_file, "Received ACCEPTED status, no PSBT").map_err(|e| {
-                    napi::Error::from_reason(format!("Failed to write to log: {}", e))
-                })?;
+                log::debug!("Received ACCEPTED status, no PSBT");
                 Ok(None)
             }
             Err(e) => {
-                writeln!(log_file, "Error processing response: {}", e).map_err(|e| {
-                    napi::Error::from_reason(format!("Failed to write to log: {}", e))
-                })?;
-                Err(napi::Error::from_reason(format!(
-                    "Failed to process response: {}",
-                    e
-                )))
+                log::debug!("Error processing response: {}", e);
+                Err(PayjoinError::from_response(
+                    e,
+                    response.as_ref(),
+                    ErrorStage::Sender,
+                    "Failed to process response",
+                )
+                .into())
             }
-        };
+        }
+    }
 
-        writeln!(log_file, "Completed processing response")
-            .map_err(|e| napi::Error::from_reason(format!("Failed to write to log: {}", e)))?;
+    #[napi]
+    pub fn to_json(&self) -> napi::Result<String> {
+        seal(
+            "PayjoinV2Context",
+            &PersistedV2Context {
+                sender: self.sender.clone(),
+            },
+            "Failed to serialize v2 context",
+        )
+    }
 
-        result
+    /// Restore a context serialized by `to_json`. It cannot poll until the
+    /// original PSBT has been posted again with `extract_post_request`, since
+    /// polling can only resume through the directory's acknowledgement.
+    #[napi(factory)]
+    pub fn from_json(json: String) -> napi::Result<Self> {
+        let persisted: PersistedV2Context = open(
+            "PayjoinV2Context",
+            &json,
+            "Failed to deserialize v2 context",
+        )?;
+
+        Ok(Self {
+            inner: Arc::new(RwLock::new(None)),
+            sender: persisted.sender,
+        })
     }
 }
@@ -1,34 +1,75 @@
-use crate::request::PayjoinRequest;
+use crate::amount::{to_amount, to_fee_rate};
+use crate::cancel::{with_cancellation, CancellationToken};
+use crate::encrypt::{open_encrypted, seal_encrypted, ExportKey, Secret};
+use crate::envelope::{open, seal};
+use crate::error::{ErrorCode, ErrorStage, PayjoinError, ResultExt};
+use crate::poll::{Backoff, PollOptions, DEFAULT_SEND_TIMEOUT};
+use crate::request::{post_request, PayjoinRequest};
+use crate::uri::{endpoint_expiry, PjVersion};
+use napi::bindgen_prelude::BigInt;
 use napi_derive::napi;
 use payjoin::{
-    bitcoin::{psbt::Psbt, Amount, FeeRate},
+    bitcoin::psbt::Psbt,
     send::{Sender, SenderBuilder},
     Uri, UriExt,
 };
 use std::str::FromStr;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 use url::Url;
 
 #[napi]
 pub struct PayjoinSenderBuilder {
     inner: SenderBuilder<'static>,
+    version: PjVersion,
 }
 
 #[napi]
 impl PayjoinSenderBuilder {
     #[napi]
     pub fn from_psbt_and_uri(psbt: String, uri: String) -> napi::Result<Self> {
-        let psbt = Psbt::from_str(&psbt)
-            .map_err(|e| napi::Error::from_reason(format!("Invalid PSBT: {}", e)))?;
+        let psbt = Psbt::from_str(&psbt).or_payjoin(
+            ErrorCode::InvalidPsbt,
+            ErrorStage::SenderBuilder,
+            "Invalid PSBT",
+        )?;
 
         let uri = Uri::from_str(&uri)
-            .map_err(|e| napi::Error::from_reason(format!("Invalid URI: {}", e)))?
+            .or_payjoin_kind(
+                ErrorCode::InvalidUri,
+                ErrorStage::SenderBuilder,
+                "UriParseError",
+                "Invalid URI",
+            )?
             .assume_checked()
             .check_pj_supported()
-            .map_err(|e| napi::Error::from_reason(format!("Invalid Payjoin URI: {}", e)))?;
+            .map_err(|e| {
+                PayjoinError::new(
+                    ErrorCode::UnsupportedUri,
+                    ErrorStage::SenderBuilder,
+                    format!("Invalid Payjoin URI: {}", e),
+                )
+            })?;
+
+        let version = PjVersion::from_endpoint(uri.extras.endpoint());
 
         SenderBuilder::from_psbt_and_uri(psbt, uri)
-            .map(|builder| Self { inner: builder })
-            .map_err(|e| napi::Error::from_reason(format!("Failed to create sender: {}", e)))
+            .map(|builder| Self {
+                inner: builder,
+                version,
+            })
+            .or_payjoin_kind(
+                ErrorCode::InvalidPsbt,
+                ErrorStage::SenderBuilder,
+                "BuildSenderError",
+                "Failed to create sender",
+            )
+    }
+
+    /// Protocol version the sender will use: "v2" when the endpoint carries
+    /// BIP77 parameters, "v1" otherwise.
+    #[napi]
+    pub fn version(&self) -> String {
+        self.version.as_str().to_string()
     }
 
     #[napi]
@@ -42,27 +83,45 @@ impl PayjoinSenderBuilder {
 
     #[napi]
     pub fn build_recommended(&self, min_fee_rate_sat_per_vb: f64) -> napi::Result<PayjoinSender> {
-        let fee_rate = FeeRate::from_sat_per_vb(min_fee_rate_sat_per_vb as u64)
-            .ok_or_else(|| napi::Error::from_reason("Invalid fee rate"))?;
+        let fee_rate = to_fee_rate(
+            min_fee_rate_sat_per_vb,
+            ErrorStage::SenderBuilder,
+            "Invalid fee rate",
+        )?;
 
         self.inner
             .clone()
             .build_recommended(fee_rate)
-            .map(|sender| PayjoinSender { inner: sender })
-            .map_err(|e| napi::Error::from_reason(format!("Failed to build sender: {}", e)))
+            .map(|sender| PayjoinSender {
+                inner: sender,
+                version: self.version,
+            })
+            .or_payjoin_kind(
+                ErrorCode::InvalidPsbt,
+                ErrorStage::SenderBuilder,
+                "BuildSenderError",
+                "Failed to build sender",
+            )
     }
 
     #[napi]
     pub fn build_with_additional_fee(
         &self,
-        max_fee_contribution_sats: f64,
+        max_fee_contribution_sats: BigInt,
         change_index: Option<u32>,
         min_fee_rate_sat_per_vb: f64,
         clamp_fee_contribution: bool,
     ) -> napi::Result<PayjoinSender> {
-        let fee = Amount::from_sat(max_fee_contribution_sats as u64);
-        let fee_rate = FeeRate::from_sat_per_vb(min_fee_rate_sat_per_vb as u64)
-            .ok_or_else(|| napi::Error::from_reason("Invalid fee rate"))?;
+        let fee = to_amount(
+            &max_fee_contribution_sats,
+            ErrorStage::SenderBuilder,
+            "Invalid fee contribution",
+        )?;
+        let fee_rate = to_fee_rate(
+            min_fee_rate_sat_per_vb,
+            ErrorStage::SenderBuilder,
+            "Invalid fee rate",
+        )?;
 
         self.inner
             .clone()
@@ -72,68 +131,292 @@ impl PayjoinSenderBuilder {
                 fee_rate,
                 clamp_fee_contribution,
             )
-            .map(|sender| PayjoinSender { inner: sender })
-            .map_err(|e| napi::Error::from_reason(format!("Failed to build sender: {}", e)))
+            .map(|sender| PayjoinSender {
+                inner: sender,
+                version: self.version,
+            })
+            .or_payjoin_kind(
+                ErrorCode::InvalidPsbt,
+                ErrorStage::SenderBuilder,
+                "BuildSenderError",
+                "Failed to build sender",
+            )
+    }
+}
+
+/// Result of driving a send to completion with `PayjoinSender::send_v2`.
+#[napi(object)]
+pub struct SendOutcome {
+    /// "proposal" when the receiver replied, "timeout" when the polling budget
+    /// ran out and "expired" when the receiver's session expired. In the last
+    /// two cases the original transaction should be broadcast.
+    pub status: String,
+    /// The receiver's proposal PSBT, present when the status is "proposal".
+    pub psbt: Option<String>,
+}
+
+impl SendOutcome {
+    fn proposal(psbt: Psbt) -> Self {
+        Self {
+            status: "proposal".to_string(),
+            psbt: Some(psbt.to_string()),
+        }
+    }
+
+    fn timeout() -> Self {
+        Self {
+            status: "timeout".to_string(),
+            psbt: None,
+        }
+    }
+
+    fn expired() -> Self {
+        Self {
+            status: "expired".to_string(),
+            psbt: None,
+        }
     }
 }
 
 #[napi]
 pub struct PayjoinSender {
     inner: Sender,
+    version: PjVersion,
+}
+
+impl PayjoinSender {
+    pub(crate) fn from_session(inner: Sender) -> Self {
+        let version = PjVersion::from_endpoint(inner.endpoint());
+        Self { inner, version }
+    }
+
+    pub(crate) fn session(&self) -> &Sender {
+        &self.inner
+    }
 }
 
 #[napi]
 impl PayjoinSender {
-    // #[napi]
-    // pub fn extract_v1(&self) -> napi::Result<PayjoinRequest> {
-    //     self.inner
-    //         .extract_v1()
-    //         .map(|(request, context)| PayjoinRequest {
-    //             url: request.url().to_string(),
-    //             body: request.body().to_vec(),
-    //             context: Some(context),
-    //             #[cfg(feature = "v2")]
-    //             v2_context: None,
-    //             #[cfg(feature = "v2")]
-    //             ohttp_ctx: None,
-    //         })
-    //         .map_err(|e| napi::Error::from_reason(format!("Failed to extract v1 request: {}", e)))
-    // }
+    #[napi]
+    pub fn version(&self) -> String {
+        self.version.as_str().to_string()
+    }
+
+    /// Extract the request for whichever protocol version the endpoint supports.
+    /// The OHTTP relay is only required for v2 endpoints.
+    #[napi]
+    pub fn extract_request(&self, ohttp_relay: Option<String>) -> napi::Result<PayjoinRequest> {
+        match self.version {
+            PjVersion::V1 => self.extract_v1(),
+            PjVersion::V2 => {
+                let ohttp_relay = ohttp_relay.ok_or_else(|| {
+                    PayjoinError::new(
+                        ErrorCode::InvalidOptions,
+                        ErrorStage::Sender,
+                        "OHTTP relay is required for v2 endpoints",
+                    )
+                })?;
+                self.extract_v2(ohttp_relay)
+            }
+        }
+    }
+
+    #[napi]
+    pub fn extract_v1(&self) -> napi::Result<PayjoinRequest> {
+        self.inner
+            .extract_v1()
+            .map(|(request, context)| {
+                PayjoinRequest::new_v1(request.url.to_string(), request.body, context)
+            })
+            .or_payjoin(
+                ErrorCode::CreateRequestFailed,
+                ErrorStage::Sender,
+                "Failed to extract v1 request",
+            )
+    }
 
     // #[cfg(feature = "v2")]
-    #[napi(ts_return_type = "Promise<PayjoinRequest>")]
+    #[napi]
     pub fn extract_v2(&self, ohttp_relay: String) -> napi::Result<PayjoinRequest> {
-        let relay_url = Url::parse(&ohttp_relay)
-            .map_err(|e| napi::Error::from_reason(format!("Invalid relay URL: {}", e)))?;
+        let relay_url = Url::parse(&ohttp_relay).or_payjoin(
+            ErrorCode::InvalidUrl,
+            ErrorStage::Sender,
+            "Invalid relay URL",
+        )?;
 
         self.inner
             .extract_v2(relay_url)
             .map(|(request, context)| {
-                PayjoinRequest::new(
+                PayjoinRequest::new_v2(
                     request.url.to_string(),
                     request.body.to_vec(),
-                    None,
-                    Some(context),
+                    context,
+                    self.inner.clone(),
                 )
             })
-            .map_err(|e| napi::Error::from_reason(format!("Failed to extract v2 request: {}", e)))
+            .or_payjoin(
+                ErrorCode::CreateRequestFailed,
+                ErrorStage::Sender,
+                "Failed to extract v2 request",
+            )
+    }
+
+    /// POST the original PSBT to the directory, then poll for the receiver's
+    /// proposal until it arrives, the polling budget runs out or the
+    /// receiver's session expires.
+    #[napi(ts_return_type = "Promise<SendOutcome>")]
+    pub async fn send_v2(
+        &self,
+        ohttp_relay: String,
+        options: Option<PollOptions>,
+        cancellation_token: Option<&CancellationToken>,
+    ) -> napi::Result<SendOutcome> {
+        let relay_url = Url::parse(&ohttp_relay).or_payjoin(
+            ErrorCode::InvalidUrl,
+            ErrorStage::Sender,
+            "Invalid relay URL",
+        )?;
+        let backoff = Backoff::new(options, Some(DEFAULT_SEND_TIMEOUT), ErrorStage::Sender)?;
+
+        with_cancellation(
+            cancellation_token.cloned(),
+            ErrorStage::Sender,
+            self.drive_v2(relay_url, backoff),
+        )
+        .await
+    }
+
+    async fn drive_v2(&self, relay_url: Url, mut backoff: Backoff) -> napi::Result<SendOutcome> {
+        if self.is_expired() {
+            return Ok(SendOutcome::expired());
+        }
+        if let Some(expiry) = endpoint_expiry(self.inner.endpoint()) {
+            backoff.expire_at(UNIX_EPOCH + Duration::from_secs(expiry));
+        }
+
+        let (request, context) = self.inner.extract_v2(relay_url.clone()).or_payjoin(
+            ErrorCode::CreateRequestFailed,
+            ErrorStage::Sender,
+            "Failed to extract v2 request",
+        )?;
+        let response = match backoff
+            .bounded(post_request(
+                request.url.as_str(),
+                request.content_type,
+                request.body,
+            ))
+            .await
+        {
+            Some(response) => response?,
+            None => return Ok(self.out_of_time()),
+        };
+        let context = context.process_response(&response).map_err(|e| {
+            PayjoinError::from_response(
+                e,
+                &response,
+                ErrorStage::Sender,
+                "Failed to process v2 response",
+            )
+        })?;
+
+        loop {
+            if self.is_expired() {
+                return Ok(SendOutcome::expired());
+            }
+
+            let (request, ohttp_ctx) = context.extract_req(relay_url.clone()).or_payjoin(
+                ErrorCode::CreateRequestFailed,
+                ErrorStage::Sender,
+                "Failed to extract request",
+            )?;
+            let response = match backoff
+                .bounded(post_request(
+                    request.url.as_str(),
+                    request.content_type,
+                    request.body,
+                ))
+                .await
+            {
+                Some(response) => response?,
+                None => return Ok(self.out_of_time()),
+            };
+            let proposal = context
+                .process_response(&response, ohttp_ctx)
+                .map_err(|e| {
+                    PayjoinError::from_response(
+                        e,
+                        &response,
+                        ErrorStage::Sender,
+                        "Failed to process response",
+                    )
+                })?;
+
+            if let Some(psbt) = proposal {
+                return Ok(SendOutcome::proposal(psbt));
+            }
+            if backoff.is_timed_out() {
+                return Ok(self.out_of_time());
+            }
+            backoff.wait().await;
+        }
+    }
+
+    /// The outcome once the deadline passed, which is the receiver's session
+    /// expiry when that comes before the polling budget.
+    fn out_of_time(&self) -> SendOutcome {
+        if self.is_expired() {
+            SendOutcome::expired()
+        } else {
+            SendOutcome::timeout()
+        }
+    }
+
+    fn is_expired(&self) -> bool {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default();
+        endpoint_expiry(self.inner.endpoint()).is_some_and(|expiry| now >= expiry)
     }
 
     #[napi]
     pub fn to_json(&self) -> napi::Result<String> {
-        // Serialize the inner Sender to JSON
-        serde_json::to_string(&self.inner)
-            .map_err(|e| napi::Error::from_reason(format!("Failed to serialize sender: {}", e)))
+        seal("PayjoinSender", &self.inner, "Failed to serialize sender")
     }
 
     #[napi]
     pub fn from_json(json: String) -> napi::Result<Self> {
-        // Deserialize from JSON string to Sender
-        let inner: Sender = serde_json::from_str(&json).map_err(|e| {
-            napi::Error::from_reason(format!("Failed to deserialize sender: {}", e))
-        })?;
+        let inner: Sender = open("PayjoinSender", &json, "Failed to deserialize sender")?;
+
+        let version = PjVersion::from_endpoint(inner.endpoint());
 
         // Return a new PayjoinSender instance
-        Ok(PayjoinSender { inner })
+        Ok(PayjoinSender { inner, version })
+    }
+
+    /// Like `to_json`, encrypted under `key`. The sender's reply key never
+    /// leaves the process in plaintext.
+    #[napi]
+    pub fn to_encrypted_json(&self, key: ExportKey) -> napi::Result<String> {
+        seal_encrypted(
+            "PayjoinSender",
+            &self.inner,
+            &Secret::try_from(key)?,
+            "Failed to serialize sender",
+        )
+    }
+
+    /// Restore a sender exported by `to_encrypted_json` with the same key.
+    #[napi(factory)]
+    pub fn from_encrypted_json(json: String, key: ExportKey) -> napi::Result<Self> {
+        let inner: Sender = open_encrypted(
+            "PayjoinSender",
+            &json,
+            &Secret::try_from(key)?,
+            "Failed to deserialize sender",
+        )?;
+        let version = PjVersion::from_endpoint(inner.endpoint());
+
+        Ok(PayjoinSender { inner, version })
     }
 }
@@ -0,0 +1,510 @@
+use crate::amount::to_u64;
+use crate::callback::{call_async, AsyncCallback};
+use crate::encrypt::{open_encrypted, seal_encrypted, ExportKey, Secret};
+use crate::envelope::{open, seal};
+use crate::error::{ErrorCode, ErrorStage, PayjoinError, ResultExt};
+use crate::receive::PayjoinReceiver;
+use crate::reply::{session_keys, short_hash, short_id};
+use crate::send::PayjoinSender;
+use crate::uri::endpoint_expiry;
+use napi::bindgen_prelude::BigInt;
+use napi_derive::napi;
+use payjoin::receive::v2::Receiver;
+use payjoin::send::Sender;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::future::{ready, Future};
+use std::path::PathBuf;
+use std::pin::Pin;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::io::AsyncWriteExt;
+
+/// Which side of a payjoin a stored session belongs to.
+#[napi(string_enum)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SessionKind {
+    Receiver,
+    Sender,
+}
+
+/// A session as listed by `SessionStore::list`.
+#[napi(object)]
+pub struct SessionInfo {
+    pub id: String,
+    pub kind: SessionKind,
+    /// Unix timestamp after which the session can no longer complete.
+    pub expires_at: Option<BigInt>,
+}
+
+/// A session as handed to and returned by a JavaScript backend. `json` is
+/// what the session's `toJson` produced, or its `toEncryptedJson` when the
+/// store was given a key.
+#[napi(object)]
+pub struct StoredSession {
+    pub id: String,
+    pub kind: SessionKind,
+    pub expires_at: Option<BigInt>,
+    pub json: String,
+}
+
+/// A backend implemented in JavaScript, e.g. on top of an existing database.
+#[napi(object, object_to_js = false)]
+pub struct SessionBackend {
+    #[napi(ts_type = "() => Promise<StoredSession[]>")]
+    pub list: AsyncCallback<(), Vec<StoredSession>>,
+    #[napi(ts_type = "(id: string) => Promise<StoredSession | null | undefined>")]
+    pub load: AsyncCallback<String, Option<StoredSession>>,
+    #[napi(ts_type = "(session: StoredSession) => Promise<void>")]
+    pub save: AsyncCallback<StoredSession, ()>,
+    /// Resolves with whether a session was deleted.
+    #[napi(ts_type = "(id: string) => Promise<boolean>")]
+    pub delete: AsyncCallback<String, bool>,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Record {
+    id: String,
+    kind: SessionKind,
+    expires_at: Option<u64>,
+    json: String,
+}
+
+impl Record {
+    fn info(&self) -> SessionInfo {
+        SessionInfo {
+            id: self.id.clone(),
+            kind: self.kind,
+            expires_at: self.expires_at.map(BigInt::from),
+        }
+    }
+
+    fn is_expired(&self, now: u64) -> bool {
+        self.expires_at.is_some_and(|expiry| expiry <= now)
+    }
+}
+
+impl From<Record> for StoredSession {
+    fn from(record: Record) -> Self {
+        Self {
+            id: record.id,
+            kind: record.kind,
+            expires_at: record.expires_at.map(BigInt::from),
+            json: record.json,
+        }
+    }
+}
+
+impl TryFrom<StoredSession> for Record {
+    type Error = napi::Error;
+
+    fn try_from(session: StoredSession) -> napi::Result<Self> {
+        let expires_at = session
+            .expires_at
+            .map(|expiry| {
+                to_u64(
+                    &expiry,
+                    ErrorCode::SerializationFailed,
+                    ErrorStage::Persistence,
+                    "Invalid stored session expiry",
+                )
+            })
+            .transpose()?;
+        Ok(Self {
+            id: session.id,
+            kind: session.kind,
+            expires_at,
+            json: session.json,
+        })
+    }
+}
+
+type BoxFuture<'a, T> = Pin<Box<dyn Future<Output = napi::Result<T>> + Send + 'a>>;
+
+/// Where a `SessionStore` keeps its records.
+trait Backend: Send + Sync {
+    fn list(&self) -> BoxFuture<'_, Vec<Record>>;
+    fn load<'a>(&'a self, id: &'a str) -> BoxFuture<'a, Option<Record>>;
+    fn save(&self, record: Record) -> BoxFuture<'_, ()>;
+    fn delete<'a>(&'a self, id: &'a str) -> BoxFuture<'a, bool>;
+}
+
+#[derive(Default)]
+struct MemoryBackend {
+    records: Mutex<HashMap<String, Record>>,
+}
+
+impl Backend for MemoryBackend {
+    fn list(&self) -> BoxFuture<'_, Vec<Record>> {
+        let records = self.records.lock().unwrap().values().cloned().collect();
+        Box::pin(ready(Ok(records)))
+    }
+
+    fn load<'a>(&'a self, id: &'a str) -> BoxFuture<'a, Option<Record>> {
+        let record = self.records.lock().unwrap().get(id).cloned();
+        Box::pin(ready(Ok(record)))
+    }
+
+    fn save(&self, record: Record) -> BoxFuture<'_, ()> {
+        self.records
+            .lock()
+            .unwrap()
+            .insert(record.id.clone(), record);
+        Box::pin(ready(Ok(())))
+    }
+
+    fn delete<'a>(&'a self, id: &'a str) -> BoxFuture<'a, bool> {
+        let deleted = self.records.lock().unwrap().remove(id).is_some();
+        Box::pin(ready(Ok(deleted)))
+    }
+}
+
+/// One JSON file per session in a directory.
+struct FileBackend {
+    directory: PathBuf,
+}
+
+impl FileBackend {
+    fn path(&self, id: &str) -> napi::Result<PathBuf> {
+        // IDs become file names, so anything that could leave the directory
+        // is refused.
+        if id.is_empty() || !id.chars().all(|c| c.is_ascii_alphanumeric()) {
+            return Err(PayjoinError::new(
+                ErrorCode::InvalidOptions,
+                ErrorStage::Persistence,
+                format!("Invalid session id: {:?}", id),
+            )
+            .into());
+        }
+        Ok(self.directory.join(format!("{}.json", id)))
+    }
+}
+
+fn parse_record(bytes: &[u8]) -> napi::Result<Record> {
+    serde_json::from_slice(bytes).or_payjoin(
+        ErrorCode::SerializationFailed,
+        ErrorStage::Persistence,
+        "Failed to read stored session",
+    )
+}
+
+impl Backend for FileBackend {
+    fn list(&self) -> BoxFuture<'_, Vec<Record>> {
+        Box::pin(async move {
+            let mut records = Vec::new();
+            let mut entries = tokio::fs::read_dir(&self.directory).await.or_payjoin(
+                ErrorCode::SerializationFailed,
+                ErrorStage::Persistence,
+                "Failed to list sessions",
+            )?;
+            while let Some(entry) = entries.next_entry().await.or_payjoin(
+                ErrorCode::SerializationFailed,
+                ErrorStage::Persistence,
+                "Failed to list sessions",
+            )? {
+                let path = entry.path();
+                if path.extension().is_some_and(|ext| ext == "json") {
+                    let bytes = tokio::fs::read(&path).await.or_payjoin(
+                        ErrorCode::SerializationFailed,
+                        ErrorStage::Persistence,
+                        "Failed to read stored session",
+                    )?;
+                    records.push(parse_record(&bytes)?);
+                }
+            }
+            Ok(records)
+        })
+    }
+
+    fn load<'a>(&'a self, id: &'a str) -> BoxFuture<'a, Option<Record>> {
+        Box::pin(async move {
+            match tokio::fs::read(self.path(id)?).await {
+                Ok(bytes) => parse_record(&bytes).map(Some),
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+                Err(e) => Err(e).or_payjoin(
+                    ErrorCode::SerializationFailed,
+                    ErrorStage::Persistence,
+                    "Failed to read stored session",
+                ),
+            }
+        })
+    }
+
+    fn save(&self, record: Record) -> BoxFuture<'_, ()> {
+        Box::pin(async move {
+            let path = self.path(&record.id)?;
+            let json = serde_json::to_vec(&record).or_payjoin(
+                ErrorCode::SerializationFailed,
+                ErrorStage::Persistence,
+                "Failed to store session",
+            )?;
+
+            // Write then rename, so a crash never leaves a truncated session.
+            // Sessions hold their secrets, so only the owner may read them.
+            let partial = path.with_extension("json.partial");
+            let mut options = tokio::fs::OpenOptions::new();
+            options.write(true).create(true).truncate(true);
+            #[cfg(unix)]
+            options.mode(0o600);
+            let mut file = options.open(&partial).await.or_payjoin(
+                ErrorCode::SerializationFailed,
+                ErrorStage::Persistence,
+                "Failed to store session",
+            )?;
+            file.write_all(&json).await.or_payjoin(
+                ErrorCode::SerializationFailed,
+                ErrorStage::Persistence,
+                "Failed to store session",
+            )?;
+            tokio::fs::rename(&partial, &path).await.or_payjoin(
+                ErrorCode::SerializationFailed,
+                ErrorStage::Persistence,
+                "Failed to store session",
+            )
+        })
+    }
+
+    fn delete<'a>(&'a self, id: &'a str) -> BoxFuture<'a, bool> {
+        Box::pin(async move {
+            match tokio::fs::remove_file(self.path(id)?).await {
+                Ok(()) => Ok(true),
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(false),
+                Err(e) => Err(e).or_payjoin(
+                    ErrorCode::SerializationFailed,
+                    ErrorStage::Persistence,
+                    "Failed to delete stored session",
+                ),
+            }
+        })
+    }
+}
+
+impl Backend for SessionBackend {
+    fn list(&self) -> BoxFuture<'_, Vec<Record>> {
+        Box::pin(async move {
+            let sessions = call_async(&self.list, (), "Failed to list sessions").await?;
+            sessions.into_iter().map(Record::try_from).collect()
+        })
+    }
+
+    fn load<'a>(&'a self, id: &'a str) -> BoxFuture<'a, Option<Record>> {
+        Box::pin(async move {
+            let session =
+                call_async(&self.load, id.to_string(), "Failed to load stored session").await?;
+            session.map(Record::try_from).transpose()
+        })
+    }
+
+    fn save(&self, record: Record) -> BoxFuture<'_, ()> {
+        Box::pin(call_async(
+            &self.save,
+            record.into(),
+            "Failed to store session",
+        ))
+    }
+
+    fn delete<'a>(&'a self, id: &'a str) -> BoxFuture<'a, bool> {
+        Box::pin(call_async(
+            &self.delete,
+            id.to_string(),
+            "Failed to delete stored session",
+        ))
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+/// payjoin keeps the sender's reply key private, so it is read back through
+/// serde like the receiver's session keys.
+#[derive(Deserialize)]
+struct SenderKeys {
+    reply_key: serde_json::Value,
+}
+
+/// A session's `to_json`, or its `to_encrypted_json` under `key`.
+fn seal_session<T: Serialize>(
+    state: &str,
+    session: &T,
+    key: Option<&Secret>,
+    context: &str,
+) -> napi::Result<String> {
+    match key {
+        Some(key) => seal_encrypted(state, session, key, context),
+        None => seal(state, session, context),
+    }
+}
+
+/// Read a session stored by `seal_session` with the same `key`.
+fn open_session<T: DeserializeOwned>(
+    state: &str,
+    json: &str,
+    key: Option<&Secret>,
+    context: &str,
+) -> napi::Result<T> {
+    match key {
+        Some(key) => open_encrypted(state, json, key, context),
+        None => open(state, json, context),
+    }
+}
+
+/// A receiver is identified by its mailbox on the directory.
+fn receiver_record(receiver: &PayjoinReceiver, key: Option<&Secret>) -> napi::Result<Record> {
+    let keys = session_keys(receiver.session())?;
+    Ok(Record {
+        id: short_id(keys.s.public_key()),
+        kind: SessionKind::Receiver,
+        expires_at: keys
+            .expiry
+            .duration_since(UNIX_EPOCH)
+            .ok()
+            .map(|d| d.as_secs()),
+        json: seal_session(
+            "PayjoinReceiver",
+            receiver.session(),
+            key,
+            "Failed to serialize receiver",
+        )?,
+    })
+}
+
+/// A sender is identified by a digest of its reply key, which is unique to
+/// the session and never changes while it is resumed.
+fn sender_record(sender: &PayjoinSender, key: Option<&Secret>) -> napi::Result<Record> {
+    let session: &Sender = sender.session();
+    let keys: SenderKeys = serde_json::to_value(session)
+        .and_then(serde_json::from_value)
+        .or_payjoin(
+            ErrorCode::SerializationFailed,
+            ErrorStage::Persistence,
+            "Failed to read sender keys",
+        )?;
+
+    Ok(Record {
+        id: short_hash(keys.reply_key.to_string().as_bytes()),
+        kind: SessionKind::Sender,
+        expires_at: endpoint_expiry(session.endpoint()),
+        json: seal_session("PayjoinSender", session, key, "Failed to serialize sender")?,
+    })
+}
+
+/// Persists receiver and sender sessions under stable IDs, so they can be
+/// resumed after a restart. Given a key, a store keeps each session as its
+/// encrypted export, so no backend ever sees its secrets in plaintext.
+#[napi]
+pub struct SessionStore {
+    backend: Box<dyn Backend>,
+    key: Option<Secret>,
+}
+
+#[napi]
+impl SessionStore {
+    fn new(backend: Box<dyn Backend>, key: Option<ExportKey>) -> napi::Result<Self> {
+        Ok(Self {
+            backend,
+            key: key.map(Secret::try_from).transpose()?,
+        })
+    }
+
+    /// Keep sessions in this process only, e.g. for tests.
+    #[napi(factory)]
+    pub fn memory(key: Option<ExportKey>) -> napi::Result<Self> {
+        Self::new(Box::<MemoryBackend>::default(), key)
+    }
+
+    /// Keep each session as a JSON file in `directory`, which must exist.
+    /// Files are created readable by their owner only.
+    #[napi(factory)]
+    pub fn file(directory: String, key: Option<ExportKey>) -> napi::Result<Self> {
+        Self::new(
+            Box::new(FileBackend {
+                directory: PathBuf::from(directory),
+            }),
+            key,
+        )
+    }
+
+    /// Keep sessions wherever `backend` puts them.
+    #[napi(factory)]
+    pub fn custom(backend: SessionBackend, key: Option<ExportKey>) -> napi::Result<Self> {
+        Self::new(Box::new(backend), key)
+    }
+
+    /// Store `receiver`, replacing any earlier save, and return its ID.
+    #[napi]
+    pub async fn save_receiver(&self, receiver: &PayjoinReceiver) -> napi::Result<String> {
+        let record = receiver_record(receiver, self.key.as_ref())?;
+        let id = record.id.clone();
+        self.backend.save(record).await?;
+        Ok(id)
+    }
+
+    /// Store `sender`, replacing any earlier save, and return its ID.
+    #[napi]
+    pub async fn save_sender(&self, sender: &PayjoinSender) -> napi::Result<String> {
+        let record = sender_record(sender, self.key.as_ref())?;
+        let id = record.id.clone();
+        self.backend.save(record).await?;
+        Ok(id)
+    }
+
+    #[napi]
+    pub async fn load_receiver(&self, id: String) -> napi::Result<Option<PayjoinReceiver>> {
+        let Some(record) = self.backend.load(&id).await? else {
+            return Ok(None);
+        };
+        let session: Receiver = open_session(
+            "PayjoinReceiver",
+            &record.json,
+            self.key.as_ref(),
+            "Failed to deserialize receiver",
+        )?;
+        Ok(Some(PayjoinReceiver::from_session(session)))
+    }
+
+    #[napi]
+    pub async fn load_sender(&self, id: String) -> napi::Result<Option<PayjoinSender>> {
+        let Some(record) = self.backend.load(&id).await? else {
+            return Ok(None);
+        };
+        let session: Sender = open_session(
+            "PayjoinSender",
+            &record.json,
+            self.key.as_ref(),
+            "Failed to deserialize sender",
+        )?;
+        Ok(Some(PayjoinSender::from_session(session)))
+    }
+
+    #[napi]
+    pub async fn list(&self) -> napi::Result<Vec<SessionInfo>> {
+        let records = self.backend.list().await?;
+        Ok(records.iter().map(Record::info).collect())
+    }
+
+    /// Resolves with whether a session was deleted.
+    #[napi]
+    pub async fn delete(&self, id: String) -> napi::Result<bool> {
+        self.backend.delete(&id).await
+    }
+
+    /// Delete every session whose expiry has passed and resolve with their
+    /// IDs.
+    #[napi]
+    pub async fn sweep_expired(&self) -> napi::Result<Vec<String>> {
+        let now = now();
+        let mut swept = Vec::new();
+        for record in self.backend.list().await? {
+            if record.is_expired(now) && self.backend.delete(&record.id).await? {
+                swept.push(record.id);
+            }
+        }
+        Ok(swept)
+    }
+}
@@ -1,10 +1,13 @@
 #![deny(clippy::all)]
 
+use crate::amount::{from_amount, to_amount};
+use crate::error::{ErrorCode, ErrorStage, PayjoinError, ResultExt};
+use napi::bindgen_prelude::BigInt;
 use napi_derive::napi;
 use payjoin::bitcoin::address::{NetworkChecked, NetworkUnchecked};
 use payjoin::bitcoin::bech32;
 use payjoin::bitcoin::consensus::Decodable;
-use payjoin::bitcoin::{Address, Amount};
+use payjoin::bitcoin::Address;
 use payjoin::{PjUri, PjUriBuilder, Uri, UriExt};
 use std::str::FromStr;
 use url::Url;
@@ -22,11 +25,17 @@ pub struct PayjoinUriBuilder {
 impl PayjoinUriBuilder {
     #[napi(constructor)]
     pub fn new(address: String, endpoint: String) -> napi::Result<Self> {
-        let bitcoin_address = Address::from_str(&address)
-            .map_err(|e| napi::Error::from_reason(format!("Invalid address: {}", e)))?;
-
-        let endpoint_url = url::Url::parse(&endpoint)
-            .map_err(|e| napi::Error::from_reason(format!("Invalid endpoint URL: {}", e)))?;
+        let bitcoin_address = Address::from_str(&address).or_payjoin(
+            ErrorCode::InvalidAddress,
+            ErrorStage::Uri,
+            "Invalid address",
+        )?;
+
+        let endpoint_url = url::Url::parse(&endpoint).or_payjoin(
+            ErrorCode::InvalidUrl,
+            ErrorStage::Uri,
+            "Invalid endpoint URL",
+        )?;
 
         Ok(Self {
             inner: PjUriBuilder::new(
@@ -40,12 +49,10 @@ impl PayjoinUriBuilder {
     }
 
     #[napi]
-    pub fn amount(&self, amount_sat: f64) -> napi::Result<Self> {
+    pub fn amount(&self, amount_sat: BigInt) -> napi::Result<Self> {
+        let amount = to_amount(&amount_sat, ErrorStage::Uri, "Invalid amount")?;
         Ok(Self {
-            inner: self
-                .inner
-                .clone()
-                .amount(Amount::from_sat(amount_sat as u64)),
+            inner: self.inner.clone().amount(amount),
         })
     }
 
@@ -97,9 +104,12 @@ impl BtcUri {
     pub fn try_from(bip21: String) -> napi::Result<Self> {
         Uri::try_from(bip21)
             .map(|uri| Self { inner: uri })
-            .map_err(|e| {
-                napi::Error::from_reason(format!("Failed to create URI from BIP21: {}", e))
-            })
+            .or_payjoin_kind(
+                ErrorCode::InvalidUri,
+                ErrorStage::Uri,
+                "UriParseError",
+                "Failed to create URI from BIP21",
+            )
     }
 
     #[napi]
@@ -123,7 +133,14 @@ impl CheckedBtcUri {
         checked_uri
             .check_pj_supported()
             .map(|uri| PayjoinUri { inner: uri })
-            .map_err(|_| napi::Error::from_reason("URI does not support Payjoin"))
+            .map_err(|_| {
+                PayjoinError::new(
+                    ErrorCode::UnsupportedUri,
+                    ErrorStage::Uri,
+                    "URI does not support Payjoin",
+                )
+                .into()
+            })
     }
 }
 
@@ -143,32 +160,13 @@ impl PayjoinUri {
 
     #[napi]
     pub fn exp(&self) -> Option<u64> {
-        let endpoint = self.inner.extras.endpoint();
-
-        let value = get_param(endpoint, "EX1", |v| Some(v.to_owned()))?;
-
-        let hrp_string =
-            bech32::primitives::decode::CheckedHrpstring::new::<bech32::NoChecksum>(&value)
-                .map_err(|_| napi::Error::from_reason("Invalid EX1 value"))
-                .ok()?;
-
-        let hrp = hrp_string.hrp();
-        let bytes = hrp_string.byte_iter().collect::<Vec<u8>>();
-
-        let ex_hrp: bech32::Hrp = bech32::Hrp::parse("EX").unwrap();
-        if hrp != ex_hrp {
-            return None;
-        }
-
-        match u32::consensus_decode(&mut &bytes[..]) {
-            Ok(timestamp) => Some(timestamp as u64),
-            Err(_) => None,
-        }
+        endpoint_expiry(self.inner.extras.endpoint())
     }
 
+    /// The requested amount, in satoshis.
     #[napi]
-    pub fn amount(&self) -> Option<f64> {
-        self.inner.amount.map(|amt| amt.to_sat() as f64)
+    pub fn amount(&self) -> Option<BigInt> {
+        self.inner.amount.map(from_amount)
     }
 
     #[napi]
@@ -184,12 +182,61 @@ pub struct PayjoinUrl {
 
 #[napi]
 impl PayjoinUrl {
+    #[allow(clippy::inherent_to_string)]
     #[napi]
     pub fn to_string(&self) -> String {
         self.inner.to_string()
     }
 }
 
+/// Unix timestamp encoded in the `EX1` fragment parameter of a BIP77 endpoint.
+pub(crate) fn endpoint_expiry(endpoint: &Url) -> Option<u64> {
+    let value = get_param(endpoint, "EX1", |v| Some(v.to_owned()))?;
+
+    let hrp_string =
+        bech32::primitives::decode::CheckedHrpstring::new::<bech32::NoChecksum>(&value).ok()?;
+
+    let hrp = hrp_string.hrp();
+    let bytes = hrp_string.byte_iter().collect::<Vec<u8>>();
+
+    let ex_hrp: bech32::Hrp = bech32::Hrp::parse("EX").unwrap();
+    if hrp != ex_hrp {
+        return None;
+    }
+
+    match u32::consensus_decode(&mut &bytes[..]) {
+        Ok(timestamp) => Some(timestamp as u64),
+        Err(_) => None,
+    }
+}
+
+/// Payjoin protocol version a `pj=` endpoint can be reached with.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum PjVersion {
+    V1,
+    V2,
+}
+
+impl PjVersion {
+    /// BIP77 endpoints carry the receiver key (`RK1`) and OHTTP keys (`OH1`)
+    /// in their fragment; anything else is a plain BIP78 endpoint.
+    pub(crate) fn from_endpoint(endpoint: &Url) -> Self {
+        let has_param = |prefix| get_param(endpoint, prefix, |_| Some(())).is_some();
+        if has_param("RK1") && has_param("OH1") {
+            PjVersion::V2
+        } else {
+            PjVersion::V1
+        }
+    }
+
+    pub(crate) fn as_str(&self) -> &'static str {
+        match self {
+            PjVersion::V1 => "v1",
+            PjVersion::V2 => "v2",
+        }
+    }
+}
+
 fn get_param<F, T>(url: &Url, prefix: &str, parse: F) -> Option<T>
 where
     F: Fn(&str) -> Option<T>,
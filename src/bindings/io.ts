@@ -1,5 +1,6 @@
 import { IPayjoinOhttpKeys } from '../types';
 import native from '../native';
+import { withCancellation, toPayjoinError } from '../utils';
 
 export class PayjoinOhttpKeys implements IPayjoinOhttpKeys {
     constructor(private readonly internal: any) {}
@@ -9,19 +10,22 @@ export class PayjoinOhttpKeys implements IPayjoinOhttpKeys {
             const keys = await native.PayjoinOhttpKeys.fromBytes(bytes);
             return new PayjoinOhttpKeys(keys);
         } catch (error) {
-            throw new Error(`Failed to decode OHTTP keys: ${error}`);
+            throw toPayjoinError(error, 'Failed to decode OHTTP keys');
         }
     }
 
     static async fetch(
         ohttpRelay: string,
-        payjoinDirectory: string
+        payjoinDirectory: string,
+        signal?: AbortSignal
     ): Promise<PayjoinOhttpKeys> {
         try {
-            const keys = await native.PayjoinOhttpKeys.fetch(ohttpRelay, payjoinDirectory);
+            const keys = await withCancellation(signal, token =>
+                native.PayjoinOhttpKeys.fetch(ohttpRelay, payjoinDirectory, token)
+            );
             return new PayjoinOhttpKeys(keys);
         } catch (error) {
-            throw new Error(`Failed to fetch OHTTP keys: ${error}`);
+            throw toPayjoinError(error, 'Failed to fetch OHTTP keys');
         }
     }
 
@@ -29,7 +33,7 @@ export class PayjoinOhttpKeys implements IPayjoinOhttpKeys {
         try {
             return await this.internal.toBytes();
         } catch (error) {
-            throw new Error(`Failed to encode OHTTP keys: ${error}`);
+            throw toPayjoinError(error, 'Failed to encode OHTTP keys');
         }
     }
 }
@@ -0,0 +1,27 @@
+import { ILogRecord, LogLevel, LogRedaction } from '../types';
+import native from '../native';
+
+/**
+ * Forward native diagnostics at `level` and above (default `Info`) to
+ * `callback`. Nothing is logged until a callback is installed, and the native
+ * module never writes log files itself. Sensitive values are redacted unless
+ * `setLogRedaction('Full')` was called.
+ */
+export function setLogger(callback: (record: ILogRecord) => void, level?: LogLevel): void {
+  native.setLogger(callback, level);
+}
+
+/** Choose whether sensitive values are redacted from log records. */
+export function setLogRedaction(redaction: LogRedaction): void {
+  native.setLogRedaction(redaction);
+}
+
+/** Change the verbosity of the installed logger. */
+export function setLogLevel(level: LogLevel): void {
+  native.setLogLevel(level);
+}
+
+/** Stop forwarding native diagnostics. */
+export function clearLogger(): void {
+  native.clearLogger();
+}
@@ -0,0 +1,60 @@
+import { IReceiverEvent, IReceiverManagerOptions } from '../types';
+import { PayjoinReceiver, UncheckedProposal } from './receive';
+import native from '../native';
+import { withCancellation, toPayjoinError } from '../utils';
+
+/**
+ * Owns many receiver sessions, e.g. one per invoice, and polls them all
+ * concurrently on the native runtime while `run` is pending. Expired sessions
+ * are retired automatically.
+ */
+export class ReceiverManager {
+  private readonly internal: any;
+
+  constructor(options?: IReceiverManagerOptions) {
+    try {
+      this.internal = new native.ReceiverManager(options);
+    } catch (error) {
+      throw toPayjoinError(error, 'Failed to create ReceiverManager');
+    }
+  }
+
+  /** Start managing `receiver` and return its ID, its mailbox on the directory. */
+  add(receiver: PayjoinReceiver): string {
+    try {
+      return this.internal.add(receiver.nativeHandle);
+    } catch (error) {
+      throw toPayjoinError(error, 'Failed to add receiver');
+    }
+  }
+
+  /** Stop managing a session. Returns whether it was managed. */
+  remove(id: string): boolean {
+    return this.internal.remove(id);
+  }
+
+  sessionIds(): string[] {
+    return this.internal.sessionIds();
+  }
+
+  /**
+   * Poll every session until `signal` aborts, reporting proposals, expiries
+   * and failed polls to `onEvent`. Resolves once aborted.
+   */
+  async run(onEvent: (event: IReceiverEvent) => void, signal?: AbortSignal): Promise<void> {
+    try {
+      await withCancellation(signal, token =>
+        this.internal.run((event: any) => {
+          onEvent({
+            kind: event.kind,
+            sessionId: event.sessionId,
+            proposal: event.proposal ? new UncheckedProposal(event.proposal) : undefined,
+            error: event.error ? toPayjoinError(event.error, 'Failed to poll session') : undefined,
+          });
+        }, token)
+      );
+    } catch (error) {
+      throw toPayjoinError(error, 'Failed to run receiver manager');
+    }
+  }
+}
@@ -10,11 +10,55 @@ import {
   IPayjoinProposal,
   IInputPairRequest,
   IReplacementOutput,
+  IPollOptions,
+  IExportKey,
+  IInputContext,
+  IOutputContext,
+  ITxContext,
+  WellKnownErrorCode,
+  SelectionFallback,
+  IContributionOutcome,
 } from '../types';
 import { PayjoinRequest } from './request';
 import { PayjoinOhttpKeys } from './io';
 import native from '../native';
 import { UriBuilder } from './uri';
+import { withCancellation, toPayjoinError } from '../utils';
+
+/**
+ * Check that `input` maps to a valid PSBT input and can be contributed,
+ * before any proposal needs it.
+ */
+export function validateInputPair(input: IInputPairRequest): void {
+  try {
+    native.validateInputPair(toNativeInput(input));
+  } catch (error) {
+    throw toPayjoinError(error, 'Invalid input');
+  }
+}
+
+/**
+ * The native module takes byte fields as arrays of numbers, so the
+ * `Uint8Array`s of an input are copied into plain arrays.
+ */
+function toNativeInput(input: IInputPairRequest): unknown {
+  return toNativeBytes(input);
+}
+
+function toNativeBytes(value: unknown): unknown {
+  if (value instanceof Uint8Array) {
+    return Array.from(value);
+  }
+  if (Array.isArray(value)) {
+    return value.map(toNativeBytes);
+  }
+  if (value !== null && typeof value === 'object') {
+    return Object.fromEntries(
+      Object.entries(value).map(([key, field]) => [key, toNativeBytes(field)])
+    );
+  }
+  return value;
+}
 
 export class PayjoinReceiver implements IPayjoinReceiver {
   private readonly internal: any;
@@ -35,15 +79,19 @@ export class PayjoinReceiver implements IPayjoinReceiver {
         expirySeconds
       );
     } catch (error) {
-      throw new Error(`Failed to create PayjoinReceiver: ${error}`);
+      throw toPayjoinError(error, 'Failed to create PayjoinReceiver');
     }
   }
 
+  get nativeHandle() {
+    return this.internal;
+  }
+
   toJson(): string {
     try {
       return this.internal.toJson();
     } catch (error) {
-      throw new Error(`Failed to serialize receiver: ${error}`);
+      throw toPayjoinError(error, 'Failed to serialize receiver');
     }
   }
 
@@ -55,7 +103,31 @@ export class PayjoinReceiver implements IPayjoinReceiver {
 
       return receiver;
     } catch (error) {
-      throw new Error(`Failed to deserialize receiver: ${error}`);
+      throw toPayjoinError(error, 'Failed to deserialize receiver');
+    }
+  }
+
+  /**
+   * Like `toJson`, encrypted with XChaCha20-Poly1305 so the session secret
+   * can be stored in a shared database.
+   */
+  toEncryptedJson(key: IExportKey): string {
+    try {
+      return this.internal.toEncryptedJson(key);
+    } catch (error) {
+      throw toPayjoinError(error, 'Failed to serialize receiver');
+    }
+  }
+
+  static fromEncryptedJson(json: string, key: IExportKey): PayjoinReceiver {
+    try {
+      const internal = native.PayjoinReceiver.fromEncryptedJson(json, key);
+      const receiver = Object.create(PayjoinReceiver.prototype);
+      receiver.internal = internal;
+
+      return receiver;
+    } catch (error) {
+      throw toPayjoinError(error, 'Failed to deserialize receiver');
     }
   }
 
@@ -73,7 +145,7 @@ export class PayjoinReceiver implements IPayjoinReceiver {
       const request = this.internal.extractRequest();
       return new PayjoinRequest(request);
     } catch (error) {
-      throw new Error(`Failed to extract request: ${error}`);
+      throw toPayjoinError(error, 'Failed to extract request');
     }
   }
 
@@ -86,7 +158,24 @@ export class PayjoinReceiver implements IPayjoinReceiver {
 
       return result ? new UncheckedProposal(result) : null;
     } catch (error) {
-      throw new Error(`Failed to process response: ${error}`);
+      throw toPayjoinError(error, 'Failed to process response');
+    }
+  }
+
+  async pollForProposal(
+    options?: IPollOptions,
+    signal?: AbortSignal
+  ): Promise<UncheckedProposal | null> {
+    try {
+      const result = await withCancellation(signal, token =>
+        this.internal.pollForProposal(
+          options,
+          token
+        )
+      );
+      return result ? new UncheckedProposal(result) : null;
+    } catch (error) {
+      throw toPayjoinError(error, 'Failed to poll for proposal');
     }
   }
 }
@@ -94,26 +183,69 @@ export class PayjoinReceiver implements IPayjoinReceiver {
 export class UncheckedProposal implements IUncheckedProposal {
   constructor(private readonly internal: any) {}
 
+  toJson(): string {
+    try {
+      return this.internal.toJson();
+    } catch (error) {
+      throw toPayjoinError(error, 'Failed to serialize proposal');
+    }
+  }
+
+  static fromJson(json: string): UncheckedProposal {
+    try {
+      return new UncheckedProposal(native.UncheckedProposalWrapper.fromJson(json));
+    } catch (error) {
+      throw toPayjoinError(error, 'Failed to deserialize proposal');
+    }
+  }
+
+  extractErrorRequest(code: WellKnownErrorCode, message?: string): PayjoinRequest {
+    try {
+      return new PayjoinRequest(this.internal.extractErrorRequest(code, message));
+    } catch (error) {
+      throw toPayjoinError(error, 'Failed to extract error request');
+    }
+  }
+
   originalTx(): string {
     try {
       return this.internal.originalTx();
     } catch (error) {
-      throw new Error(`Failed to get original transaction: ${error}`);
+      throw toPayjoinError(error, 'Failed to get original transaction');
     }
   }
 
   async checkBroadcastSuitability(
     minFeeRate: number | null,
-    canBoradcast?: (txhex: string) => boolean
+    canBoradcast?: (txhex: string, tx: ITxContext) => boolean
   ): Promise<MaybeInputsOwned> {
     try {
       const result = await this.internal.checkBroadcastSuitability(
         minFeeRate,
-        canBoradcast
+        canBoradcast && ((tx: ITxContext) => canBoradcast(tx.txHex, tx))
       );
       return new MaybeInputsOwned(result);
     } catch (error) {
-      throw new Error(`Failed to check broadcast suitability: ${error}`);
+      throw toPayjoinError(error, 'Failed to check broadcast suitability');
+    }
+  }
+
+  async checkBroadcastSuitabilityAsync(
+    minFeeRate: number | null,
+    canBroadcast: (txHex: string, tx: ITxContext) => Promise<boolean> | boolean,
+    signal?: AbortSignal
+  ): Promise<MaybeInputsOwned> {
+    try {
+      const result = await withCancellation(signal, token =>
+        this.internal.checkBroadcastSuitabilityAsync(
+          minFeeRate,
+          async (tx: ITxContext) => canBroadcast(tx.txHex, tx),
+          token
+        )
+      );
+      return new MaybeInputsOwned(result);
+    } catch (error) {
+      throw toPayjoinError(error, 'Failed to check broadcast suitability');
     }
   }
 
@@ -121,7 +253,7 @@ export class UncheckedProposal implements IUncheckedProposal {
     try {
       return new MaybeInputsOwned(this.internal.assumeInteractiveReceiver());
     } catch (error) {
-      throw new Error(`Failed to assume interactive receiver: ${error}`);
+      throw toPayjoinError(error, 'Failed to assume interactive receiver');
     }
   }
 }
@@ -129,14 +261,78 @@ export class UncheckedProposal implements IUncheckedProposal {
 export class MaybeInputsOwned implements IMaybeInputsOwned {
   constructor(private readonly internal: any) {}
 
+  toJson(): string {
+    try {
+      return this.internal.toJson();
+    } catch (error) {
+      throw toPayjoinError(error, 'Failed to serialize proposal');
+    }
+  }
+
+  static fromJson(json: string): MaybeInputsOwned {
+    try {
+      return new MaybeInputsOwned(native.MaybeInputsOwnedWrapper.fromJson(json));
+    } catch (error) {
+      throw toPayjoinError(error, 'Failed to deserialize proposal');
+    }
+  }
+
+  extractErrorRequest(code: WellKnownErrorCode, message?: string): PayjoinRequest {
+    try {
+      return new PayjoinRequest(this.internal.extractErrorRequest(code, message));
+    } catch (error) {
+      throw toPayjoinError(error, 'Failed to extract error request');
+    }
+  }
+
   async checkInputsNotOwned(
-    isOwned: (script: string) => boolean
+    isOwned: (script: string, input: IInputContext) => boolean
   ): Promise<MaybeInputsSeen> {
     try {
-      const result = await this.internal.checkInputsNotOwned(isOwned);
+      const result = await this.internal.checkInputsNotOwned(
+        (input: IInputContext) => isOwned(input.script, input)
+      );
       return new MaybeInputsSeen(result);
     } catch (error) {
-      throw new Error(`Failed to check inputs not owned: ${error}`);
+      throw toPayjoinError(error, 'Failed to check inputs not owned');
+    }
+  }
+
+  async checkInputsNotOwnedAsync(
+    isOwned: (script: string, input: IInputContext) => Promise<boolean> | boolean,
+    signal?: AbortSignal
+  ): Promise<MaybeInputsSeen> {
+    try {
+      const result = await withCancellation(signal, token =>
+        this.internal.checkInputsNotOwnedAsync(
+          async (input: IInputContext) => isOwned(input.script, input),
+          token
+        )
+      );
+      return new MaybeInputsSeen(result);
+    } catch (error) {
+      throw toPayjoinError(error, 'Failed to check inputs not owned');
+    }
+  }
+
+  async checkInputsNotOwnedBatch(
+    areOwned: (
+      scripts: string[],
+      inputs: IInputContext[]
+    ) => Promise<boolean[]> | boolean[],
+    signal?: AbortSignal
+  ): Promise<MaybeInputsSeen> {
+    try {
+      const result = await withCancellation(signal, token =>
+        this.internal.checkInputsNotOwnedBatch(
+          async (inputs: IInputContext[]) =>
+            areOwned(inputs.map(input => input.script), inputs),
+          token
+        )
+      );
+      return new MaybeInputsSeen(result);
+    } catch (error) {
+      throw toPayjoinError(error, 'Failed to check inputs not owned');
     }
   }
 }
@@ -144,12 +340,78 @@ export class MaybeInputsOwned implements IMaybeInputsOwned {
 export class MaybeInputsSeen implements IMaybeInputsSeen {
   constructor(private readonly internal: any) {}
 
-  async checkNoInputsSeenBefore(isKnown: (outpoint: string) => boolean): Promise<OutputsUnknown> {
+  toJson(): string {
+    try {
+      return this.internal.toJson();
+    } catch (error) {
+      throw toPayjoinError(error, 'Failed to serialize proposal');
+    }
+  }
+
+  static fromJson(json: string): MaybeInputsSeen {
+    try {
+      return new MaybeInputsSeen(native.MaybeInputsSeenWrapper.fromJson(json));
+    } catch (error) {
+      throw toPayjoinError(error, 'Failed to deserialize proposal');
+    }
+  }
+
+  extractErrorRequest(code: WellKnownErrorCode, message?: string): PayjoinRequest {
+    try {
+      return new PayjoinRequest(this.internal.extractErrorRequest(code, message));
+    } catch (error) {
+      throw toPayjoinError(error, 'Failed to extract error request');
+    }
+  }
+
+  async checkNoInputsSeenBefore(
+    isKnown: (outpoint: string, input: IInputContext) => boolean
+  ): Promise<OutputsUnknown> {
     try {
-      const result = await this.internal.checkNoInputsSeenBefore(isKnown);
+      const result = await this.internal.checkNoInputsSeenBefore(
+        (input: IInputContext) => isKnown(input.outpoint, input)
+      );
       return new OutputsUnknown(result);
     } catch (error) {
-      throw new Error(`Failed to check inputs seen before: ${error}`);
+      throw toPayjoinError(error, 'Failed to check inputs seen before');
+    }
+  }
+
+  async checkNoInputsSeenBeforeAsync(
+    isKnown: (outpoint: string, input: IInputContext) => Promise<boolean> | boolean,
+    signal?: AbortSignal
+  ): Promise<OutputsUnknown> {
+    try {
+      const result = await withCancellation(signal, token =>
+        this.internal.checkNoInputsSeenBeforeAsync(
+          async (input: IInputContext) => isKnown(input.outpoint, input),
+          token
+        )
+      );
+      return new OutputsUnknown(result);
+    } catch (error) {
+      throw toPayjoinError(error, 'Failed to check inputs seen before');
+    }
+  }
+
+  async checkNoInputsSeenBeforeBatch(
+    areKnown: (
+      outpoints: string[],
+      inputs: IInputContext[]
+    ) => Promise<boolean[]> | boolean[],
+    signal?: AbortSignal
+  ): Promise<OutputsUnknown> {
+    try {
+      const result = await withCancellation(signal, token =>
+        this.internal.checkNoInputsSeenBeforeBatch(
+          async (inputs: IInputContext[]) =>
+            areKnown(inputs.map(input => input.outpoint), inputs),
+          token
+        )
+      );
+      return new OutputsUnknown(result);
+    } catch (error) {
+      throw toPayjoinError(error, 'Failed to check inputs seen before');
     }
   }
 }
@@ -157,14 +419,57 @@ export class MaybeInputsSeen implements IMaybeInputsSeen {
 export class OutputsUnknown implements IOutputsUnknown {
   constructor(private readonly internal: any) {}
 
+  toJson(): string {
+    try {
+      return this.internal.toJson();
+    } catch (error) {
+      throw toPayjoinError(error, 'Failed to serialize proposal');
+    }
+  }
+
+  static fromJson(json: string): OutputsUnknown {
+    try {
+      return new OutputsUnknown(native.OutputsUnknownWrapper.fromJson(json));
+    } catch (error) {
+      throw toPayjoinError(error, 'Failed to deserialize proposal');
+    }
+  }
+
+  extractErrorRequest(code: WellKnownErrorCode, message?: string): PayjoinRequest {
+    try {
+      return new PayjoinRequest(this.internal.extractErrorRequest(code, message));
+    } catch (error) {
+      throw toPayjoinError(error, 'Failed to extract error request');
+    }
+  }
+
   async identifyReceiverOutputs(
-    isReceiverOutput: (script: string) => boolean
+    isReceiverOutput: (script: string, output: IOutputContext) => boolean
   ): Promise<WantsOutputs> {
     try {
-      const result = await this.internal.identifyReceiverOutputs(isReceiverOutput);
+      const result = await this.internal.identifyReceiverOutputs(
+        (output: IOutputContext) => isReceiverOutput(output.script, output)
+      );
       return new WantsOutputs(result);
     } catch (error) {
-      throw new Error(`Failed to identify receiver outputs: ${error}`);
+      throw toPayjoinError(error, 'Failed to identify receiver outputs');
+    }
+  }
+
+  async identifyReceiverOutputsAsync(
+    isReceiverOutput: (script: string, output: IOutputContext) => Promise<boolean> | boolean,
+    signal?: AbortSignal
+  ): Promise<WantsOutputs> {
+    try {
+      const result = await withCancellation(signal, token =>
+        this.internal.identifyReceiverOutputsAsync(
+          async (output: IOutputContext) => isReceiverOutput(output.script, output),
+          token
+        )
+      );
+      return new WantsOutputs(result);
+    } catch (error) {
+      throw toPayjoinError(error, 'Failed to identify receiver outputs');
     }
   }
 }
@@ -172,16 +477,40 @@ export class OutputsUnknown implements IOutputsUnknown {
 export class WantsOutputs implements IWantsOutputs {
   constructor(private readonly internal: any) {}
 
+  toJson(): string {
+    try {
+      return this.internal.toJson();
+    } catch (error) {
+      throw toPayjoinError(error, 'Failed to serialize proposal');
+    }
+  }
+
+  static fromJson(json: string): WantsOutputs {
+    try {
+      return new WantsOutputs(native.WantsOutputsWrapper.fromJson(json));
+    } catch (error) {
+      throw toPayjoinError(error, 'Failed to deserialize proposal');
+    }
+  }
+
+  extractErrorRequest(code: WellKnownErrorCode, message?: string): PayjoinRequest {
+    try {
+      return new PayjoinRequest(this.internal.extractErrorRequest(code, message));
+    } catch (error) {
+      throw toPayjoinError(error, 'Failed to extract error request');
+    }
+  }
+
   isOutputSubstitutionDisabled(): boolean {
     return this.internal.isOutputSubstitutionDisabled();
   }
 
   async substituteReceiverScript(outputScript: Uint8Array): Promise<WantsOutputs> {
     try {
-      const result = await this.internal.substituteReceiverScript(outputScript);
+      const result = await this.internal.substituteReceiverScript(toNativeBytes(outputScript));
       return new WantsOutputs(result);
     } catch (error) {
-      throw new Error(`Failed to substitute receiver script: ${error}`);
+      throw toPayjoinError(error, 'Failed to substitute receiver script');
     }
   }
 
@@ -191,12 +520,15 @@ export class WantsOutputs implements IWantsOutputs {
   ): Promise<WantsOutputs> {
     try {
       const result = await this.internal.replaceReceiverOutputs(
-        replacementOutputs,
-        drainScript
+        replacementOutputs.map(([script, value]) => ({
+          script: toNativeBytes(script),
+          value: BigInt(value),
+        })),
+        toNativeBytes(drainScript)
       );
       return new WantsOutputs(result);
     } catch (error) {
-      throw new Error(`Failed to replace receiver outputs: ${error}`);
+      throw toPayjoinError(error, 'Failed to replace receiver outputs');
     }
   }
 
@@ -204,7 +536,7 @@ export class WantsOutputs implements IWantsOutputs {
     try {
       return new WantsInputs(this.internal.commitOutputs());
     } catch (error) {
-      throw new Error(`Failed to commit outputs: ${error}`);
+      throw toPayjoinError(error, 'Failed to commit outputs');
     }
   }
 }
@@ -212,14 +544,91 @@ export class WantsOutputs implements IWantsOutputs {
 export class WantsInputs implements IWantsInputs {
   constructor(private readonly internal: any) {}
 
+  toJson(): string {
+    try {
+      return this.internal.toJson();
+    } catch (error) {
+      throw toPayjoinError(error, 'Failed to serialize proposal');
+    }
+  }
+
+  static fromJson(json: string): WantsInputs {
+    try {
+      return new WantsInputs(native.WantsInputsWrapper.fromJson(json));
+    } catch (error) {
+      throw toPayjoinError(error, 'Failed to deserialize proposal');
+    }
+  }
+
+  extractErrorRequest(code: WellKnownErrorCode, message?: string): PayjoinRequest {
+    try {
+      return new PayjoinRequest(this.internal.extractErrorRequest(code, message));
+    } catch (error) {
+      throw toPayjoinError(error, 'Failed to extract error request');
+    }
+  }
+
+  /**
+   * Pick the candidate that best preserves privacy. Nothing is contributed
+   * until `contributeInputs`.
+   */
+  selectPrivacyPreservingInput(candidateInputs: IInputPairRequest[]): IInputPairRequest {
+    try {
+      const index = this.internal.selectPrivacyPreservingInput(candidateInputs.map(toNativeInput));
+      return candidateInputs[index];
+    } catch (error) {
+      throw toPayjoinError(error, 'Failed to make privacy preserving selection');
+    }
+  }
+
+  /** Add `inputs` to the proposal. Call again to contribute more. */
+  contributeInputs(inputs: IInputPairRequest[]): WantsInputs {
+    try {
+      return new WantsInputs(this.internal.contributeInputs(inputs.map(toNativeInput)));
+    } catch (error) {
+      throw toPayjoinError(error, 'Failed to contribute inputs');
+    }
+  }
+
+  /** Stop contributing inputs. Committing without contributing any is allowed. */
+  commitInputs(): ProvisionalProposal {
+    return new ProvisionalProposal(this.internal.commitInputs());
+  }
+
+  /** Select one privacy preserving input, contribute it and commit. */
   async tryContributeInputs(
     candidateInputs: IInputPairRequest[]
   ): Promise<ProvisionalProposal> {
     try {
-      const result = await this.internal.tryContributeInputs(candidateInputs);
+      const result = await this.internal.tryContributeInputs(
+        candidateInputs.map(toNativeInput)
+      );
       return new ProvisionalProposal(result);
     } catch (error) {
-      throw new Error(`Failed to contribute inputs: ${error}`);
+      throw toPayjoinError(error, 'Failed to contribute inputs');
+    }
+  }
+
+  /**
+   * Like `tryContributeInputs`, but `fallback` decides what happens when no
+   * candidate avoids the unnecessary input heuristics.
+   */
+  contributeInputsWithFallback(
+    candidateInputs: IInputPairRequest[],
+    fallback: SelectionFallback
+  ): IContributionOutcome {
+    try {
+      const outcome = this.internal.contributeInputsWithFallback(
+        candidateInputs.map(toNativeInput),
+        fallback
+      );
+      return {
+        proposal: new ProvisionalProposal(outcome.proposal),
+        branch: outcome.branch,
+        selectedIndex: outcome.selectedIndex,
+      };
+    } catch (error) {
+      throw toPayjoinError(error, 'Failed to contribute inputs');
     }
   }
 }
@@ -227,6 +636,30 @@ export class WantsInputs implements IWantsInputs {
 export class ProvisionalProposal implements IProvisionalProposal {
   constructor(private readonly internal: any) {}
 
+  toJson(): string {
+    try {
+      return this.internal.toJson();
+    } catch (error) {
+      throw toPayjoinError(error, 'Failed to serialize proposal');
+    }
+  }
+
+  static fromJson(json: string): ProvisionalProposal {
+    try {
+      return new ProvisionalProposal(native.ProvisionalProposalWrapper.fromJson(json));
+    } catch (error) {
+      throw toPayjoinError(error, 'Failed to deserialize proposal');
+    }
+  }
+
+  extractErrorRequest(code: WellKnownErrorCode, message?: string): PayjoinRequest {
+    try {
+      return new PayjoinRequest(this.internal.extractErrorRequest(code, message));
+    } catch (error) {
+      throw toPayjoinError(error, 'Failed to extract error request');
+    }
+  }
+
   async finalizeProposal(
     minFeerateSatPerVb: number | null,
     maxFeerateSatPerVb: number,
@@ -240,7 +673,28 @@ export class ProvisionalProposal implements IProvisionalProposal {
       );
       return new PayjoinProposal(result);
     } catch (error) {
-      throw new Error(`Failed to finalize proposal: ${error}`);
+      throw toPayjoinError(error, 'Failed to finalize proposal');
+    }
+  }
+
+  async finalizeProposalAsync(
+    minFeerateSatPerVb: number | null,
+    maxFeerateSatPerVb: number,
+    walletProcessPsbt: (psbt: string) => Promise<string> | string,
+    signal?: AbortSignal
+  ): Promise<PayjoinProposal> {
+    try {
+      const result = await withCancellation(signal, token =>
+        this.internal.finalizeProposalAsync(
+          minFeerateSatPerVb,
+          maxFeerateSatPerVb,
+          async (psbt: string) => walletProcessPsbt(psbt),
+          token
+        )
+      );
+      return new PayjoinProposal(result);
+    } catch (error) {
+      throw toPayjoinError(error, 'Failed to finalize proposal');
     }
   }
 }
@@ -248,6 +702,22 @@ export class ProvisionalProposal implements IProvisionalProposal {
 export class PayjoinProposal implements IPayjoinProposal {
   constructor(private readonly internal: any) {}
 
+  toJson(): string {
+    try {
+      return this.internal.toJson();
+    } catch (error) {
+      throw toPayjoinError(error, 'Failed to serialize proposal');
+    }
+  }
+
+  static fromJson(json: string): PayjoinProposal {
+    try {
+      return new PayjoinProposal(native.PayjoinProposalWrapper.fromJson(json));
+    } catch (error) {
+      throw toPayjoinError(error, 'Failed to deserialize proposal');
+    }
+  }
+
   utxosToBeLocked(): string[] {
     return this.internal.utxosToBeLocked();
   }
@@ -264,7 +734,7 @@ export class PayjoinProposal implements IPayjoinProposal {
     try {
       return this.internal.getTxid();
     } catch (error) {
-      throw new Error(`Failed to get txid: ${error}`);
+      throw toPayjoinError(error, 'Failed to get txid');
     }
   }
 
@@ -273,7 +743,7 @@ export class PayjoinProposal implements IPayjoinProposal {
       const result = await this.internal.extractV2Req();
       return new PayjoinRequest(result);
     } catch (error) {
-      throw new Error(`Failed to extract v2 request: ${error}`);
+      throw toPayjoinError(error, 'Failed to extract v2 request');
     }
   }
 
@@ -282,7 +752,7 @@ export class PayjoinProposal implements IPayjoinProposal {
       const res = await this.internal.processRes(response, request.nativeHandle);
       return new PayjoinProposal(res);
     } catch (error) {
-      throw new Error(`Failed to process response: ${error}`);
+      throw toPayjoinError(error, 'Failed to process response');
     }
   }
 }
\ No newline at end of file
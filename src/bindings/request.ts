@@ -1,4 +1,6 @@
 import { IPayjoinRequest, IPayjoinResponse, IPayjoinV2Context } from "../types";
+import { withCancellation, toPayjoinError } from "../utils";
+import native from "../native";
 
   export class PayjoinRequest implements IPayjoinRequest {
     private readonly internal: any;
@@ -14,17 +16,21 @@ import { IPayjoinRequest, IPayjoinResponse, IPayjoinV2Context } from "../types";
     url(): string {
       return this.internal.url();
     }
+
+    contentType(): string {
+      return this.internal.contentType();
+    }
   
     body(): Uint8Array {
       return this.internal.body();
     }
   
-    async post(): Promise<Uint8Array> {
+    async post(signal?: AbortSignal): Promise<Uint8Array> {
         try {
-            const response = await this.internal.post();
+            const response = await withCancellation(signal, token => this.internal.post(token));
             return response;
         } catch (error) {
-            throw new Error(`Failed to post request: ${error}`);
+            throw toPayjoinError(error, 'Failed to post request');
         }
     }
 
@@ -33,7 +39,7 @@ import { IPayjoinRequest, IPayjoinResponse, IPayjoinV2Context } from "../types";
         const result = await this.internal.processResponse(response);
         return new PayjoinResponse(result);
       } catch (error) {
-        throw new Error(`Failed to process response: ${error}`);
+        throw toPayjoinError(error, 'Failed to process response');
       }
     }
   }
@@ -66,12 +72,33 @@ import { IPayjoinRequest, IPayjoinResponse, IPayjoinV2Context } from "../types";
         this.internal = internal;
     }
   
+    /**
+     * Whether `extractRequest` can poll. A restored context cannot until
+     * `extractPostRequest` has posted the original PSBT again.
+     */
+    isPolling(): boolean {
+      return this.internal.isPolling();
+    }
+
+    /**
+     * Post the original PSBT again to resume polling a restored context;
+     * its response resolves to `null` like an empty poll.
+     */
+    async extractPostRequest(ohttpRelay: string): Promise<PayjoinRequest> {
+      try {
+        const request = await this.internal.extractPostRequest(ohttpRelay);
+        return new PayjoinRequest(request);
+      } catch (error) {
+        throw toPayjoinError(error, 'Failed to extract request');
+      }
+    }
+
     async extractRequest(ohttpRelay: string): Promise<PayjoinRequest> {
       try {
         const request = await this.internal.extractRequest(ohttpRelay);
         return new PayjoinRequest(request);
       } catch (error) {
-        throw new Error(`Failed to extract request: ${error}`);
+        throw toPayjoinError(error, 'Failed to extract request');
       }
     }
 
@@ -80,7 +107,27 @@ import { IPayjoinRequest, IPayjoinResponse, IPayjoinV2Context } from "../types";
         const result = await this.internal.processResponse(response, request.nativeHandle);
         return result;
       } catch (error) {
-        throw new Error(`Failed to process response: ${error}`);
+        throw toPayjoinError(error, 'Failed to process response');
       }
     }
-  }
\ No newline at end of file
+
+    toJson(): string {
+      try {
+        return this.internal.toJson();
+      } catch (error) {
+        throw toPayjoinError(error, 'Failed to serialize v2 context');
+      }
+    }
+
+    /**
+     * Resume a context after a restart. It polls again once
+     * `extractPostRequest` has posted the original PSBT.
+     */
+    static fromJson(json: string): PayjoinV2Context {
+      try {
+        return new PayjoinV2Context(native.PayjoinV2Context.fromJson(json));
+      } catch (error) {
+        throw toPayjoinError(error, 'Failed to deserialize v2 context');
+      }
+    }
+  }
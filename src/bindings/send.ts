@@ -1,9 +1,14 @@
 import { 
     IPayjoinSenderBuilder,
     IPayjoinSender,
+    IExportKey,
+    IPollOptions,
+    ISendOutcome,
+    PayjoinVersion,
 } from '../types';
 import { PayjoinRequest } from './request';
 import native from '../native';
+import { withCancellation, toPayjoinError, toSats } from '../utils';
 
   
   export class PayjoinSenderBuilder implements IPayjoinSenderBuilder {
@@ -13,7 +18,7 @@ import native from '../native';
       try {
         this.internal = new native.PayjoinSenderBuilder(psbt, uri);
       } catch (error) {
-        throw new Error(`Failed to create PayjoinReceiver: ${error}`);
+        throw toPayjoinError(error, 'Failed to create PayjoinReceiver');
       }
     }
   
@@ -25,16 +30,20 @@ import native from '../native';
   
         return builder;
       } catch (error) {
-        throw new Error(`Failed to create PayjoinSenderBuilder: ${error}`);
+        throw toPayjoinError(error, 'Failed to create PayjoinSenderBuilder');
       }
     }
   
+    version(): PayjoinVersion {
+      return this.internal.version();
+    }
+
     disableOutputSubstitution(disable: boolean): PayjoinSenderBuilder {
       try {
         this.internal.disableOutputSubstitution(disable);
         return this;
       } catch (error) {
-        throw new Error(`Failed to disable output substitution: ${error}`);
+        throw toPayjoinError(error, 'Failed to disable output substitution');
       }
     }
   
@@ -43,26 +52,26 @@ import native from '../native';
         const sender = await this.internal.buildRecommended(minFeeRateSatPerVb);
         return new PayjoinSender(sender);
       } catch (error) {
-        throw new Error(`Failed to build recommended sender: ${error}`);
+        throw toPayjoinError(error, 'Failed to build recommended sender');
       }
     }
   
     async buildWithAdditionalFee(
-      maxFeeContributionSats: number,
+      maxFeeContributionSats: bigint | number,
       changeIndex: number | null,
       minFeeRateSatPerVb: number,
       clampFeeContribution: boolean
     ): Promise<PayjoinSender> {
       try {
         const sender = await this.internal.buildWithAdditionalFee(
-          maxFeeContributionSats,
+          toSats(maxFeeContributionSats, 'Invalid fee contribution', 'SenderBuilder'),
           changeIndex,
           minFeeRateSatPerVb,
           clampFeeContribution
         );
         return new PayjoinSender(sender);
       } catch (error) {
-        throw new Error(`Failed to build sender with additional fee: ${error}`);
+        throw toPayjoinError(error, 'Failed to build sender with additional fee');
       }
     }
   }
@@ -73,13 +82,57 @@ import native from '../native';
     constructor(internal: any) {
         this.internal = internal;
     }
+
+    get nativeHandle() {
+      return this.internal;
+    }
   
+    version(): PayjoinVersion {
+      return this.internal.version();
+    }
+
+    async extractRequest(ohttpRelay?: string): Promise<PayjoinRequest> {
+      try {
+        const request = await this.internal.extractRequest(ohttpRelay);
+        return new PayjoinRequest(request);
+      } catch (error) {
+        throw toPayjoinError(error, 'Failed to extract request');
+      }
+    }
+
+    async extractV1(): Promise<PayjoinRequest> {
+      try {
+        const request = await this.internal.extractV1();
+        return new PayjoinRequest(request);
+      } catch (error) {
+        throw toPayjoinError(error, 'Failed to extract v1 request');
+      }
+    }
+
     async extractV2(ohttpRelay: string): Promise<PayjoinRequest> {
       try {
         const request = await this.internal.extractV2(ohttpRelay);
         return new PayjoinRequest(request);
       } catch (error) {
-        throw new Error(`Failed to extract v2 request: ${error}`);
+        throw toPayjoinError(error, 'Failed to extract v2 request');
+      }
+    }
+
+    async sendV2(
+      ohttpRelay: string,
+      options?: IPollOptions,
+      signal?: AbortSignal
+    ): Promise<ISendOutcome> {
+      try {
+        return await withCancellation(signal, token =>
+          this.internal.sendV2(
+            ohttpRelay,
+            options,
+            token
+          )
+        );
+      } catch (error) {
+        throw toPayjoinError(error, 'Failed to send v2 payjoin');
       }
     }
 
@@ -95,7 +148,27 @@ import native from '../native';
 
         return sender;
       } catch (error) {
-        throw new Error(`Failed to deserialize sender: ${error}`);
+        throw toPayjoinError(error, 'Failed to deserialize sender');
+      }
+    }
+
+    toEncryptedJson(key: IExportKey): string {
+      try {
+        return this.internal.toEncryptedJson(key);
+      } catch (error) {
+        throw toPayjoinError(error, 'Failed to serialize sender');
+      }
+    }
+
+    static fromEncryptedJson(json: string, key: IExportKey): PayjoinSender {
+      try {
+        const internal = native.PayjoinSender.fromEncryptedJson(json, key);
+        const sender = Object.create(PayjoinSender.prototype);
+        sender.internal = internal;
+
+        return sender;
+      } catch (error) {
+        throw toPayjoinError(error, 'Failed to deserialize sender');
       }
     }
   }
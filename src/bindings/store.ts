@@ -0,0 +1,123 @@
+import { IExportKey, ISessionBackend, ISessionInfo } from '../types';
+import { PayjoinReceiver } from './receive';
+import { PayjoinSender } from './send';
+import native from '../native';
+import { toPayjoinError } from '../utils';
+
+/**
+ * Persists receiver and sender sessions under stable IDs, so they can be
+ * resumed after a restart. Receivers are keyed by their directory mailbox,
+ * senders by their reply key. Given a `key`, a store keeps each session as
+ * its `toEncryptedJson` export, so no backend sees its secrets in plaintext.
+ */
+export class SessionStore {
+  private constructor(private readonly internal: any) {}
+
+  /** Keep sessions in this process only, e.g. for tests. */
+  static memory(key?: IExportKey): SessionStore {
+    try {
+      return new SessionStore(native.SessionStore.memory(key));
+    } catch (error) {
+      throw toPayjoinError(error, 'Invalid session store key');
+    }
+  }
+
+  /**
+   * Keep each session as a JSON file in `directory`, which must exist.
+   * Files are created readable by their owner only.
+   */
+  static file(directory: string, key?: IExportKey): SessionStore {
+    try {
+      return new SessionStore(native.SessionStore.file(directory, key));
+    } catch (error) {
+      throw toPayjoinError(error, 'Invalid session store key');
+    }
+  }
+
+  /** Keep sessions wherever `backend` puts them. */
+  static custom(backend: ISessionBackend, key?: IExportKey): SessionStore {
+    try {
+      return new SessionStore(
+        native.SessionStore.custom(
+          {
+            list: () => backend.list(),
+            load: (id: string) => backend.load(id),
+            save: (session: any) => backend.save(session),
+            delete: (id: string) => backend.delete(id),
+          },
+          key
+        )
+      );
+    } catch (error) {
+      throw toPayjoinError(error, 'Invalid session store key');
+    }
+  }
+
+  /** Store `receiver`, replacing any earlier save, and resolve with its ID. */
+  async saveReceiver(receiver: PayjoinReceiver): Promise<string> {
+    try {
+      return await this.internal.saveReceiver(receiver.nativeHandle);
+    } catch (error) {
+      throw toPayjoinError(error, 'Failed to store receiver');
+    }
+  }
+
+  /** Store `sender`, replacing any earlier save, and resolve with its ID. */
+  async saveSender(sender: PayjoinSender): Promise<string> {
+    try {
+      return await this.internal.saveSender(sender.nativeHandle);
+    } catch (error) {
+      throw toPayjoinError(error, 'Failed to store sender');
+    }
+  }
+
+  async loadReceiver(id: string): Promise<PayjoinReceiver | null> {
+    try {
+      const internal = await this.internal.loadReceiver(id);
+      if (!internal) {
+        return null;
+      }
+      const receiver = Object.create(PayjoinReceiver.prototype);
+      receiver.internal = internal;
+
+      return receiver;
+    } catch (error) {
+      throw toPayjoinError(error, 'Failed to load receiver');
+    }
+  }
+
+  async loadSender(id: string): Promise<PayjoinSender | null> {
+    try {
+      const internal = await this.internal.loadSender(id);
+      return internal ? new PayjoinSender(internal) : null;
+    } catch (error) {
+      throw toPayjoinError(error, 'Failed to load sender');
+    }
+  }
+
+  async list(): Promise<ISessionInfo[]> {
+    try {
+      return await this.internal.list();
+    } catch (error) {
+      throw toPayjoinError(error, 'Failed to list sessions');
+    }
+  }
+
+  /** Resolves with whether a session was deleted. */
+  async delete(id: string): Promise<boolean> {
+    try {
+      return await this.internal.delete(id);
+    } catch (error) {
+      throw toPayjoinError(error, 'Failed to delete session');
+    }
+  }
+
+  /** Delete every expired session and resolve with their IDs. */
+  async sweepExpired(): Promise<string[]> {
+    try {
+      return await this.internal.sweepExpired();
+    } catch (error) {
+      throw toPayjoinError(error, 'Failed to sweep expired sessions');
+    }
+  }
+}
@@ -6,6 +6,7 @@ import {
   IPayjoinUrl, 
 } from '../types';
 import native from '../native';
+import { toPayjoinError, toSats } from '../utils';
 
 export class UriBuilder implements IPayjoinUriBuilder<UriBuilder> {
   private internal: any;
@@ -18,16 +19,16 @@ export class UriBuilder implements IPayjoinUriBuilder<UriBuilder> {
     try {
       this.internal = new native.PayjoinUriBuilder(address, endpoint);
     } catch (error) {
-      throw new Error(`Failed to create PayjoinUriBuilder: ${error}`);
+      throw toPayjoinError(error, 'Failed to create PayjoinUriBuilder');
     }
   }
 
-  amount(amountSat: number): UriBuilder {
+  amount(amountSat: bigint | number): UriBuilder {
     try {
-      this.internal = this.internal.amount(amountSat);
+      this.internal = this.internal.amount(toSats(amountSat, 'Invalid amount', 'Uri'));
       return this;
     } catch (error) {
-      throw new Error(`Failed to set amount: ${error}`);
+      throw toPayjoinError(error, 'Failed to set amount');
     }
   }
 
@@ -36,7 +37,7 @@ export class UriBuilder implements IPayjoinUriBuilder<UriBuilder> {
       this.internal = this.internal.message(message);
       return this;
     } catch (error) {
-      throw new Error(`Failed to set message: ${error}`);
+      throw toPayjoinError(error, 'Failed to set message');
     }
   }
 
@@ -45,7 +46,7 @@ export class UriBuilder implements IPayjoinUriBuilder<UriBuilder> {
       this.internal = this.internal.label(label);
       return this;
     } catch (error) {
-      throw new Error(`Failed to set label: ${error}`);
+      throw toPayjoinError(error, 'Failed to set label');
     }
   }
 
@@ -54,7 +55,7 @@ export class UriBuilder implements IPayjoinUriBuilder<UriBuilder> {
       this.internal = this.internal.disableOutputSubstitution();
       return this;
     } catch (error) {
-      throw new Error(`Failed to disable output substitution: ${error}`);
+      throw toPayjoinError(error, 'Failed to disable output substitution');
     }
   }
 
@@ -62,7 +63,7 @@ export class UriBuilder implements IPayjoinUriBuilder<UriBuilder> {
     try {
       return this.internal.build();
     } catch (error) {
-      throw new Error(`Failed to build URI: ${error}`);
+      throw toPayjoinError(error, 'Failed to build URI');
     }
   }
 }
@@ -76,7 +77,7 @@ export class BtcUri implements IBtcUri {
         bip21,
       );
     } catch (error) {
-      throw new Error(`Failed to create BtcUri: ${error}`);
+      throw toPayjoinError(error, 'Failed to create BtcUri');
     }
   }
 
@@ -88,7 +89,7 @@ export class BtcUri implements IBtcUri {
 
       return uri;
     } catch (error) {
-      throw new Error(`Failed to create URI from BIP21: ${error}`);
+      throw toPayjoinError(error, 'Failed to create URI from BIP21');
     }
   }
 
@@ -105,7 +106,7 @@ export class CheckedBtcUri implements ICheckedBtcUri {
       const pjUri = await this.internal.checkPjSupported();
       return new PayjoinUri(pjUri);
     } catch (error) {
-      throw new Error(`URI does not support Payjoin: ${error}`);
+      throw toPayjoinError(error, 'URI does not support Payjoin');
     }
   }
 }
@@ -113,7 +114,8 @@ export class CheckedBtcUri implements ICheckedBtcUri {
 export class PayjoinUri implements IPayjoinUri {
   constructor(private readonly internal: any) {}
 
-  amount(): number {
+  /** The requested amount, in satoshis. */
+  amount(): bigint | null {
     return this.internal.amount();
   }
 
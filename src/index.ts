@@ -3,4 +3,7 @@ export * from './bindings/send';
 export * from './bindings/uri';
 export * from './bindings/io';
 export * from './bindings/request';
+export * from './bindings/logging';
+export * from './bindings/store';
+export * from './bindings/manager';
 export * from './utils';
\ No newline at end of file
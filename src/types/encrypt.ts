@@ -0,0 +1,8 @@
+/**
+ * Secret an encrypted export is sealed with. Give exactly one of a random
+ * 32-byte key or a passphrase, which is stretched with scrypt.
+ */
+export interface IExportKey {
+  key?: Uint8Array;
+  passphrase?: string;
+}
@@ -3,3 +3,8 @@ export * from './send';
 export * from './receive';
 export * from './uri';
 export * from './request';
+export * from './poll';
+export * from './encrypt';
+export * from './logging';
+export * from './store';
+export * from './manager';
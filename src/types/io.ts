@@ -1,6 +1,6 @@
 export interface IPayjoinOhttpKeysStatic {
     fromBytes(bytes: Uint8Array): Promise<IPayjoinOhttpKeys>;
-    fetch(ohttpRelay: string, payjoinDirectory: string): Promise<IPayjoinOhttpKeys>;
+    fetch(ohttpRelay: string, payjoinDirectory: string, signal?: AbortSignal): Promise<IPayjoinOhttpKeys>;
 }
 
 export interface IPayjoinOhttpKeys {
@@ -0,0 +1,17 @@
+/** Verbosity of the diagnostics forwarded from the native module. */
+export type LogLevel = 'Off' | 'Error' | 'Warn' | 'Info' | 'Debug' | 'Trace';
+
+/**
+ * How much of PSBTs, transactions, txids, scripts and OHTTP payloads reaches
+ * the logger. `Redacted`, the default, keeps a short prefix and a hash of each;
+ * `Full` is only meant for local debugging.
+ */
+export type LogRedaction = 'Redacted' | 'Full';
+
+/** A single diagnostic emitted by the bindings or by rust-payjoin. */
+export interface ILogRecord {
+  level: LogLevel;
+  /** The Rust module the record came from, e.g. `payjoin::receive::v2`. */
+  target: string;
+  message: string;
+}
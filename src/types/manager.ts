@@ -0,0 +1,30 @@
+import { PayjoinError } from '../utils';
+import { IPollOptions } from './poll';
+import { IUncheckedProposal } from './receive';
+
+export interface IReceiverManagerOptions {
+  /** Most polls in flight at once across all sessions. Defaults to 16. */
+  maxConcurrentPolls?: number;
+  /**
+   * Schedule each session is polled on. `timeoutMs` is ignored: sessions are
+   * polled until they yield a proposal, expire or are removed.
+   */
+  poll?: IPollOptions;
+}
+
+/**
+ * What happened to a managed session. A session that yields a proposal or
+ * expires leaves the manager; one whose poll failed is polled again, unless
+ * its poller itself failed.
+ */
+export type ReceiverEventKind = 'Proposal' | 'Expired' | 'Error';
+
+export interface IReceiverEvent {
+  kind: ReceiverEventKind;
+  /** The session's ID, as returned by `ReceiverManager.add`. */
+  sessionId: string;
+  /** The sender's proposal, for `Proposal` events. */
+  proposal?: IUncheckedProposal;
+  /** Why the poll failed, for `Error` events. */
+  error?: PayjoinError;
+}
@@ -0,0 +1,6 @@
+export interface IPollOptions {
+  intervalMs?: number;
+  maxIntervalMs?: number;
+  backoffMultiplier?: number;
+  timeoutMs?: number;
+}
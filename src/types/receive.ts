@@ -1,5 +1,5 @@
 import { UriBuilder } from "..";
-import { IPayjoinRequest } from "./index";
+import { IPayjoinRequest, IPollOptions } from "./index";
 
 export interface IPayjoinReceiver {
   pjUrl(): string;
@@ -9,32 +9,123 @@ export interface IPayjoinReceiver {
     response: Uint8Array, 
     request: IPayjoinRequest
   ): Promise<IUncheckedProposal | null>;
+  pollForProposal(
+    options?: IPollOptions,
+    signal?: AbortSignal
+  ): Promise<IUncheckedProposal | null>;
+}
+
+/** One of the sender's inputs, decoded for the receiver's checks. */
+export interface IInputContext {
+  /** The spent output's script, hex encoded. */
+  script: string;
+  /** The script's address on the receiver's network, when it has one. */
+  address?: string;
+  /** The spent outpoint, as `txid:vout`. */
+  outpoint: string;
+  /** The spent output's value in satoshis. */
+  value: bigint;
+}
+
+/** One of the original transaction's outputs. */
+export interface IOutputContext {
+  script: string;
+  address?: string;
+  vout: number;
+  value: bigint;
+}
+
+/** The sender's original transaction, decoded for the broadcast check. */
+export interface ITxContext {
+  txHex: string;
+  txid: string;
+  vsize: number;
+  /** The fee paid by the sender, in satoshis. */
+  fee: bigint;
+  feeRateSatPerVb: number;
 }
 
-export interface IUncheckedProposal {
+/** BIP78 well-known errors a receiver can reply to the sender with. */
+export type WellKnownErrorCode =
+  | 'Unavailable'
+  | 'NotEnoughMoney'
+  | 'VersionUnsupported'
+  | 'OriginalPsbtRejected';
+
+/**
+ * Proposal states that can be persisted and resumed after a restart with the
+ * matching class's static `fromJson`.
+ */
+export interface IPersistedProposal {
+  toJson(): string;
+}
+
+/**
+ * Proposal states that can still be rejected. The request tells the sender to
+ * broadcast the original transaction instead of waiting for a proposal.
+ */
+export interface IRejectableProposal extends IPersistedProposal {
+  extractErrorRequest(code: WellKnownErrorCode, message?: string): IPayjoinRequest;
+}
+
+export interface IUncheckedProposal extends IRejectableProposal {
   originalTx(): string;
   checkBroadcastSuitability(
     minFeeRate: number | null,
-    canBoradcast?: (txhex: string) => boolean
+    canBoradcast?: (txhex: string, tx: ITxContext) => boolean
+  ): Promise<IMaybeInputsOwned>;
+  /** Like `checkBroadcastSuitability`, for wallets that answer asynchronously. */
+  checkBroadcastSuitabilityAsync(
+    minFeeRate: number | null,
+    canBroadcast: (txHex: string, tx: ITxContext) => Promise<boolean> | boolean,
+    signal?: AbortSignal
   ): Promise<IMaybeInputsOwned>;
   assumeInteractiveReceiver(): IMaybeInputsOwned;
 }
 
-export interface IMaybeInputsOwned {
+export interface IMaybeInputsOwned extends IRejectableProposal {
   checkInputsNotOwned(
-    isOwned: (script: string) => boolean
+    isOwned: (script: string, input: IInputContext) => boolean
+  ): Promise<IMaybeInputsSeen>;
+  checkInputsNotOwnedAsync(
+    isOwned: (script: string, input: IInputContext) => Promise<boolean> | boolean,
+    signal?: AbortSignal
+  ): Promise<IMaybeInputsSeen>;
+  /** Ask about every input script at once; answer with one boolean per script. */
+  checkInputsNotOwnedBatch(
+    areOwned: (
+      scripts: string[],
+      inputs: IInputContext[]
+    ) => Promise<boolean[]> | boolean[],
+    signal?: AbortSignal
   ): Promise<IMaybeInputsSeen>;
 }
 
-export interface IMaybeInputsSeen {
+export interface IMaybeInputsSeen extends IRejectableProposal {
   checkNoInputsSeenBefore(
-    isKnown: (outpoint: string) => boolean
+    isKnown: (outpoint: string, input: IInputContext) => boolean
+  ): Promise<IOutputsUnknown>;
+  checkNoInputsSeenBeforeAsync(
+    isKnown: (outpoint: string, input: IInputContext) => Promise<boolean> | boolean,
+    signal?: AbortSignal
+  ): Promise<IOutputsUnknown>;
+  /** Ask about every input outpoint at once; answer with one boolean per outpoint. */
+  checkNoInputsSeenBeforeBatch(
+    areKnown: (
+      outpoints: string[],
+      inputs: IInputContext[]
+    ) => Promise<boolean[]> | boolean[],
+    signal?: AbortSignal
   ): Promise<IOutputsUnknown>;
 }
 
-export interface IOutputsUnknown {
+export interface IOutputsUnknown extends IRejectableProposal {
   identifyReceiverOutputs(
-    isReceiverOutput: (script: string) => boolean
+    isReceiverOutput: (script: string, output: IOutputContext) => boolean
+  ): Promise<IWantsOutputs>;
+  identifyReceiverOutputsAsync(
+    isReceiverOutput: (script: string, output: IOutputContext) => Promise<boolean> | boolean,
+    signal?: AbortSignal
   ): Promise<IWantsOutputs>;
 }
 
@@ -43,7 +134,7 @@ export interface IReplacementOutput {
   value: bigint;
 }
 
-export interface IWantsOutputs {
+export interface IWantsOutputs extends IRejectableProposal {
   isOutputSubstitutionDisabled(): boolean;
   substituteReceiverScript(outputScript: Uint8Array): Promise<IWantsOutputs>;
   replaceReceiverOutputs(
@@ -60,25 +151,68 @@ export interface IPartialSigData {
 
 export interface IBip32DerivationData {
   pubkey: Uint8Array;
-  fingerprint_path: Uint8Array;
+  /**
+   * The master key fingerprint, followed by any path steps before `child` as
+   * little-endian u32s, as in a PSBT key source.
+   */
+  fingerprintPath: Uint8Array;
+  /** The final path step. Hardened steps have the high bit set. */
   child: number;
 }
 
 export interface IWitnessUtxoData {
-  amount: number;
-  script_pub_key: string;
+  /** The UTXO's value, in satoshis. */
+  amount: bigint;
+  scriptPubKey: string;
 }
 
 export interface IPsbtInputData {
-  non_witness_utxo?: Uint8Array;
-  witness_utxo?: IWitnessUtxoData;
-  partial_sigs?: IPartialSigData[];
-  sighash_type?: number;
-  redeem_script?: Uint8Array;
-  witness_script?: Uint8Array;
-  bip32_derivation?: IBip32DerivationData[];
-  final_script_sig?: Uint8Array;
-  final_script_witness?: Uint8Array[];
+  nonWitnessUtxo?: Uint8Array;
+  witnessUtxo?: IWitnessUtxoData;
+  /** DER signatures with their sighash byte, keyed by public key. */
+  partialSigs?: IPartialSigData[];
+  sighashType?: number;
+  redeemScript?: Uint8Array;
+  witnessScript?: Uint8Array;
+  bip32Derivation?: IBip32DerivationData[];
+  finalScriptSig?: Uint8Array;
+  finalScriptWitness?: Uint8Array[];
+  /** Schnorr signature for a key path spend. */
+  tapKeySig?: Uint8Array;
+  tapScriptSigs?: ITapScriptSigData[];
+  tapScripts?: ITapLeafScriptData[];
+  tapBip32Derivation?: ITapBip32DerivationData[];
+  /** The 32-byte x-only internal key. */
+  tapInternalKey?: Uint8Array;
+  /** The 32-byte root of the script tree. */
+  tapMerkleRoot?: Uint8Array;
+}
+
+/** A Schnorr signature for a script path spend. */
+export interface ITapScriptSigData {
+  /** The 32-byte x-only key that signed. */
+  pubkey: Uint8Array;
+  /** The 32-byte hash of the leaf the signature is for. */
+  leafHash: Uint8Array;
+  signature: Uint8Array;
+}
+
+/** A leaf script with the control block proving it is in the tree. */
+export interface ITapLeafScriptData {
+  controlBlock: Uint8Array;
+  script: Uint8Array;
+  /** Defaults to 0xc0, tapscript. */
+  leafVersion?: number;
+}
+
+export interface ITapBip32DerivationData {
+  /** The 32-byte x-only key. */
+  pubkey: Uint8Array;
+  /** Hashes of the leaves the key appears in. Empty for the internal key. */
+  leafHashes: Uint8Array[];
+  /** As in `IBip32DerivationData`. */
+  fingerprintPath: Uint8Array;
+  child: number;
 }
 
 export interface ITxOutpoint {
@@ -88,27 +222,57 @@ export interface ITxOutpoint {
 
 export interface IInputPairRequest {
   prevout: ITxOutpoint;
-  script_sig: Uint8Array;
-  witness: Uint8Array[];
-  sequence: number;
-  psbt_data: IPsbtInputData;
+  scriptSig?: Uint8Array;
+  witness?: Uint8Array[];
+  sequence?: number;
+  psbtData: IPsbtInputData;
+}
+
+/**
+ * What `contributeInputsWithFallback` does when no candidate avoids the
+ * unnecessary input heuristics: contribute the largest candidate anyway,
+ * contribute nothing, or fail as `tryContributeInputs` does.
+ */
+export type SelectionFallback = 'LargestInput' | 'NoInput' | 'Abort';
+
+/** Which inputs `contributeInputsWithFallback` ended up contributing. */
+export type ContributionBranch = 'PrivacyPreserving' | 'LargestInput' | 'NoInput';
+
+export interface IContributionOutcome {
+  proposal: IProvisionalProposal;
+  branch: ContributionBranch;
+  /** Index of the contributed input in the candidates, unless none was. */
+  selectedIndex?: number;
 }
 
-export interface IWantsInputs {
+export interface IWantsInputs extends IRejectableProposal {
+  selectPrivacyPreservingInput(candidateInputs: IInputPairRequest[]): IInputPairRequest;
+  contributeInputs(inputs: IInputPairRequest[]): IWantsInputs;
+  commitInputs(): IProvisionalProposal;
   tryContributeInputs(
     candidateInputs: IInputPairRequest[]
   ): Promise<IProvisionalProposal>;
+  contributeInputsWithFallback(
+    candidateInputs: IInputPairRequest[],
+    fallback: SelectionFallback
+  ): IContributionOutcome;
 }
 
-export interface IProvisionalProposal {
+export interface IProvisionalProposal extends IRejectableProposal {
   finalizeProposal(
     minFeerateSatPerVb: number | null,
     maxFeerateSatPerVb: number,
     walletProcessPsbt: (psbt: string) => string
   ): Promise<IPayjoinProposal>;
+  finalizeProposalAsync(
+    minFeerateSatPerVb: number | null,
+    maxFeerateSatPerVb: number,
+    walletProcessPsbt: (psbt: string) => Promise<string> | string,
+    signal?: AbortSignal
+  ): Promise<IPayjoinProposal>;
 }
 
-export interface IPayjoinProposal {
+export interface IPayjoinProposal extends IPersistedProposal {
   utxosToBeLocked(): string[];
   isOutputSubstitutionDisabled(): boolean;
   psbt(): string;
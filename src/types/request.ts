@@ -1,8 +1,9 @@
 
 export interface IPayjoinRequest {
   url(): string;
+  contentType(): string;
   body(): Uint8Array;
-  post(): Promise<Uint8Array>;
+  post(signal?: AbortSignal): Promise<Uint8Array>;
   processResponse(response: Uint8Array): Promise<IPayjoinResponse>;
 }
 
@@ -13,6 +14,9 @@ export interface IPayjoinResponse {
 }
 
 export interface IPayjoinV2Context {
+  isPolling(): boolean;
+  extractPostRequest(ohttpRelay: string): Promise<IPayjoinRequest>;
   extractRequest(ohttpRelay: string): Promise<IPayjoinRequest>;
   processResponse(response: Uint8Array, request: IPayjoinRequest): Promise<string>;
+  toJson(): string;
 }
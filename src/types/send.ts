@@ -1,10 +1,18 @@
-import { IPayjoinRequest } from "./index";
+import { IPayjoinRequest, IPollOptions } from "./index";
+
+export type PayjoinVersion = 'v1' | 'v2';
+
+export interface ISendOutcome {
+  status: 'proposal' | 'timeout' | 'expired';
+  psbt?: string;
+}
 
 export interface IPayjoinSenderBuilder {
+  version(): PayjoinVersion;
   disableOutputSubstitution(disable: boolean): IPayjoinSenderBuilder;
   buildRecommended(minFeeRateSatPerVb: number): Promise<IPayjoinSender>;
   buildWithAdditionalFee(
-    maxFeeContributionSats: number,
+    maxFeeContributionSats: bigint | number,
     changeIndex: number | null,
     minFeeRateSatPerVb: number,
     clampFeeContribution: boolean
@@ -12,5 +20,13 @@ export interface IPayjoinSenderBuilder {
 }
 
 export interface IPayjoinSender {
+  version(): PayjoinVersion;
+  extractRequest(ohttpRelay?: string): Promise<IPayjoinRequest>;
+  extractV1(): Promise<IPayjoinRequest>;
   extractV2(ohttpRelay: string): Promise<IPayjoinRequest>;
+  sendV2(
+    ohttpRelay: string,
+    options?: IPollOptions,
+    signal?: AbortSignal
+  ): Promise<ISendOutcome>;
 }
\ No newline at end of file
@@ -0,0 +1,28 @@
+/** Which side of a payjoin a stored session belongs to. */
+export type SessionKind = 'Receiver' | 'Sender';
+
+/** A session as listed by `SessionStore.list`. */
+export interface ISessionInfo {
+  id: string;
+  kind: SessionKind;
+  /** Unix timestamp after which the session can no longer complete. */
+  expiresAt?: bigint;
+}
+
+/** A session as handed to and returned by a custom backend. */
+export interface IStoredSession extends ISessionInfo {
+  /** What the session's `toJson` produced. */
+  json: string;
+}
+
+/**
+ * Storage implemented in JavaScript, e.g. on top of an existing database.
+ * Every method may be called concurrently.
+ */
+export interface ISessionBackend {
+  list(): Promise<IStoredSession[]>;
+  load(id: string): Promise<IStoredSession | null | undefined>;
+  save(session: IStoredSession): Promise<void>;
+  /** Resolves with whether a session was deleted. */
+  delete(id: string): Promise<boolean>;
+}
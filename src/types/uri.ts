@@ -1,6 +1,6 @@
 export interface IPayjoinUriBuilder<T extends IPayjoinUriBuilder<T>> {
     //new(address: string, endpoint: string): T;
-    amount(amountSat: number): T;
+    amount(amountSat: bigint | number): T;
     message(message: string): T;
     label(label: string): T;
     disableOutputSubstitution(): T;
@@ -16,7 +16,7 @@ export interface IBtcUri {
   }
   
   export interface IPayjoinUri {
-    amount(): number;
+    amount(): bigint | null;
     address(): string;
     endpoint(): IPayjoinUrl;
     exp(): bigint;
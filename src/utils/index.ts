@@ -1,3 +1,5 @@
+import native from '../native';
+
 
 export class PayjoinHttp {
 
@@ -17,4 +19,256 @@ export class PayjoinHttp {
 
     return new Uint8Array(await relayResponse.arrayBuffer());
   }
-}
\ No newline at end of file
+}
+
+/** Stable error codes carried by every error the bindings throw. */
+export type ErrorCode =
+  | 'InvalidAddress'
+  | 'InvalidAmount'
+  | 'InvalidFeeRate'
+  | 'InvalidInput'
+  | 'InvalidOhttpKeys'
+  | 'InvalidPsbt'
+  | 'InvalidScript'
+  | 'InvalidUri'
+  | 'InvalidUrl'
+  | 'InvalidOptions'
+  | 'UnsupportedUri'
+  | 'CreateRequestFailed'
+  | 'SessionExpired'
+  | 'MissingContext'
+  | 'NetworkError'
+  | 'HttpStatus'
+  | 'InvalidResponse'
+  | 'ProposalRejected'
+  | 'ReceiverUnavailable'
+  | 'NotEnoughMoney'
+  | 'VersionUnsupported'
+  | 'OriginalPsbtRejected'
+  | 'UnrecognizedReceiverError'
+  | 'ReceiverFailed'
+  | 'CallbackFailed'
+  | 'SerializationFailed'
+  | 'Cancelled'
+  | 'Unknown';
+
+/** The part of the payjoin flow an error originated from. */
+export type ErrorStage =
+  | 'Uri'
+  | 'OhttpKeys'
+  | 'Http'
+  | 'SenderBuilder'
+  | 'Sender'
+  | 'Receiver'
+  | 'Proposal'
+  | 'Persistence';
+
+/**
+ * Error thrown by the bindings. `code` and `stage` are stable and safe to
+ * branch on; `kind` names the underlying payjoin error type when known.
+ */
+export class PayjoinError extends Error {
+  readonly code: ErrorCode;
+  readonly stage?: ErrorStage;
+  readonly kind?: string;
+  readonly details?: Record<string, unknown>;
+
+  constructor(
+    code: ErrorCode,
+    message: string,
+    options: {
+      stage?: ErrorStage;
+      kind?: string;
+      details?: Record<string, unknown>;
+      cause?: unknown;
+    } = {}
+  ) {
+    super(message);
+    this.name = 'PayjoinError';
+    this.code = code;
+    this.stage = options.stage;
+    this.kind = options.kind;
+    this.details = options.details;
+    if (options.cause !== undefined) {
+      (this as { cause?: unknown }).cause = options.cause;
+    }
+  }
+}
+
+/**
+ * An error reply from the payjoin receiver. `code` tells BIP78 well-known
+ * errors apart; `receiverMessage` is chosen by the receiver and should only
+ * be logged, never shown to end users. Only v1 replies are read this way: a
+ * v2 receiver's error cannot be decrypted as a proposal and is reported as
+ * `InvalidResponse`.
+ */
+export class ReceiverError extends PayjoinError {
+  /** The `errorCode` field of the receiver's reply, e.g. `not-enough-money`. */
+  readonly errorCode: string;
+  readonly receiverMessage: string;
+  /** Protocol versions the receiver accepts, for `version-unsupported`. */
+  readonly supported?: number[];
+
+  constructor(
+    code: ErrorCode,
+    message: string,
+    details: { errorCode: string; receiverMessage?: string; supported?: number[] },
+    options: { stage?: ErrorStage; kind?: string; cause?: unknown } = {}
+  ) {
+    super(code, message, { ...options, details });
+    this.name = 'ReceiverError';
+    this.errorCode = details.errorCode;
+    this.receiverMessage = details.receiverMessage ?? '';
+    this.supported = details.supported;
+  }
+}
+
+/** Thrown when a native operation is aborted through its AbortSignal. */
+export class CancelledError extends PayjoinError {
+  constructor(message = 'Operation cancelled', stage?: ErrorStage) {
+    super('Cancelled', message, { stage });
+    this.name = 'CancelledError';
+  }
+}
+
+/** Whether a native error was raised because the operation was cancelled. */
+export function isCancelled(error: unknown): boolean {
+  return (error as { code?: string } | null)?.code === 'Cancelled';
+}
+
+/** The classification of an error the native module throws. */
+interface NativeError {
+  code?: string;
+  stage?: ErrorStage;
+  kind?: string;
+  details?: Record<string, unknown>;
+  message?: string;
+  cause?: unknown;
+}
+
+const ERROR_CODES: ReadonlySet<string> = new Set(Object.values(native.ErrorCode));
+
+/**
+ * Marks the start and end of the classification, as JSON, at the front of the
+ * message of an error the native module throws. napi builds those errors with
+ * no way to add properties.
+ */
+const MARK = '\u0001';
+
+/**
+ * Move the classification a native error carries in its message onto it as
+ * `code`, `stage`, `kind` and `details`, along its chain of causes.
+ */
+function readClassification(error: unknown): void {
+  if (!(error instanceof Error) || !error.message.startsWith(MARK)) {
+    return;
+  }
+  const end = error.message.indexOf(MARK, MARK.length);
+  if (end === -1) {
+    return;
+  }
+  const raw = error.message;
+  const info = JSON.parse(raw.slice(MARK.length, end));
+  const message = raw.slice(end + MARK.length);
+  error.message = message;
+  if (typeof error.stack === 'string') {
+    error.stack = error.stack.replace(raw, message);
+  }
+  Object.assign(error, {
+    code: info.code,
+    stage: info.stage ?? undefined,
+    kind: info.kind,
+    details: info.details,
+  });
+  readClassification((error as { cause?: unknown }).cause);
+}
+
+/**
+ * Convert an error thrown by the native module into a `PayjoinError`, reading
+ * its `code`, `stage`, `kind` and `details`.
+ */
+export function toPayjoinError(error: unknown, context: string): PayjoinError {
+  if (error instanceof PayjoinError) {
+    return error;
+  }
+
+  readClassification(error);
+  const fields: NativeError = typeof error === 'object' && error !== null ? (error as NativeError) : {};
+  const detail = typeof fields.message === 'string' ? fields.message : String(error);
+  if (fields.code === undefined || !ERROR_CODES.has(fields.code)) {
+    if (isCancelled(error)) {
+      return new CancelledError();
+    }
+    return new PayjoinError('Unknown', `${context}: ${detail}`, { cause: error });
+  }
+
+  const code = fields.code as ErrorCode;
+  const { stage, details } = fields;
+  if (code === 'Cancelled') {
+    return new CancelledError(detail, stage);
+  }
+  // A failing callback's own exception is attached to the native error as its
+  // cause; surface that rather than the native wrapper.
+  const cause = code === 'CallbackFailed' && fields.cause !== undefined ? fields.cause : error;
+  const options = { stage, kind: fields.kind, cause };
+  if (details && typeof details.errorCode === 'string') {
+    return new ReceiverError(
+      code,
+      `${context}: ${detail}`,
+      details as { errorCode: string; receiverMessage?: string; supported?: number[] },
+      options
+    );
+  }
+  return new PayjoinError(code, `${context}: ${detail}`, { ...options, details });
+}
+
+/**
+ * Convert a satoshi amount to the bigint the native module takes. Numbers are
+ * accepted when they are safe integers; fractional or imprecise ones are
+ * refused rather than rounded. The native side refuses negative amounts and
+ * amounts above the 21 million bitcoin supply.
+ */
+export function toSats(value: bigint | number, context: string, stage?: ErrorStage): bigint {
+  if (typeof value === 'bigint') {
+    return value;
+  }
+  if (!Number.isInteger(value)) {
+    throw new PayjoinError('InvalidAmount', `${context}: ${value} is not a whole number of satoshis`, {
+      stage,
+    });
+  }
+  if (!Number.isSafeInteger(value)) {
+    throw new PayjoinError('InvalidAmount', `${context}: ${value} cannot be represented exactly, pass a bigint`, {
+      stage,
+    });
+  }
+  return BigInt(value);
+}
+
+/**
+ * Run a native operation with a CancellationToken bridged from `signal`, so
+ * it stops when the signal aborts. The abort listener is removed once the
+ * operation settles, so a long-lived signal does not keep every finished
+ * operation's token alive.
+ */
+export async function withCancellation<T>(
+  signal: AbortSignal | undefined,
+  run: (token: any) => Promise<T> | T
+): Promise<T> {
+  if (!signal) {
+    return run(undefined);
+  }
+
+  const token = new native.CancellationToken();
+  const cancel = () => token.cancel();
+  if (signal.aborted) {
+    token.cancel();
+  } else {
+    signal.addEventListener('abort', cancel, { once: true });
+  }
+  try {
+    return await run(token);
+  } finally {
+    signal.removeEventListener('abort', cancel);
+  }
+}
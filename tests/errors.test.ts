@@ -0,0 +1,279 @@
+import native from '../src/native';
+import {
+    BtcUri,
+    CancelledError,
+    PayjoinError,
+    PayjoinOhttpKeys,
+    PayjoinSenderBuilder,
+    ReceiverError,
+    UriBuilder,
+    toPayjoinError,
+} from '../src/index';
+import { originalPsbt, receiverAddress, uncheckedProposal } from './fixtures';
+
+function catchError(fn: () => unknown): PayjoinError {
+    try {
+        fn();
+    } catch (error) {
+        return error as PayjoinError;
+    }
+    throw new Error('Expected function to throw');
+}
+
+describe('Error codes', () => {
+    const validAddress = 'bc1qw508d6qejxtdg4y5r3zarvary0c5xw7kv8f3t4';
+    const validEndpoint = 'https://example.com';
+
+    it('should expose a stable set of codes', () => {
+        expect(Object.values(native.ErrorCode)).toEqual([
+            'InvalidAddress',
+            'InvalidAmount',
+            'InvalidFeeRate',
+            'InvalidInput',
+            'InvalidOhttpKeys',
+            'InvalidPsbt',
+            'InvalidScript',
+            'InvalidUri',
+            'InvalidUrl',
+            'InvalidOptions',
+            'UnsupportedUri',
+            'CreateRequestFailed',
+            'SessionExpired',
+            'MissingContext',
+            'NetworkError',
+            'HttpStatus',
+            'InvalidResponse',
+            'ProposalRejected',
+            'ReceiverUnavailable',
+            'NotEnoughMoney',
+            'VersionUnsupported',
+            'OriginalPsbtRejected',
+            'UnrecognizedReceiverError',
+            'ReceiverFailed',
+            'CallbackFailed',
+            'SerializationFailed',
+            'Cancelled',
+            'Unknown',
+        ]);
+    });
+
+    it('should read the classification a native error carries', () => {
+        const thrown = catchError(() => native.WantsInputsWrapper.fromJson('not json'));
+        expect(thrown).not.toBeInstanceOf(PayjoinError);
+        const error = toPayjoinError(thrown, 'Failed to restore proposal');
+        expect(error).toBeInstanceOf(PayjoinError);
+        expect(error.code).toBe('SerializationFailed');
+        expect(error.stage).toBe('Persistence');
+        expect(error.kind).toBe('SerdeJsonError');
+        expect(error.message).toMatch(/^Failed to restore proposal: Failed to deserialize proposal: /);
+        expect((error as { cause?: unknown }).cause).toBe(thrown);
+        expect(thrown.message).toMatch(/^Failed to deserialize proposal: /);
+        expect(thrown.stack).toMatch(/^Error: Failed to deserialize proposal: /);
+    });
+
+    it('should report an invalid address', () => {
+        const error = catchError(() => new UriBuilder('invalid-address', validEndpoint));
+        expect(error).toBeInstanceOf(PayjoinError);
+        expect(error.code).toBe('InvalidAddress');
+        expect(error.stage).toBe('Uri');
+        expect(error.kind).toBe('AddressParseError');
+        expect(error.message).toContain('Failed to create PayjoinUriBuilder');
+    });
+
+    it('should report an invalid endpoint URL', () => {
+        const error = catchError(() => new UriBuilder(validAddress, 'not-a-url'));
+        expect(error.code).toBe('InvalidUrl');
+        expect(error.stage).toBe('Uri');
+        expect(error.kind).toBe('UrlParseError');
+    });
+
+    it('should report an unparseable BIP21 URI', () => {
+        const error = catchError(() => BtcUri.tryFrom('not a uri'));
+        expect(error.code).toBe('InvalidUri');
+        expect(error.stage).toBe('Uri');
+    });
+
+    it('should report an invalid PSBT', () => {
+        const uri = new UriBuilder(validAddress, validEndpoint).build();
+        const error = catchError(() => PayjoinSenderBuilder.fromPsbtAndUri('not-a-psbt', uri));
+        expect(error.code).toBe('InvalidPsbt');
+        expect(error.stage).toBe('SenderBuilder');
+        expect(error.kind).toBe('PsbtParseError');
+    });
+
+    it.each<[string, number, string]>([
+        ['a negative', -1, 'fee rate is negative'],
+        ['a NaN', NaN, 'fee rate is not a finite number'],
+        ['an infinite', Infinity, 'fee rate is not a finite number'],
+        ['an overflowing', 1e300, 'fee rate is too high'],
+    ])('should report %s fee rate instead of truncating it', async (_, rate, reason) => {
+        const uri = `bitcoin:${receiverAddress}?amount=0.02&pj=${validEndpoint}`;
+        const builder = PayjoinSenderBuilder.fromPsbtAndUri(originalPsbt, uri);
+
+        for (const build of [
+            () => builder.buildRecommended(rate),
+            () => builder.buildWithAdditionalFee(1000n, null, rate, false),
+        ]) {
+            const error = await build().catch(e => e);
+            expect(error).toBeInstanceOf(PayjoinError);
+            expect(error.code).toBe('InvalidFeeRate');
+            expect(error.stage).toBe('SenderBuilder');
+            expect(error.message).toContain(`Invalid fee rate: ${reason}`);
+        }
+
+        const proposal = await uncheckedProposal()
+            .checkBroadcastSuitability(rate, () => true)
+            .catch(e => e);
+        expect(proposal.code).toBe('InvalidFeeRate');
+        expect(proposal.stage).toBe('Proposal');
+        expect(proposal.message).toContain(`Invalid fee rate: ${reason}`);
+    });
+
+    it('should keep a fractional fee rate to the sat/kwu', async () => {
+        const proposal = await uncheckedProposal().checkBroadcastSuitability(1.5, () => true);
+        expect(JSON.parse(proposal.toJson()).data.steps[0].minFeeRateSatPerKwu).toBe(375);
+    });
+
+    it('should report invalid OHTTP keys', async () => {
+        const error = await PayjoinOhttpKeys.fromBytes(new Uint8Array([1, 2, 3])).catch(e => e);
+        expect(error).toBeInstanceOf(PayjoinError);
+        expect(error.code).toBe('InvalidOhttpKeys');
+        expect(error.stage).toBe('OhttpKeys');
+    });
+
+    it('should report an invalid relay URL when fetching keys', async () => {
+        const error = await PayjoinOhttpKeys.fetch('not-a-url', 'https://payjo.in').catch(e => e);
+        expect(error.code).toBe('InvalidUrl');
+        expect(error.stage).toBe('OhttpKeys');
+        expect(error.message).toContain('Invalid relay URL');
+    });
+
+    it('should report cancellation as a CancelledError', async () => {
+        const controller = new AbortController();
+        controller.abort();
+        const error = await PayjoinOhttpKeys.fetch(
+            'https://pj.bobspacebkk.com',
+            'https://payjo.in',
+            controller.signal
+        ).catch(e => e);
+        expect(error).toBeInstanceOf(CancelledError);
+        expect(error).toBeInstanceOf(PayjoinError);
+        expect(error.code).toBe('Cancelled');
+        expect(error.stage).toBe('OhttpKeys');
+    });
+
+    describe('callback failures', () => {
+        it('should keep the exception a callback threw as the cause', () => {
+            class WalletError extends Error {}
+            const thrown = new WalletError('wallet is locked');
+            const error = toPayjoinError(
+                Object.assign(new Error('Failed to check inputs: wallet is locked'), {
+                    code: 'CallbackFailed',
+                    stage: 'Proposal',
+                    cause: thrown,
+                }),
+                'Failed to check inputs not owned'
+            );
+            expect(error.code).toBe('CallbackFailed');
+            expect(error.stage).toBe('Proposal');
+            expect((error as { cause?: unknown }).cause).toBe(thrown);
+            expect((error as { cause?: unknown }).cause).toBeInstanceOf(WalletError);
+        });
+
+        it('should keep the exception thrown from a real broadcast check', async () => {
+            class MempoolError extends Error {
+                constructor(message: string) {
+                    super(message);
+                    this.name = 'MempoolError';
+                }
+            }
+            const thrown = new MempoolError('mempool unavailable');
+            const stack = thrown.stack;
+
+            const error = await uncheckedProposal()
+                .checkBroadcastSuitability(null, () => {
+                    throw thrown;
+                })
+                .catch(e => e);
+
+            expect(error).toBeInstanceOf(PayjoinError);
+            expect(error.code).toBe('CallbackFailed');
+            expect(error.stage).toBe('Proposal');
+            expect(error.message).toContain('MempoolError: mempool unavailable');
+            expect(error.cause).toBe(thrown);
+            expect(error.cause).toBeInstanceOf(MempoolError);
+            expect(error.cause.stack).toBe(stack);
+        });
+
+        it('should keep the native error as the cause of other failures', () => {
+            const native = Object.assign(new Error('Failed to check inputs: owned'), {
+                code: 'ProposalRejected',
+                stage: 'Proposal',
+                kind: 'Server',
+            });
+            const error = toPayjoinError(native, 'Failed to check inputs not owned');
+            expect(error.code).toBe('ProposalRejected');
+            expect((error as { cause?: unknown }).cause).toBe(native);
+        });
+    });
+
+    describe('receiver replies', () => {
+        it('should surface version-unsupported with the supported versions', () => {
+            const error = toPayjoinError(
+                Object.assign(
+                    new Error(
+                        'Failed to process v2 response: This version of payjoin is not supported. Use version [1].'
+                    ),
+                    {
+                        code: 'VersionUnsupported',
+                        stage: 'Sender',
+                        kind: 'WellKnownError',
+                        details: { errorCode: 'version-unsupported', receiverMessage: 'use v1', supported: [1] },
+                    }
+                ),
+                'Failed to process response'
+            );
+            expect(error).toBeInstanceOf(ReceiverError);
+            const receiverError = error as ReceiverError;
+            expect(receiverError.code).toBe('VersionUnsupported');
+            expect(receiverError.errorCode).toBe('version-unsupported');
+            expect(receiverError.receiverMessage).toBe('use v1');
+            expect(receiverError.supported).toEqual([1]);
+            expect(receiverError.message).toBe(
+                'Failed to process response: Failed to process v2 response: ' +
+                'This version of payjoin is not supported. Use version [1].'
+            );
+        });
+
+        it('should surface unavailable as a retryable receiver error', () => {
+            const error = toPayjoinError(
+                Object.assign(
+                    new Error('Failed to process response: The payjoin endpoint is not available for now.'),
+                    {
+                        code: 'ReceiverUnavailable',
+                        stage: 'Sender',
+                        kind: 'WellKnownError',
+                        details: { errorCode: 'unavailable', receiverMessage: '' },
+                    }
+                ),
+                'Failed to send v2 payjoin'
+            );
+            expect(error).toBeInstanceOf(ReceiverError);
+            expect(error.code).toBe('ReceiverUnavailable');
+            expect((error as ReceiverError).supported).toBeUndefined();
+        });
+
+        it('should keep invalid responses as plain payjoin errors', () => {
+            const error = toPayjoinError(
+                Object.assign(
+                    new Error('Failed to process v1 response: The receiver sent an invalid response.'),
+                    { code: 'InvalidResponse', stage: 'Sender', kind: 'ValidationError' }
+                ),
+                'Failed to process response'
+            );
+            expect(error).not.toBeInstanceOf(ReceiverError);
+            expect(error.code).toBe('InvalidResponse');
+            expect(error.kind).toBe('ValidationError');
+        });
+    });
+});
@@ -0,0 +1,365 @@
+import crypto from 'crypto';
+import {
+    PayjoinReceiver,
+    PayjoinRequest,
+    PayjoinSenderBuilder,
+    UncheckedProposal,
+    WantsInputs,
+} from '../src/index';
+
+// An OHTTP key configuration for the secp256k1 generator point.
+export const ohttpKeys = Uint8Array.from(
+    Buffer.from(
+        '01001604' +
+            '79be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798' +
+            '483ada7726a3c4655da4fbfc0e1108a8fd17b448a68554199c47d08ffb10d4b8' +
+            '000400010003',
+        'hex'
+    )
+);
+
+// The original PSBT from the BIP78 test vectors. It spends one P2SH-P2WPKH
+// input worth 97983400 sats, pays 2000000 sats to `receiverAddress` at vout 1
+// and sends the rest back as change at vout 0.
+export const originalPsbt =
+    'cHNidP8BAHMCAAAAAY8nutGgJdyYGXWiBEb45Hoe9lWGbkxh/6bNiOJdCDuDAAAAAAD+////AtyVuAUAAAAAF6kUHehJ8GnSdBUOOv6ujXLrWmsJRDCHgIQeAAAAAAAXqRR3QJbbz0hnQ8IvQ0fptGn+votneofTAAAAAAEBIKgb1wUAAAAAF6kU3k4ekGHKWRNbA1rV5tR5kEVDVNCHAQcXFgAUx4pFclNVgo1WWAdN1SYNX8tphTABCGsCRzBEAiB8Q+A6dep+Rz92vhy26lT0AjZn4PRLi8Bf9qoB/CMk0wIgP/Rj2PWZ3gEjUkTlhDRNAQ0gXwTO7t9n+V14pZ6oljUBIQMVmsAaoNWHVMS02LfTSe0e388LNitPa1UQZyOihY+FFgABABYAFEb2Giu6c4KO5YW0pfw3lGp9jMUUAAA=';
+
+export const receiverAddress = '3CZZi7aWFugaCdUCS15dgrUUViupmB8bVM';
+
+export function newReceiver(
+    relay = 'https://relay.example.com',
+    expirySeconds?: bigint
+): PayjoinReceiver {
+    return new PayjoinReceiver(
+        receiverAddress,
+        'https://directory.example.com',
+        ohttpKeys,
+        relay,
+        expirySeconds
+    );
+}
+
+/** The request a v1 sender of `originalPsbt` posts to the receiver. */
+export async function v1Request(): Promise<PayjoinRequest> {
+    const uri = `bitcoin:${receiverAddress}?amount=0.02&pj=https://example.com`;
+    const sender = await PayjoinSenderBuilder.fromPsbtAndUri(originalPsbt, uri).buildRecommended(1);
+    return sender.extractV1();
+}
+
+function varint(value: number): number[] {
+    return value < 64 ? [value] : [0x40 | (value >> 8), value & 0xff];
+}
+
+function le(value: number | bigint, bytes: 4 | 8): Buffer {
+    const buffer = Buffer.alloc(bytes);
+    if (bytes === 4) {
+        buffer.writeUInt32LE(Number(value));
+    } else {
+        buffer.writeBigUInt64LE(BigInt(value));
+    }
+    return buffer;
+}
+
+function withLength(hex: string): Buffer {
+    return Buffer.concat([Buffer.from([hex.length / 2]), Buffer.from(hex, 'hex')]);
+}
+
+export const reusedInputs = [
+    { outpoint: `${'aa'.repeat(32)}:0`, value: 1_000_000n },
+    { outpoint: `${'bb'.repeat(32)}:1`, value: 2_000_000n },
+];
+
+/**
+ * An unsigned PSBT whose two `reusedInputs` both spend the script of
+ * `originalPsbt`'s input, paying 2900000 sats to `receiverAddress`.
+ */
+export function reusedScriptPsbt(): string {
+    const spent = 'a914de4e1e9061ca59135b035ad5e6d47990454354d087';
+    const paid = 'a914774096dbcf486743c22f4347e9b469febe8b677a87';
+    const inputs = reusedInputs.map(({ outpoint }) => {
+        const [txid, vout] = outpoint.split(':');
+        return Buffer.concat([
+            Buffer.from(txid, 'hex').reverse(),
+            le(Number(vout), 4),
+            Buffer.from('00fdffffff', 'hex'),
+        ]);
+    });
+    const tx = Buffer.concat([
+        le(2, 4),
+        Buffer.from([inputs.length]),
+        ...inputs,
+        Buffer.from([1]),
+        le(2_900_000n, 8),
+        withLength(paid),
+        le(0, 4),
+    ]);
+    const witnessUtxos = reusedInputs.map(({ value }) => {
+        const utxo = Buffer.concat([le(value, 8), withLength(spent)]);
+        return Buffer.concat([Buffer.from([0x01, 0x01, utxo.length]), utxo, Buffer.from([0x00])]);
+    });
+    return Buffer.concat([
+        Buffer.from('70736274ff0100', 'hex'),
+        Buffer.from([tx.length]),
+        tx,
+        Buffer.from([0x00]),
+        ...witnessUtxos,
+        Buffer.from([0x00]),
+    ]).toString('base64');
+}
+
+/**
+ * The directory's response carrying `psbt` from a v1 sender with `query` as
+ * its parameters, base64 encoded as persisted proposals hold it.
+ */
+export function sealedProposal(query = 'v=1', psbt = originalPsbt): string {
+    const content = Buffer.from(`${psbt}\n${query}`);
+    // A known-length binary HTTP 200 response, padded to the size the
+    // directory replies with.
+    const response = Buffer.alloc(8192 - 48);
+    Buffer.from([0x01, ...varint(200), 0x00, ...varint(content.length), ...content, 0x00]).copy(response);
+    return response.toString('base64');
+}
+
+/**
+ * A proposal as the fixture receiver would have polled it from the directory:
+ * `psbt` sent by a v1 sender with `query` as its parameters.
+ */
+export function uncheckedProposal(query = 'v=1', psbt = originalPsbt): UncheckedProposal {
+    const session = JSON.parse(newReceiver().toJson());
+    return UncheckedProposal.fromJson(
+        JSON.stringify({
+            ...session,
+            state: 'UncheckedProposal',
+            data: { session: session.data, proposal: sealedProposal(query, psbt), steps: [] },
+        })
+    );
+}
+
+/**
+ * The fixture proposal taken through every check up to input contribution,
+ * with vout 1 as the receiver's output.
+ */
+export async function wantsInputs(query = 'v=1'): Promise<WantsInputs> {
+    const proposal = await uncheckedProposal(query).checkBroadcastSuitability(null, () => true);
+    const unseen = await proposal.checkInputsNotOwned(() => false);
+    const unknown = await unseen.checkNoInputsSeenBefore(() => false);
+    const outputs = await unknown.identifyReceiverOutputs((_, output) => output.vout === 1);
+    return outputs.commitOutputs();
+}
+
+// Enough of a directory to read what the fixture receiver and senders send
+// it and to answer them: OHTTP (RFC 9458) under the fixture key, whose secret
+// is 1, and known-length binary HTTP (RFC 9292).
+const KEM_ID = Buffer.from([0x00, 0x16]);
+const KEM_SUITE = Buffer.concat([Buffer.from('KEM'), KEM_ID]);
+const HPKE_SUITE = Buffer.concat([Buffer.from('HPKE'), KEM_ID, Buffer.from([0x00, 0x01, 0x00, 0x03])]);
+const EMPTY = Buffer.alloc(0);
+
+function hmac(key: Buffer, data: Buffer): Buffer {
+    return crypto.createHmac('sha256', key).update(data).digest();
+}
+
+function labeledExtract(suite: Buffer, salt: Buffer, label: string, ikm: Buffer): Buffer {
+    return hmac(salt, Buffer.concat([Buffer.from('HPKE-v1'), suite, Buffer.from(label), ikm]));
+}
+
+function labeledExpand(suite: Buffer, prk: Buffer, label: string, info: Buffer, length: number): Buffer {
+    const labeledInfo = Buffer.concat([
+        Buffer.from([length >> 8, length & 0xff]),
+        Buffer.from('HPKE-v1'),
+        suite,
+        Buffer.from(label),
+        info,
+    ]);
+    let block = EMPTY;
+    let okm = EMPTY;
+    for (let counter = 1; okm.length < length; counter++) {
+        block = hmac(prk, Buffer.concat([block, labeledInfo, Buffer.from([counter])]));
+        okm = Buffer.concat([okm, block]);
+    }
+    return okm.subarray(0, length);
+}
+
+interface Decapsulated {
+    enc: Buffer;
+    exporterSecret: Buffer;
+    message: Buffer;
+}
+
+function decapsulate(request: Uint8Array): Decapsulated {
+    const message = Buffer.from(request);
+    const header = message.subarray(0, 7);
+    const enc = message.subarray(7, 72);
+    const sealed = message.subarray(72);
+
+    const directory = crypto.createECDH('secp256k1');
+    directory.setPrivateKey(Buffer.alloc(32).fill(1, 31));
+    const eaePrk = labeledExtract(KEM_SUITE, EMPTY, 'eae_prk', directory.computeSecret(enc));
+    const kemContext = Buffer.concat([enc, directory.getPublicKey()]);
+    const sharedSecret = labeledExpand(KEM_SUITE, eaePrk, 'shared_secret', kemContext, 32);
+
+    const info = Buffer.concat([Buffer.from('message/bhttp request\0'), header]);
+    const context = Buffer.concat([
+        Buffer.from([0x00]),
+        labeledExtract(HPKE_SUITE, EMPTY, 'psk_id_hash', EMPTY),
+        labeledExtract(HPKE_SUITE, EMPTY, 'info_hash', info),
+    ]);
+    const secret = labeledExtract(HPKE_SUITE, sharedSecret, 'secret', EMPTY);
+    const key = labeledExpand(HPKE_SUITE, secret, 'key', context, 32);
+    const nonce = labeledExpand(HPKE_SUITE, secret, 'base_nonce', context, 12);
+    const exporterSecret = labeledExpand(HPKE_SUITE, secret, 'exp', context, 32);
+
+    const decipher = crypto.createDecipheriv('chacha20-poly1305', key, nonce, { authTagLength: 16 });
+    decipher.setAuthTag(sealed.subarray(sealed.length - 16));
+    const message = Buffer.concat([decipher.update(sealed.subarray(0, sealed.length - 16)), decipher.final()]);
+    return { enc, exporterSecret, message };
+}
+
+/**
+ * The directory's empty answer to `request` with `status`, encapsulated and
+ * padded to the size payjoin expects.
+ */
+export function directoryReply(request: Uint8Array, status = 200): Uint8Array {
+    const { enc, exporterSecret } = decapsulate(request);
+    const secret = labeledExpand(HPKE_SUITE, exporterSecret, 'sec', Buffer.from('message/bhttp response'), 32);
+    const responseNonce = crypto.randomBytes(32);
+    const prk = hmac(Buffer.concat([enc, responseNonce]), secret);
+    const key = hmac(prk, Buffer.from('key\x01'));
+    const nonce = hmac(prk, Buffer.from('nonce\x01')).subarray(0, 12);
+
+    const response = Buffer.alloc(8192 - responseNonce.length - 16);
+    Buffer.from([0x01, ...varint(status), 0x00, 0x00, 0x00]).copy(response);
+    const cipher = crypto.createCipheriv('chacha20-poly1305', key, nonce, { authTagLength: 16 });
+    return Buffer.concat([responseNonce, cipher.update(response), cipher.final(), cipher.getAuthTag()]);
+}
+
+export interface DirectoryRequest {
+    method: string;
+    url: string;
+    body: Buffer;
+}
+
+/** Open a request sent to the directory through the OHTTP relay. */
+export function openDirectoryRequest(request: Uint8Array): DirectoryRequest {
+    const { message } = decapsulate(request);
+    let offset = 0;
+    const readVarint = (): number => {
+        const length = 1 << (message[offset] >> 6);
+        let value = message[offset] & 0x3f;
+        for (let i = 1; i < length; i++) {
+            value = value * 256 + message[offset + i];
+        }
+        offset += length;
+        return value;
+    };
+    const readField = (): Buffer => {
+        const length = readVarint();
+        offset += length;
+        return message.subarray(offset - length, offset);
+    };
+
+    if (readVarint() !== 0) {
+        throw new Error('Expected a known-length request');
+    }
+    const method = readField().toString();
+    const scheme = readField().toString();
+    const authority = readField().toString();
+    const path = readField().toString();
+    readField(); // header fields
+    return { method, url: `${scheme}://${authority}${path}`, body: readField() };
+}
+
+// States as format 1 writes them. Every release must read these back and
+// write them out unchanged; a change to the persisted form needs a new
+// format and a migration.
+export const persistedReceiver =
+    '{"format":1,"crateVersion":"0.1.0","state":"PayjoinReceiver","data":{"context":{"address":"3CZZi7aWFugaCdUCS15dgrUUViupmB8bVM","directory":"https://directory.example.com/","subdirectory":null,"ohttp_keys":[1,0,22,4,121,190,102,126,249,220,187,172,85,160,98,149,206,135,11,7,2,155,252,219,45,206,40,217,89,242,129,91,22,248,23,152,72,58,218,119,38,163,196,101,93,164,251,252,14,17,8,168,253,23,180,72,166,133,84,25,156,71,208,143,251,16,212,184,0,4,0,1,0,3],"expiry":{"secs_since_epoch":4102444800,"nanos_since_epoch":0},"ohttp_relay":"https://relay.example.com/","s":[[206,178,63,54,227,62,221,17,233,6,68,8,168,212,206,80,15,144,184,183,38,242,254,53,131,46,135,159,205,114,131,182],[4,186,185,64,129,226,119,152,211,111,109,251,18,235,82,28,143,62,207,223,33,159,112,164,26,175,222,126,21,123,101,7,237,244,71,22,139,111,41,28,182,111,250,8,9,32,73,80,129,107,247,45,49,189,91,189,231,194,251,122,175,147,212,204,104]],"e":null}}}';
+
+export const persistedSender =
+    '{"format":1,"crateVersion":"0.1.0","state":"PayjoinSender","data":{"psbt":{"unsigned_tx":{"version":2,"lock_time":211,"input":[{"previous_output":"833b085de288cda6ff614c6e8655f61e7ae4f84604a2751998dc25a0d1ba278f:0","script_sig":"","sequence":4294967294,"witness":[]}],"output":[{"value":95983068,"script_pubkey":"a9141de849f069d274150e3afeae8d72eb5a6b09443087"},{"value":2000000,"script_pubkey":"a914774096dbcf486743c22f4347e9b469febe8b677a87"}]},"version":0,"xpub":{},"proprietary":[],"unknown":[],"inputs":[{"non_witness_utxo":null,"witness_utxo":{"value":97983400,"script_pubkey":"a914de4e1e9061ca59135b035ad5e6d47990454354d087"},"partial_sigs":{},"sighash_type":null,"redeem_script":null,"witness_script":null,"bip32_derivation":[],"final_script_sig":"160014c78a45725355828d5658074dd5260d5fcb698530","final_script_witness":["304402207c43e03a75ea7e473f76be1cb6ea54f4023667e0f44b8bc05ff6aa01fc2324d302203ff463d8f599de01235244e584344d010d205f04ceeedf67f95d78a59ea8963501","03159ac01aa0d58754c4b4d8b7d349ed1edfcf0b362b4f6b55106723a2858f8516"],"ripemd160_preimages":{},"sha256_preimages":{},"hash160_preimages":{},"hash256_preimages":{},"tap_key_sig":null,"tap_script_sigs":[],"tap_scripts":[],"tap_key_origins":[],"tap_internal_key":null,"tap_merkle_root":null,"proprietary":[],"unknown":[]}],"outputs":[{"redeem_script":"001446f61a2bba73828ee585b4a5fc37946a7d8cc514","witness_script":null,"bip32_derivation":[],"tap_internal_key":null,"tap_tree":null,"tap_key_origins":[],"proprietary":[],"unknown":[]},{"redeem_script":null,"witness_script":null,"bip32_derivation":[],"tap_internal_key":null,"tap_tree":null,"tap_key_origins":[],"proprietary":[],"unknown":[]}]},"endpoint":"https://directory.example.com/QY9LSRUMC8HVU#RK1Q2ATJSYPUFME35M0DHA3966JRJ8NAN7LYX0HPFQ64L08U9TMV5R76+OH1QYP8N0NX0MUAEWAV2KSX99WWSU9SWQ5MLNDJMN3GM9VL9Q2MZMUP0XQ+EX1QPTCDAQ","disable_output_substitution":false,"fee_contribution":[91,0],"min_fee_rate":250,"payee":"a914774096dbcf486743c22f4347e9b469febe8b677a87","reply_key":[89,88,144,227,28,41,65,139,207,14,193,38,40,99,165,36,0,165,52,92,77,192,191,18,121,217,72,97,103,90,29,58]}}';
+
+/** A `WantsInputs` proposal of `persistedReceiver`'s session, as format 1 writes it. */
+export function persistedProposal(): string {
+    const session = JSON.parse(persistedReceiver);
+    return JSON.stringify({
+        ...session,
+        state: 'WantsInputs',
+        data: {
+            session: session.data,
+            proposal: sealedProposal('v=2'),
+            steps: [
+                {
+                    step: 'checkBroadcastSuitability',
+                    minFeeRateSatPerKwu: null,
+                    answers: [true],
+                },
+                { step: 'checkInputsNotOwned', answers: [false] },
+                { step: 'checkNoInputsSeenBefore', answers: [false] },
+                { step: 'identifyReceiverOutputs', answers: [false, true] },
+                {
+                    step: 'replaceReceiverOutputs',
+                    outputs: [
+                        {
+                            value: 2000000,
+                            script_pubkey: 'a914774096dbcf486743c22f4347e9b469febe8b677a87',
+                        },
+                        {
+                            value: 50000,
+                            script_pubkey: '00143333333333333333333333333333333333333333',
+                        },
+                    ],
+                    drainScript: 'a914774096dbcf486743c22f4347e9b469febe8b677a87',
+                    order: [
+                        '00143333333333333333333333333333333333333333',
+                        'a9141de849f069d274150e3afeae8d72eb5a6b09443087',
+                        'a914774096dbcf486743c22f4347e9b469febe8b677a87',
+                    ],
+                },
+                { step: 'commitOutputs' },
+                {
+                    step: 'contributeInputs',
+                    inputs: [
+                        [
+                            {
+                                previous_output: 'aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa:0',
+                                script_sig: '',
+                                sequence: 4294967295,
+                                witness: [],
+                            },
+                            {
+                                non_witness_utxo: null,
+                                witness_utxo: {
+                                    value: 3000000,
+                                    script_pubkey: '00142222222222222222222222222222222222222222',
+                                },
+                                partial_sigs: {},
+                                sighash_type: null,
+                                redeem_script: null,
+                                witness_script: null,
+                                bip32_derivation: [],
+                                final_script_sig: null,
+                                final_script_witness: null,
+                                ripemd160_preimages: {},
+                                sha256_preimages: {},
+                                hash160_preimages: {},
+                                hash256_preimages: {},
+                                tap_key_sig: null,
+                                tap_script_sigs: [],
+                                tap_scripts: [],
+                                tap_key_origins: [],
+                                tap_internal_key: null,
+                                tap_merkle_root: null,
+                                proprietary: [],
+                                unknown: [],
+                            },
+                        ],
+                    ],
+                    order: [
+                        'aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa:0',
+                        '833b085de288cda6ff614c6e8655f61e7ae4f84604a2751998dc25a0d1ba278f:0',
+                    ],
+                },
+            ],
+        },
+    });
+}
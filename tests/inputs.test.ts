@@ -0,0 +1,138 @@
+import { PayjoinError, validateInputPair } from '../src/index';
+import { IInputPairRequest, IPsbtInputData } from '../src/types';
+import { wantsInputs } from './fixtures';
+
+const bytes = (hex: string) => Uint8Array.from(Buffer.from(hex, 'hex'));
+
+// Input 1 of the BIP174 vectors: a 2-of-2 P2SH-P2WSH multisig, with the
+// Signer's signature for its first key.
+const firstKey = '03089dc10c7ac6db54f91329af617333db388cead0c231f723379d1b99030b02dc';
+const secondKey = '023add904f3d6dcf59ddb906b0dee23529b7ffb9ed50e5e86151926860221f0e73';
+const redeemScript = '00208c2353173743b595dfb4a07b72ba8e42e3797da74e87fe7d9d7497e3b2028903';
+const witnessScript = `5221${firstKey}21${secondKey}52ae`;
+const signature =
+    '3044022065f45ba5998b59a27ffe1a7bed016af1f1f90d54b3aa8f7450aa5f56a25103bd02207f724703ad1edb96680b284b56d4ffcb88f7fb759eabbe08aa30f29b851383d2';
+
+const keyOrigin = 'd90c6a4f0000008000000080';
+
+const multisigInput: IInputPairRequest = {
+    prevout: { txid: 'bb'.repeat(32), vout: 1 },
+    psbtData: {
+        witnessUtxo: {
+            amount: 200_000_000n,
+            scriptPubKey: 'a914b7f5faf40e3d40a5a459b1db3535f2b72fa921e887',
+        },
+        redeemScript: bytes(redeemScript),
+        witnessScript: bytes(witnessScript),
+        partialSigs: [{ pubkey: bytes(firstKey), signature: bytes(`${signature}01`) }],
+        sighashType: 1,
+        // m/0'/0'/3' and m/0'/0'/2' under the master key d90c6a4f.
+        bip32Derivation: [
+            { pubkey: bytes(firstKey), fingerprintPath: bytes(keyOrigin), child: 0x80000003 },
+            { pubkey: bytes(secondKey), fingerprintPath: bytes(keyOrigin), child: 0x80000002 },
+        ],
+    },
+};
+
+// The finalized P2SH-P2WPKH input of the BIP78 original PSBT.
+const finalScriptSig = '160014c78a45725355828d5658074dd5260d5fcb698530';
+const finalScriptWitness = [
+    '304402207c43e03a75ea7e473f76be1cb6ea54f4023667e0f44b8bc05ff6aa01fc2324d302203ff463d8f599de01235244e584344d010d205f04ceeedf67f95d78a59ea8963501',
+    '03159ac01aa0d58754c4b4d8b7d349ed1edfcf0b362b4f6b55106723a2858f8516',
+];
+const finalizedInput: IInputPairRequest = {
+    prevout: { txid: 'cc'.repeat(32), vout: 0 },
+    psbtData: {
+        witnessUtxo: {
+            amount: 100_000_000n,
+            scriptPubKey: 'a914de4e1e9061ca59135b035ad5e6d47990454354d087',
+        },
+        redeemScript: bytes('0014c78a45725355828d5658074dd5260d5fcb698530'),
+        finalScriptSig: bytes(finalScriptSig),
+        finalScriptWitness: finalScriptWitness.map(bytes),
+    },
+};
+
+function withPsbtData(psbtData: Partial<IPsbtInputData>): IInputPairRequest {
+    return { ...multisigInput, psbtData: { ...multisigInput.psbtData, ...psbtData } };
+}
+
+function validationError(input: IInputPairRequest): PayjoinError {
+    try {
+        validateInputPair(input);
+    } catch (error) {
+        return error as PayjoinError;
+    }
+    throw new Error('Expected validation to fail');
+}
+
+describe('PSBT input contribution', () => {
+    it('should accept the BIP174 multisig and BIP78 finalized inputs', () => {
+        expect(() => validateInputPair(multisigInput)).not.toThrow();
+        expect(() => validateInputPair(finalizedInput)).not.toThrow();
+    });
+
+    // A contributed input is persisted as the PSBT input it mapped to.
+    it('should carry every field into the contributed PSBT input', async () => {
+        const inputs = (await wantsInputs('v=2')).contributeInputs([multisigInput, finalizedInput]);
+
+        const steps = JSON.parse(inputs.toJson()).data.steps;
+        const [[, multisig], [, finalized]] = steps[steps.length - 1].inputs;
+
+        expect(multisig).toMatchObject({
+            redeem_script: redeemScript,
+            witness_script: witnessScript,
+            partial_sigs: { [firstKey]: { signature, sighash_type: 'SIGHASH_ALL' } },
+            sighash_type: { inner: 1 },
+            bip32_derivation: [
+                [secondKey, ['d90c6a4f', "0'/0'/2'"]],
+                [firstKey, ['d90c6a4f', "0'/0'/3'"]],
+            ],
+        });
+        expect(finalized).toMatchObject({
+            final_script_sig: finalScriptSig,
+            final_script_witness: finalScriptWitness,
+        });
+    });
+
+    it.each<[string, Partial<IPsbtInputData>, string | undefined]>([
+        [
+            'a partial signature key of 4 bytes',
+            { partialSigs: [{ pubkey: bytes(firstKey.slice(0, 8)), signature: bytes(`${signature}01`) }] },
+            'KeyError',
+        ],
+        [
+            'a partial signature that is not DER',
+            { partialSigs: [{ pubkey: bytes(firstKey), signature: bytes('30060201010201') }] },
+            'SignatureError',
+        ],
+        [
+            'a partial signature with an unknown sighash byte',
+            { partialSigs: [{ pubkey: bytes(firstKey), signature: bytes(`${signature}04`) }] },
+            'SignatureError',
+        ],
+        [
+            'a derivation key off the curve',
+            {
+                bip32Derivation: [
+                    { pubkey: bytes(`02${'ff'.repeat(32)}`), fingerprintPath: bytes('d90c6a4f'), child: 0 },
+                ],
+            },
+            'Secp256k1Error',
+        ],
+        [
+            'a derivation fingerprint of 3 bytes',
+            { bip32Derivation: [{ pubkey: bytes(firstKey), fingerprintPath: bytes('d90c6a'), child: 0 }] },
+            undefined,
+        ],
+        ['a non-standard sighash type', { sighashType: 4 }, undefined],
+        ['a P2SH input without its redeem script', { redeemScript: undefined }, 'PsbtInputError'],
+    ])('should reject %s', (_, psbtData, kind) => {
+        const error = validationError(withPsbtData(psbtData));
+
+        expect(error).toBeInstanceOf(PayjoinError);
+        expect(error.code).toBe('InvalidInput');
+        expect(error.stage).toBe('Proposal');
+        expect(error.kind).toBe(kind);
+    });
+});
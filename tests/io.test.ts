@@ -1,4 +1,4 @@
-import { PayjoinOhttpKeys } from '../src/index';
+import { CancelledError, PayjoinOhttpKeys } from '../src/index';
 
 describe('PayjoinOhttpKeys', () => {
     const validRelay = 'https://pj.bobspacebkk.com';
@@ -47,6 +47,34 @@ describe('PayjoinOhttpKeys', () => {
         });
     });
 
+    describe('cancellation', () => {
+        it('should reject with CancelledError when the signal is already aborted', async () => {
+            const controller = new AbortController();
+            controller.abort();
+            await expect(
+                PayjoinOhttpKeys.fetch(validRelay, validDirectory, controller.signal)
+            ).rejects.toBeInstanceOf(CancelledError);
+        });
+
+        it('should reject with CancelledError when aborted in flight', async () => {
+            const controller = new AbortController();
+            const pending = PayjoinOhttpKeys.fetch(validRelay, validDirectory, controller.signal);
+            controller.abort();
+            await expect(pending).rejects.toBeInstanceOf(CancelledError);
+        });
+
+        it('should remove the abort listener once the fetch settles', async () => {
+            const controller = new AbortController();
+            const add = jest.spyOn(controller.signal, 'addEventListener');
+            const remove = jest.spyOn(controller.signal, 'removeEventListener');
+
+            await PayjoinOhttpKeys.fetch('not-a-url', validDirectory, controller.signal).catch(() => undefined);
+
+            expect(add).toHaveBeenCalledTimes(1);
+            expect(remove).toHaveBeenCalledWith('abort', add.mock.calls[0][1]);
+        });
+    });
+
     describe('error handling', () => {
         it('should handle network errors gracefully', async () => {
             const invalidRelay = 'https://invalid.example.com';
@@ -0,0 +1,108 @@
+import {
+    ILogRecord,
+    PayjoinReceiver,
+    clearLogger,
+    setLogRedaction,
+    setLogger,
+} from '../src/index';
+
+describe('Logging', () => {
+    const address = 'bc1qw508d6qejxtdg4y5r3zarvary0c5xw7kv8f3t4';
+    const ohttpKeys = Buffer.from(
+        '010016049726d59d2bca57caa47c761619b3afa9dfb7ebec9ca60d70ac2f87d2cd4672aa' +
+        '6d8cd0e99ce78372f3a70a7cd881c0f4c44cdf18f4a81abe8d148688564e74f1000400010003',
+        'hex'
+    );
+
+    const flush = () => new Promise(resolve => setTimeout(resolve, 50));
+
+    async function processGarbageResponse(): Promise<void> {
+        const receiver = new PayjoinReceiver(
+            address,
+            'https://payjo.in',
+            ohttpKeys,
+            'https://pj.bobspacebkk.com'
+        );
+        const request = receiver.extractRequest();
+        await expect(
+            receiver.processResponse(new Uint8Array(8192), request)
+        ).rejects.toThrow('Failed to process response');
+    }
+
+    afterEach(() => {
+        clearLogger();
+        setLogRedaction('Redacted');
+    });
+
+    it('should forward native records to the callback', async () => {
+        const records: ILogRecord[] = [];
+        setLogger(record => records.push(record), 'Trace');
+
+        await processGarbageResponse();
+        await flush();
+
+        expect(records.length).toBeGreaterThan(0);
+        const record = records[0];
+        expect(record.level).toBe('Trace');
+        expect(record.target).toContain('payjoin');
+        expect(typeof record.message).toBe('string');
+    });
+
+    it('should filter records below the configured level', async () => {
+        const records: ILogRecord[] = [];
+        setLogger(record => records.push(record), 'Error');
+
+        await processGarbageResponse();
+        await flush();
+
+        expect(records).toEqual([]);
+    });
+
+    it('should stop forwarding once cleared', async () => {
+        const records: ILogRecord[] = [];
+        setLogger(record => records.push(record), 'Trace');
+        clearLogger();
+
+        await processGarbageResponse();
+        await flush();
+
+        expect(records).toEqual([]);
+    });
+
+    describe('redaction', () => {
+        function extractRequest(): string {
+            const receiver = new PayjoinReceiver(
+                address,
+                'https://payjo.in',
+                ohttpKeys,
+                'https://pj.bobspacebkk.com'
+            );
+            receiver.extractRequest();
+            return receiver.pjUrl().split('#')[1];
+        }
+
+        it('should redact session keys by default', async () => {
+            const records: ILogRecord[] = [];
+            setLogger(record => records.push(record), 'Trace');
+
+            const fragment = extractRequest();
+            await flush();
+
+            const polling = records.find(r => r.message.startsWith('Polling directory'))!;
+            expect(polling.message).not.toContain(fragment);
+            expect(polling.message).toMatch(/…\[\d+ chars, sha256:[0-9a-f]{8}\]/);
+        });
+
+        it('should log everything in full mode', async () => {
+            const records: ILogRecord[] = [];
+            setLogger(record => records.push(record), 'Trace');
+            setLogRedaction('Full');
+
+            const fragment = extractRequest();
+            await flush();
+
+            const polling = records.find(r => r.message.startsWith('Polling directory'))!;
+            expect(polling.message).toContain(fragment);
+        });
+    });
+});
@@ -0,0 +1,60 @@
+import { IReceiverEvent, PayjoinError, ReceiverManager } from '../src/index';
+import { newReceiver } from './fixtures';
+
+function catchError(fn: () => unknown): PayjoinError {
+    try {
+        fn();
+    } catch (error) {
+        return error as PayjoinError;
+    }
+    throw new Error('Expected function to throw');
+}
+
+describe('ReceiverManager', () => {
+    it('should validate its options', () => {
+        expect(catchError(() => new ReceiverManager({ maxConcurrentPolls: 0 })).code).toBe(
+            'InvalidOptions'
+        );
+        expect(
+            catchError(() => new ReceiverManager({ poll: { backoffMultiplier: 0.5 } })).code
+        ).toBe('InvalidOptions');
+    });
+
+    it('should key sessions by their mailbox', () => {
+        const manager = new ReceiverManager();
+        const receiver = newReceiver();
+
+        const id = manager.add(receiver);
+        expect(receiver.pjUrl()).toContain(id);
+        expect(manager.add(receiver)).toBe(id);
+        expect(manager.sessionIds()).toEqual([id]);
+
+        expect(manager.remove(id)).toBe(true);
+        expect(manager.remove(id)).toBe(false);
+        expect(manager.sessionIds()).toEqual([]);
+    });
+
+    it('should retire expired sessions', async () => {
+        const manager = new ReceiverManager();
+        const id = manager.add(newReceiver(undefined, 0n));
+        const controller = new AbortController();
+        const events: IReceiverEvent[] = [];
+
+        await manager.run(event => {
+            events.push(event);
+            controller.abort();
+        }, controller.signal);
+
+        expect(events).toEqual([{ kind: 'Expired', sessionId: id }]);
+        expect(manager.sessionIds()).toEqual([]);
+    });
+
+    it('should resolve once aborted', async () => {
+        const manager = new ReceiverManager();
+        const controller = new AbortController();
+
+        const running = manager.run(() => undefined, controller.signal);
+        controller.abort();
+        await expect(running).resolves.toBeUndefined();
+    });
+});
@@ -1,4 +1,14 @@
 import native from '../src/native';
+import { toPayjoinError } from '../src/index';
+
+function thrownBy(fn: () => unknown) {
+  try {
+    fn();
+  } catch (error) {
+    return toPayjoinError(error, 'Native call failed');
+  }
+  throw new Error('Expected function to throw');
+}
 
 describe('Native bindings', () => {
   const expectedExports = [
@@ -32,6 +42,35 @@ describe('Native bindings', () => {
     
     // OHTTP functionality
     'PayjoinOhttpKeys',
+
+    // Cancellation
+    'CancellationToken',
+
+    // Multi-session receiving
+    'ReceiverManager',
+    'ReceiverEventKind',
+
+    // Input contribution
+    'validateInputPair',
+    'SelectionFallback',
+    'ContributionBranch',
+
+    // Session storage
+    'SessionStore',
+    'SessionKind',
+
+    // Errors
+    'ErrorCode',
+    'ErrorStage',
+    'WellKnownErrorCode',
+
+    // Logging
+    'LogLevel',
+    'LogRedaction',
+    'setLogger',
+    'setLogRedaction',
+    'setLogLevel',
+    'clearLogger',
   ];
 
   const expectedTypes = {
@@ -61,6 +100,56 @@ describe('Native bindings', () => {
     expect(native.PayjoinOhttpKeys.prototype.constructor).toBeDefined();
   });
 
+  it('should expose Promise-based receiver transitions', () => {
+    expect(typeof native.UncheckedProposalWrapper.prototype.checkBroadcastSuitabilityAsync).toBe('function');
+    expect(typeof native.MaybeInputsOwnedWrapper.prototype.checkInputsNotOwnedAsync).toBe('function');
+    expect(typeof native.MaybeInputsSeenWrapper.prototype.checkNoInputsSeenBeforeAsync).toBe('function');
+    expect(typeof native.MaybeInputsOwnedWrapper.prototype.checkInputsNotOwnedBatch).toBe('function');
+    expect(typeof native.MaybeInputsSeenWrapper.prototype.checkNoInputsSeenBeforeBatch).toBe('function');
+    expect(typeof native.OutputsUnknownWrapper.prototype.identifyReceiverOutputsAsync).toBe('function');
+    expect(typeof native.ProvisionalProposalWrapper.prototype.finalizeProposalAsync).toBe('function');
+  });
+
+  it('should expose each step of input contribution', () => {
+    const proto = native.WantsInputsWrapper.prototype;
+    expect(typeof proto.selectPrivacyPreservingInput).toBe('function');
+    expect(typeof proto.contributeInputs).toBe('function');
+    expect(typeof proto.commitInputs).toBe('function');
+    expect(typeof proto.tryContributeInputs).toBe('function');
+    expect(typeof proto.contributeInputsWithFallback).toBe('function');
+  });
+
+  it('should persist every receiver typestate', () => {
+    [
+      'UncheckedProposalWrapper',
+      'MaybeInputsOwnedWrapper',
+      'MaybeInputsSeenWrapper',
+      'OutputsUnknownWrapper',
+      'WantsOutputsWrapper',
+      'WantsInputsWrapper',
+      'ProvisionalProposalWrapper',
+      'PayjoinProposalWrapper',
+    ].forEach(className => {
+      expect(typeof native[className].prototype.toJson).toBe('function');
+      expect(typeof native[className].fromJson).toBe('function');
+    });
+  });
+
+  it('should persist the sender polling context', () => {
+    expect(typeof native.PayjoinV2Context.prototype.toJson).toBe('function');
+    expect(thrownBy(() => native.PayjoinV2Context.fromJson('{}'))).toMatchObject({
+      code: 'SerializationFailed',
+      stage: 'Persistence',
+    });
+  });
+
+  it('should reject a proposal that is not valid JSON', () => {
+    expect(thrownBy(() => native.WantsInputsWrapper.fromJson('not json'))).toMatchObject({
+      code: 'SerializationFailed',
+      stage: 'Persistence',
+    });
+  });
+
   it('should match expected export count', () => {
     const exports = Object.keys(native);
     expect(exports.length).toBe(expectedExports.length);
@@ -0,0 +1,247 @@
+import native from '../src/native';
+import {
+    MaybeInputsOwned,
+    MaybeInputsSeen,
+    OutputsUnknown,
+    PayjoinError,
+    PayjoinReceiver,
+    PayjoinSender,
+    ProvisionalProposal,
+    WantsInputs,
+    WantsOutputs,
+} from '../src/index';
+import {
+    newReceiver,
+    persistedProposal,
+    persistedReceiver,
+    persistedSender,
+    uncheckedProposal,
+} from './fixtures';
+
+function catchError(fn: () => unknown): PayjoinError {
+    try {
+        fn();
+    } catch (error) {
+        return error as PayjoinError;
+    }
+    throw new Error('Expected function to throw');
+}
+
+/**
+ * `json` read back by `restore` and written out again, keeping the release
+ * it was written by so that only the format is compared.
+ */
+function rewritten(json: string, restore: (json: string) => { toJson(): string }): string {
+    const envelope = JSON.parse(restore(json).toJson());
+    return JSON.stringify({ ...envelope, crateVersion: JSON.parse(json).crateVersion });
+}
+
+describe('Session serialization format', () => {
+    it('should write a receiver as format 1 does', () => {
+        expect(rewritten(persistedReceiver, json => PayjoinReceiver.fromJson(json))).toBe(persistedReceiver);
+    });
+
+    it('should write a proposal and its journal as format 1 does', () => {
+        const proposal = persistedProposal();
+        expect(rewritten(proposal, json => WantsInputs.fromJson(json))).toBe(proposal);
+    });
+
+    it('should write a sender as format 1 does', () => {
+        expect(rewritten(persistedSender, json => PayjoinSender.fromJson(json))).toBe(persistedSender);
+    });
+
+    it('should round-trip a receiver without changing its serialized form', () => {
+        const json = newReceiver().toJson();
+        expect(PayjoinReceiver.fromJson(json).toJson()).toBe(json);
+    });
+
+    // Before the envelope, receivers and senders were persisted as their
+    // bare serde output.
+    it('should migrate a receiver and a sender stored before the envelope existed', () => {
+        const receiver = JSON.parse(persistedReceiver);
+        const sender = JSON.parse(persistedSender);
+
+        const migratedReceiver = JSON.parse(
+            PayjoinReceiver.fromJson(JSON.stringify(receiver.data)).toJson()
+        );
+        const migratedSender = JSON.parse(PayjoinSender.fromJson(JSON.stringify(sender.data)).toJson());
+
+        expect(migratedReceiver).toMatchObject({ format: 1, state: 'PayjoinReceiver', data: receiver.data });
+        expect(migratedSender).toMatchObject({ format: 1, state: 'PayjoinSender', data: sender.data });
+    });
+
+    it('should refuse a bare proposal, which no release wrote', () => {
+        const proposal = JSON.parse(persistedProposal());
+
+        const error = catchError(() => WantsInputs.fromJson(JSON.stringify(proposal.data)));
+        expect(error.code).toBe('SerializationFailed');
+    });
+
+    it('should refuse a state stored under a different tag', () => {
+        const envelope = JSON.parse(newReceiver().toJson());
+        const json = JSON.stringify({ ...envelope, state: 'PayjoinSender' });
+
+        const error = catchError(() => PayjoinReceiver.fromJson(json));
+        expect(error.code).toBe('SerializationFailed');
+        expect(error.message).toContain('expected a PayjoinReceiver but found a PayjoinSender');
+    });
+
+    it('should refuse a format newer than this release understands', () => {
+        const envelope = JSON.parse(newReceiver().toJson());
+        const json = JSON.stringify({ ...envelope, format: envelope.format + 1 });
+
+        const error = catchError(() => PayjoinReceiver.fromJson(json));
+        expect(error.code).toBe('SerializationFailed');
+        expect(error.kind).toBe('UnsupportedFormat');
+    });
+
+    it('should tag persisted proposals with their typestate', () => {
+        const error = catchError(() =>
+            native.WantsInputsWrapper.fromJson(
+                JSON.stringify({ format: 1, crateVersion: '0.1.0', state: 'WantsOutputs', data: {} })
+            )
+        );
+        expect(error.message).toContain('expected a WantsInputs but found a WantsOutputs');
+    });
+});
+
+describe('Proposal persistence', () => {
+    it('should continue a proposal restored at every step', async () => {
+        const broadcastable = await uncheckedProposal().checkBroadcastSuitability(null, () => true);
+        const owned = MaybeInputsOwned.fromJson(broadcastable.toJson());
+        expect(owned.toJson()).toBe(broadcastable.toJson());
+
+        const seen = MaybeInputsSeen.fromJson((await owned.checkInputsNotOwned(() => false)).toJson());
+        const unknown = OutputsUnknown.fromJson(
+            (await seen.checkNoInputsSeenBefore(() => false)).toJson()
+        );
+        const outputs = WantsOutputs.fromJson(
+            (await unknown.identifyReceiverOutputs((_, output) => output.vout === 1)).toJson()
+        );
+        const inputs = WantsInputs.fromJson(outputs.commitOutputs().toJson());
+        const provisional = inputs.commitInputs();
+        const restored = ProvisionalProposal.fromJson(provisional.toJson());
+
+        expect(JSON.parse(restored.toJson()).data.steps).toEqual([
+            { step: 'checkBroadcastSuitability', minFeeRateSatPerKwu: null, answers: [true] },
+            { step: 'checkInputsNotOwned', answers: [false] },
+            { step: 'checkNoInputsSeenBefore', answers: [false] },
+            { step: 'identifyReceiverOutputs', answers: [false, true] },
+            { step: 'commitOutputs' },
+            { step: 'commitInputs' },
+        ]);
+
+        const proposal = await restored.finalizeProposal(null, 0, psbt => psbt);
+        const expected = await provisional.finalizeProposal(null, 0, psbt => psbt);
+        expect(proposal.psbt()).toBe(expected.psbt());
+        expect(proposal.utxosToBeLocked()).toEqual([
+            '833b085de288cda6ff614c6e8655f61e7ae4f84604a2751998dc25a0d1ba278f:0',
+        ]);
+    });
+
+    // payjoin shuffles contributed inputs and replaced outputs at random, so
+    // a replayed proposal only matches if the drawn order was persisted.
+    it('should finalize a restored proposal to the same PSBT after shuffling', async () => {
+        const receiverScript = Buffer.from('a914774096dbcf486743c22f4347e9b469febe8b677a87', 'hex');
+        const extraScript = Buffer.from(`0014${'33'.repeat(20)}`, 'hex');
+        const candidate = (byte: string, amount: bigint) => ({
+            prevout: { txid: byte.repeat(32), vout: 0 },
+            psbtData: { witnessUtxo: { amount, scriptPubKey: `0014${'22'.repeat(20)}` } },
+        });
+
+        for (let attempt = 0; attempt < 10; attempt++) {
+            const proposal = await uncheckedProposal('v=2').checkBroadcastSuitability(null, () => true);
+            const unseen = await proposal.checkInputsNotOwned(() => false);
+            const unknown = await unseen.checkNoInputsSeenBefore(() => false);
+            const identified = await unknown.identifyReceiverOutputs((_, output) => output.vout === 1);
+            const outputs = await identified.replaceReceiverOutputs(
+                [
+                    [receiverScript, 2_000_000],
+                    [extraScript, 50_000],
+                ],
+                receiverScript
+            );
+            const provisional = outputs
+                .commitOutputs()
+                .contributeInputs([candidate('aa', 3_000_000n), candidate('bb', 1_000_000n)])
+                .commitInputs();
+            const restored = ProvisionalProposal.fromJson(provisional.toJson());
+
+            const expected = await provisional.finalizeProposal(null, 0, psbt => psbt);
+            const finalized = await restored.finalizeProposal(null, 0, psbt => psbt);
+            expect(finalized.psbt()).toBe(expected.psbt());
+        }
+    });
+
+    it('should refuse a proposal restored into the wrong typestate', async () => {
+        const owned = await uncheckedProposal().checkBroadcastSuitability(null, () => true);
+        const json = JSON.stringify({ ...JSON.parse(owned.toJson()), state: 'MaybeInputsSeen' });
+
+        const error = catchError(() => MaybeInputsSeen.fromJson(json));
+        expect(error.code).toBe('SerializationFailed');
+        expect(error.message).toContain('expected a MaybeInputsSeen but replayed to a MaybeInputsOwned');
+    });
+});
+
+describe('Encrypted session export', () => {
+    const key = new Uint8Array(32).fill(7);
+
+    it('should round-trip a receiver under a raw key', () => {
+        const receiver = newReceiver();
+        const exported = receiver.toEncryptedJson({ key });
+
+        expect(exported).not.toContain('"context"');
+        expect(PayjoinReceiver.fromEncryptedJson(exported, { key }).toJson()).toBe(receiver.toJson());
+    });
+
+    it('should round-trip a receiver under a passphrase', () => {
+        const receiver = newReceiver();
+        const exported = receiver.toEncryptedJson({ passphrase: 'correct horse' });
+
+        expect(JSON.parse(exported).kdf.name).toBe('scrypt');
+        const restored = PayjoinReceiver.fromEncryptedJson(exported, { passphrase: 'correct horse' });
+        expect(restored.toJson()).toBe(receiver.toJson());
+    });
+
+    it('should refuse the wrong key', () => {
+        const exported = newReceiver().toEncryptedJson({ key });
+
+        const error = catchError(() =>
+            PayjoinReceiver.fromEncryptedJson(exported, { key: new Uint8Array(32) })
+        );
+        expect(error.code).toBe('SerializationFailed');
+        expect(error.kind).toBe('DecryptionFailed');
+    });
+
+    it('should refuse an export whose header was altered', () => {
+        const exported = JSON.parse(newReceiver().toEncryptedJson({ key }));
+        const json = JSON.stringify({ ...exported, format: exported.format + 1 });
+
+        const error = catchError(() => PayjoinReceiver.fromEncryptedJson(json, { key }));
+        expect(error.kind).toBe('DecryptionFailed');
+    });
+
+    // The KDF parameters are read before the export can be authenticated.
+    it('should refuse scrypt parameters above the ones it writes', () => {
+        const exported = JSON.parse(newReceiver().toEncryptedJson({ passphrase: 'correct horse' }));
+        const json = JSON.stringify({ ...exported, kdf: { ...exported.kdf, logN: 40 } });
+
+        const error = catchError(() =>
+            PayjoinReceiver.fromEncryptedJson(json, { passphrase: 'correct horse' })
+        );
+        expect(error.code).toBe('SerializationFailed');
+        expect(error.kind).toBe('UnsupportedKdf');
+    });
+
+    it('should require exactly one of key and passphrase', () => {
+        const receiver = newReceiver();
+
+        expect(catchError(() => receiver.toEncryptedJson({})).code).toBe('InvalidOptions');
+        expect(
+            catchError(() => receiver.toEncryptedJson({ key, passphrase: 'both' })).code
+        ).toBe('InvalidOptions');
+        expect(
+            catchError(() => receiver.toEncryptedJson({ key: new Uint8Array(16) })).code
+        ).toBe('InvalidOptions');
+    });
+});
@@ -0,0 +1,358 @@
+import http from 'http';
+import { AddressInfo } from 'net';
+import { CancelledError, MaybeInputsOwned, PayjoinError, ReceiverError } from '../src/index';
+import {
+    IContributionOutcome,
+    IInputContext,
+    IInputPairRequest,
+    IOutputContext,
+    ITxContext,
+    SelectionFallback,
+} from '../src/types';
+import {
+    newReceiver,
+    openDirectoryRequest,
+    reusedInputs,
+    reusedScriptPsbt,
+    uncheckedProposal,
+    v1Request,
+    wantsInputs,
+} from './fixtures';
+
+describe('Creating a receiver', () => {
+    // The session's URI carries its expiry as 32-bit seconds since the epoch.
+    it.each<[string, bigint]>([
+        ['a negative', -1n],
+        ['a 64-bit overflowing', 2n ** 64n],
+        ['a post-2106', 2n ** 32n],
+    ])('should refuse %s expiry', (_, expirySeconds) => {
+        let error: unknown;
+        try {
+            newReceiver(undefined, expirySeconds);
+        } catch (e) {
+            error = e;
+        }
+
+        expect(error).toBeInstanceOf(PayjoinError);
+        expect((error as PayjoinError).code).toBe('InvalidOptions');
+    });
+});
+
+describe('Polling for a proposal', () => {
+    let server: http.Server;
+    let relay: string;
+
+    // A relay that accepts requests and never answers them.
+    beforeAll(async () => {
+        server = http.createServer(() => {});
+        await new Promise<void>(resolve => server.listen(0, '127.0.0.1', resolve));
+        relay = `http://127.0.0.1:${(server.address() as AddressInfo).port}`;
+    });
+
+    afterAll(() => {
+        server.closeAllConnections();
+        server.close();
+    });
+
+    it('should resolve with null once the timeout passes mid-request', async () => {
+        const started = Date.now();
+
+        await expect(newReceiver(relay).pollForProposal({ timeoutMs: 300 })).resolves.toBeNull();
+        expect(Date.now() - started).toBeLessThan(5_000);
+    });
+
+    it('should reject when the session expires before the timeout', async () => {
+        const started = Date.now();
+
+        const error: PayjoinError = await newReceiver(relay, 2n)
+            .pollForProposal({ timeoutMs: 60_000 })
+            .catch(e => e);
+        expect(error.code).toBe('SessionExpired');
+        expect(Date.now() - started).toBeLessThan(10_000);
+    });
+
+    it('should reject with the cancelled code when aborted', async () => {
+        const controller = new AbortController();
+        setTimeout(() => controller.abort(), 100);
+
+        const error = await newReceiver(relay)
+            .pollForProposal(undefined, controller.signal)
+            .catch(e => e);
+        expect(error).toBeInstanceOf(CancelledError);
+        expect(error.code).toBe('Cancelled');
+    });
+});
+
+describe('Rejecting a proposal', () => {
+    it('should deliver the error to a v1 sender through the directory', async () => {
+        const request = uncheckedProposal().extractErrorRequest('NotEnoughMoney', 'no funds');
+        expect(request.url()).toBe('https://relay.example.com/');
+
+        const delivered = openDirectoryRequest(request.body());
+        expect(delivered.method).toBe('PUT');
+        expect(delivered.url).toMatch(/^https:\/\/directory\.example\.com\/[0-9A-Z]+$/);
+
+        const error = await (await v1Request()).processResponse(delivered.body).catch(e => e);
+        expect(error).toBeInstanceOf(ReceiverError);
+        expect(error.code).toBe('NotEnoughMoney');
+        expect(error.errorCode).toBe('not-enough-money');
+        expect(error.receiverMessage).toBe('no funds');
+    });
+
+    it('should offer the versions the receiver supports', async () => {
+        const request = uncheckedProposal().extractErrorRequest('VersionUnsupported');
+
+        const delivered = openDirectoryRequest(request.body());
+        const error = await (await v1Request()).processResponse(delivered.body).catch(e => e);
+        expect(error.code).toBe('VersionUnsupported');
+        expect(error.receiverMessage).toBe('This version of payjoin is not supported.');
+        expect(error.supported).toEqual([1, 2]);
+    });
+});
+
+describe('Callback context', () => {
+    const input = {
+        script: 'a914de4e1e9061ca59135b035ad5e6d47990454354d087',
+        address: '3MxTWxfSyMwacqhmUhgGoNmBJGdRGJG3du',
+        outpoint: '833b085de288cda6ff614c6e8655f61e7ae4f84604a2751998dc25a0d1ba278f:0',
+        value: 97983400n,
+    };
+
+    it('should describe the original transaction, its inputs and its outputs', async () => {
+        const txs: ITxContext[] = [];
+        const owned: IInputContext[] = [];
+        const seen: IInputContext[] = [];
+        const outputs: IOutputContext[] = [];
+
+        const proposal = await uncheckedProposal().checkBroadcastSuitability(null, (_, tx) => {
+            txs.push(tx);
+            return true;
+        });
+        const unseen = await proposal.checkInputsNotOwned((_, context) => {
+            owned.push(context);
+            return false;
+        });
+        const unknown = await unseen.checkNoInputsSeenBefore((_, context) => {
+            seen.push(context);
+            return false;
+        });
+        await unknown.identifyReceiverOutputs((_, output) => {
+            outputs.push(output);
+            return output.vout === 1;
+        });
+
+        expect(txs).toEqual([
+            expect.objectContaining({
+                txid: 'bd1ae03cc0240bae3d6063c5533e43956c192c81fc33c2f45795c611d62965b0',
+                vsize: 166,
+                fee: 332n,
+                feeRateSatPerVb: 2,
+            }),
+        ]);
+        expect(owned).toEqual([input]);
+        expect(seen).toEqual([input]);
+        expect(outputs).toEqual([
+            {
+                script: 'a9141de849f069d274150e3afeae8d72eb5a6b09443087',
+                address: '34R9npMiyq6KY81DeMMBTgUoAeueyKeycZ',
+                vout: 0,
+                value: 95983068n,
+            },
+            {
+                script: 'a914774096dbcf486743c22f4347e9b469febe8b677a87',
+                address: '3CZZi7aWFugaCdUCS15dgrUUViupmB8bVM',
+                vout: 1,
+                value: 2000000n,
+            },
+        ]);
+    });
+
+    it('should tell apart inputs spending the same address', async () => {
+        const proposal = uncheckedProposal('v=1', reusedScriptPsbt()).assumeInteractiveReceiver();
+        const expected = reusedInputs.map(reused => ({ ...input, ...reused }));
+
+        const owned: IInputContext[] = [];
+        await proposal.checkInputsNotOwned((_, context) => {
+            owned.push(context);
+            return false;
+        });
+        const batched: IInputContext[][] = [];
+        await proposal.checkInputsNotOwnedBatch((_, contexts) => {
+            batched.push(contexts);
+            return contexts.map(() => false);
+        });
+
+        expect(owned).toEqual(expected);
+        expect(batched).toEqual([expected]);
+    });
+});
+
+describe('Asynchronous callbacks', () => {
+    it('should wait for the answer a callback resolves with', async () => {
+        const txids: string[] = [];
+
+        const proposal = await uncheckedProposal().checkBroadcastSuitabilityAsync(null, async (_, tx) => {
+            await new Promise(resolve => setTimeout(resolve, 10));
+            txids.push(tx.txid);
+            return true;
+        });
+
+        expect(proposal).toBeInstanceOf(MaybeInputsOwned);
+        expect(txids).toEqual(['bd1ae03cc0240bae3d6063c5533e43956c192c81fc33c2f45795c611d62965b0']);
+    });
+
+    it('should reject the proposal when the callback resolves with false', async () => {
+        const error = await uncheckedProposal()
+            .checkBroadcastSuitabilityAsync(null, async () => false)
+            .catch(e => e);
+
+        expect(error.code).toBe('ProposalRejected');
+        expect(error.stage).toBe('Proposal');
+    });
+
+    it('should surface the rejection of the promise a callback returns', async () => {
+        class NodeError extends Error {}
+        const rejection = new NodeError('node is down');
+
+        const error = await uncheckedProposal()
+            .checkBroadcastSuitabilityAsync(null, () => Promise.reject(rejection))
+            .catch(e => e);
+
+        expect(error.code).toBe('CallbackFailed');
+        expect(error.message).toContain('node is down');
+        expect(error.cause).toBe(rejection);
+    });
+
+    it('should carry answers through later transitions', async () => {
+        const proposal = await uncheckedProposal().checkBroadcastSuitabilityAsync(null, async () => true);
+
+        const error = await proposal.checkInputsNotOwnedAsync(async () => true).catch(e => e);
+
+        expect(error.code).toBe('ProposalRejected');
+    });
+});
+
+describe('Batch callbacks', () => {
+    async function maybeInputsOwned(): Promise<MaybeInputsOwned> {
+        return uncheckedProposal().checkBroadcastSuitabilityAsync(null, async () => true);
+    }
+
+    it('should ask about every input script in a single call', async () => {
+        const calls: string[][] = [];
+
+        const proposal = await (await maybeInputsOwned()).checkInputsNotOwnedBatch(async scripts => {
+            calls.push(scripts);
+            return scripts.map(() => false);
+        });
+
+        expect(calls).toEqual([['a914de4e1e9061ca59135b035ad5e6d47990454354d087']]);
+
+        const outpoints: string[][] = [];
+        await proposal.checkNoInputsSeenBeforeBatch(async seen => {
+            outpoints.push(seen);
+            return seen.map(() => false);
+        });
+
+        expect(outpoints).toEqual([['833b085de288cda6ff614c6e8655f61e7ae4f84604a2751998dc25a0d1ba278f:0']]);
+    });
+
+    it.each<[string, boolean[]]>([
+        ['too few', []],
+        ['too many', [false, false]],
+    ])('should reject %s answers', async (_, answers) => {
+        const error = await (await maybeInputsOwned())
+            .checkInputsNotOwnedBatch(async () => answers)
+            .catch(e => e);
+
+        expect(error.code).toBe('CallbackFailed');
+        expect(error.message).toContain(`expected 1 answers, got ${answers.length}`);
+    });
+});
+
+describe('Contributing inputs', () => {
+    const senderOutpoint = '833b085de288cda6ff614c6e8655f61e7ae4f84604a2751998dc25a0d1ba278f:0';
+
+    it('should propose the original inputs when nothing is contributed', async () => {
+        const provisional = (await wantsInputs()).commitInputs();
+
+        const proposal = await provisional.finalizeProposal(null, 0, psbt => psbt);
+
+        expect(proposal.utxosToBeLocked()).toEqual([senderOutpoint]);
+    });
+
+    describe('with a fallback', () => {
+        // v2 proposals accept receiver inputs of another type than the
+        // sender's, so the candidates are plain P2WPKH. The fixture pays the
+        // receiver 2000000 sats, so only larger candidates avoid the
+        // unnecessary input heuristics.
+        function candidate(byte: string, amount: bigint): IInputPairRequest {
+            return {
+                prevout: { txid: byte.repeat(32), vout: 0 },
+                psbtData: { witnessUtxo: { amount, scriptPubKey: `0014${'22'.repeat(20)}` } },
+            };
+        }
+        const small = [candidate('aa', 1_000_000n), candidate('bb', 1_500_000n), candidate('cc', 1_200_000n)];
+
+        async function locked(outcome: IContributionOutcome): Promise<string[]> {
+            const proposal = await outcome.proposal.finalizeProposal(null, 0, psbt => psbt);
+            return proposal.utxosToBeLocked().sort();
+        }
+
+        it('should contribute the privacy preserving candidate when there is one', async () => {
+            const outcome = (await wantsInputs('v=2')).contributeInputsWithFallback(
+                [...small, candidate('dd', 3_000_000n)],
+                'Abort'
+            );
+
+            expect(outcome.branch).toBe('PrivacyPreserving');
+            expect(outcome.selectedIndex).toBe(3);
+            expect(await locked(outcome)).toEqual([senderOutpoint, `${'dd'.repeat(32)}:0`]);
+        });
+
+        it('should select the first of several privacy preserving candidates', async () => {
+            const inputs = await wantsInputs('v=2');
+
+            const preserving = candidate('dd', 3_000_000n);
+
+            const selected = inputs.selectPrivacyPreservingInput([
+                ...small,
+                preserving,
+                candidate('ee', 4_000_000n),
+            ]);
+
+            expect(selected).toBe(preserving);
+        });
+
+        it('should contribute the largest candidate when none preserves privacy', async () => {
+            const outcome = (await wantsInputs('v=2')).contributeInputsWithFallback(small, 'LargestInput');
+
+            expect(outcome.branch).toBe('LargestInput');
+            expect(outcome.selectedIndex).toBe(1);
+            expect(await locked(outcome)).toEqual([senderOutpoint, `${'bb'.repeat(32)}:0`]);
+        });
+
+        it('should contribute nothing when none preserves privacy', async () => {
+            const outcome = (await wantsInputs('v=2')).contributeInputsWithFallback(small, 'NoInput');
+
+            expect(outcome.branch).toBe('NoInput');
+            expect(outcome.selectedIndex).toBeUndefined();
+            expect(await locked(outcome)).toEqual([senderOutpoint]);
+        });
+
+        it.each<[SelectionFallback, IInputPairRequest[]]>([
+            ['Abort', small],
+            ['LargestInput', []],
+        ])('should fail with %s when no candidate can be contributed', async (fallback, candidates) => {
+            const inputs = await wantsInputs('v=2');
+
+            let error: unknown;
+            try {
+                inputs.contributeInputsWithFallback(candidates, fallback);
+            } catch (e) {
+                error = e;
+            }
+            expect(error).toBeInstanceOf(PayjoinError);
+            expect(error).toMatchObject({ code: 'InvalidInput', stage: 'Proposal' });
+        });
+    });
+});
@@ -0,0 +1,147 @@
+import http from 'http';
+import { AddressInfo } from 'net';
+import { PayjoinSender, PayjoinSenderBuilder, PayjoinV2Context, ReceiverError } from '../src/index';
+import { directoryReply, newReceiver, openDirectoryRequest, originalPsbt, v1Request } from './fixtures';
+
+describe('Processing a v1 response', () => {
+    function reply(body: unknown): Uint8Array {
+        return new TextEncoder().encode(JSON.stringify(body));
+    }
+
+    it('should surface version-unsupported with the supported versions', async () => {
+        const request = await v1Request();
+
+        const error = await request
+            .processResponse(reply({ errorCode: 'version-unsupported', message: 'use v1', supported: [1] }))
+            .catch(e => e);
+
+        expect(error).toBeInstanceOf(ReceiverError);
+        expect(error.code).toBe('VersionUnsupported');
+        expect(error.stage).toBe('Sender');
+        expect(error.errorCode).toBe('version-unsupported');
+        expect(error.receiverMessage).toBe('use v1');
+        expect(error.supported).toEqual([1]);
+    });
+
+    it('should surface not-enough-money', async () => {
+        const request = await v1Request();
+
+        const error = await request
+            .processResponse(reply({ errorCode: 'not-enough-money', message: 'no funds' }))
+            .catch(e => e);
+
+        expect(error).toBeInstanceOf(ReceiverError);
+        expect(error.code).toBe('NotEnoughMoney');
+        expect(error.supported).toBeUndefined();
+    });
+
+    it('should keep an error code BIP78 does not define apart', async () => {
+        const request = await v1Request();
+
+        const error = await request
+            .processResponse(reply({ errorCode: 'out-of-coffee', message: 'brb' }))
+            .catch(e => e);
+
+        expect(error).toBeInstanceOf(ReceiverError);
+        expect(error.code).toBe('UnrecognizedReceiverError');
+        expect(error.errorCode).toBe('out-of-coffee');
+    });
+
+    it('should report a reply that is neither a PSBT nor an error as invalid', async () => {
+        const request = await v1Request();
+
+        const error = await request.processResponse(new TextEncoder().encode('garbage')).catch(e => e);
+
+        expect(error).not.toBeInstanceOf(ReceiverError);
+        expect(error.code).toBe('InvalidResponse');
+        expect(error.kind).toBe('ValidationError');
+    });
+});
+
+describe('Sending over v2', () => {
+    let server: http.Server;
+    let relay: string;
+
+    // A relay that accepts requests and never answers them.
+    beforeAll(async () => {
+        server = http.createServer(() => {});
+        await new Promise<void>(resolve => server.listen(0, '127.0.0.1', resolve));
+        relay = `http://127.0.0.1:${(server.address() as AddressInfo).port}`;
+    });
+
+    afterAll(() => {
+        server.closeAllConnections();
+        server.close();
+    });
+
+    async function newSender(expirySeconds?: bigint): Promise<PayjoinSender> {
+        const uri = newReceiver(relay, expirySeconds).pjUriBuilder().build();
+        return PayjoinSenderBuilder.fromPsbtAndUri(originalPsbt, uri).buildRecommended(1);
+    }
+
+    it('should time out while a request is still in flight', async () => {
+        const sender = await newSender();
+
+        const started = Date.now();
+        const outcome = await sender.sendV2(relay, { timeoutMs: 300 });
+
+        expect(outcome.status).toBe('timeout');
+        expect(Date.now() - started).toBeLessThan(5_000);
+    });
+
+    it('should stop at the session expiry when it comes before the timeout', async () => {
+        const sender = await newSender(2n);
+
+        const started = Date.now();
+        const outcome = await sender.sendV2(relay, { timeoutMs: 60_000 });
+
+        expect(outcome.status).toBe('expired');
+        expect(Date.now() - started).toBeLessThan(10_000);
+    });
+});
+
+describe('Resuming a v2 session', () => {
+    const relay = 'https://relay.example.com';
+
+    async function postedContext(): Promise<{ context: PayjoinV2Context; posted: string }> {
+        const uri = newReceiver().pjUriBuilder().build();
+        const sender = await PayjoinSenderBuilder.fromPsbtAndUri(originalPsbt, uri).buildRecommended(1);
+        const request = await sender.extractV2(relay);
+        const response = await request.processResponse(directoryReply(request.body()));
+        return { context: response.v2Context()!, posted: openDirectoryRequest(request.body()).url };
+    }
+
+    it('should post the original PSBT again from a restored context', async () => {
+        const { context, posted } = await postedContext();
+
+        const restored = PayjoinV2Context.fromJson(context.toJson());
+        const request = await restored.extractPostRequest(relay);
+
+        expect(openDirectoryRequest(request.body())).toMatchObject({ method: 'POST', url: posted });
+    });
+
+    it('should refuse to poll from a restored context before the repost', async () => {
+        const { context } = await postedContext();
+        expect(context.isPolling()).toBe(true);
+
+        const restored = PayjoinV2Context.fromJson(context.toJson());
+        expect(restored.isPolling()).toBe(false);
+        await expect(restored.extractRequest(relay)).rejects.toMatchObject({
+            code: 'MissingContext',
+        });
+    });
+
+    it('should poll the same mailbox once the repost is acknowledged', async () => {
+        const { context } = await postedContext();
+        const polling = openDirectoryRequest((await context.extractRequest(relay)).body());
+
+        const restored = PayjoinV2Context.fromJson(context.toJson());
+        const repost = await restored.extractPostRequest(relay);
+        await expect(restored.processResponse(directoryReply(repost.body()), repost)).resolves.toBeNull();
+        expect(restored.isPolling()).toBe(true);
+        const poll = openDirectoryRequest((await restored.extractRequest(relay)).body());
+
+        expect(polling.method).toBe('GET');
+        expect(poll).toMatchObject({ method: 'GET', url: polling.url });
+    });
+});
@@ -0,0 +1,170 @@
+import { mkdtempSync, readFileSync, readdirSync, rmSync, statSync } from 'fs';
+import { tmpdir } from 'os';
+import { join } from 'path';
+import { IStoredSession, PayjoinError, SessionStore } from '../src/index';
+import { newReceiver } from './fixtures';
+
+describe('SessionStore', () => {
+    it('should round-trip a receiver under its mailbox ID', async () => {
+        const store = SessionStore.memory();
+        const receiver = newReceiver();
+
+        const id = await store.saveReceiver(receiver);
+        expect(receiver.pjUrl()).toContain(id);
+        expect(await store.saveReceiver(receiver)).toBe(id);
+
+        const restored = await store.loadReceiver(id);
+        expect(restored?.toJson()).toBe(receiver.toJson());
+        expect(await store.loadReceiver('MISSING')).toBeNull();
+    });
+
+    it('should list and delete sessions', async () => {
+        const store = SessionStore.memory();
+        const id = await store.saveReceiver(newReceiver());
+
+        const [info] = await store.list();
+        expect(info.id).toBe(id);
+        expect(info.kind).toBe('Receiver');
+        expect(typeof info.expiresAt).toBe('bigint');
+
+        expect(await store.delete(id)).toBe(true);
+        expect(await store.delete(id)).toBe(false);
+        expect(await store.list()).toEqual([]);
+    });
+
+    it('should sweep only expired sessions', async () => {
+        const store = SessionStore.memory();
+        const expired = await store.saveReceiver(newReceiver(undefined, 0n));
+        const live = await store.saveReceiver(newReceiver());
+
+        expect(await store.sweepExpired()).toEqual([expired]);
+        expect((await store.list()).map(info => info.id)).toEqual([live]);
+    });
+
+    it('should refuse to load a session as the other kind', async () => {
+        const store = SessionStore.memory();
+        const id = await store.saveReceiver(newReceiver());
+
+        const error = (await store.loadSender(id).catch(e => e)) as PayjoinError;
+        expect(error.code).toBe('SerializationFailed');
+        expect(error.message).toContain('expected a PayjoinSender but found a PayjoinReceiver');
+    });
+
+    describe('file backend', () => {
+        let directory: string;
+
+        beforeEach(() => {
+            directory = mkdtempSync(join(tmpdir(), 'payjoin-store-'));
+        });
+
+        afterEach(() => {
+            rmSync(directory, { recursive: true, force: true });
+        });
+
+        it('should keep one file per session across store instances', async () => {
+            const receiver = newReceiver();
+            const id = await SessionStore.file(directory).saveReceiver(receiver);
+            expect(readdirSync(directory)).toEqual([`${id}.json`]);
+
+            const restored = await SessionStore.file(directory).loadReceiver(id);
+            expect(restored?.toJson()).toBe(receiver.toJson());
+        });
+
+        // Windows has no owner-only permission bits to check.
+        (process.platform === 'win32' ? it.skip : it)(
+            'should create session files readable by their owner only',
+            async () => {
+                const id = await SessionStore.file(directory).saveReceiver(newReceiver());
+                expect(statSync(join(directory, `${id}.json`)).mode & 0o777).toBe(0o600);
+            }
+        );
+
+        it('should keep only the encrypted export in a file under a key', async () => {
+            const key = { key: new Uint8Array(32).fill(7) };
+            const receiver = newReceiver();
+            const id = await SessionStore.file(directory, key).saveReceiver(receiver);
+
+            expect(readFileSync(join(directory, `${id}.json`), 'utf8')).not.toContain('context');
+            const restored = await SessionStore.file(directory, key).loadReceiver(id);
+            expect(restored?.toJson()).toBe(receiver.toJson());
+        });
+
+        it('should refuse IDs that are not file-name safe', async () => {
+            const error = (await SessionStore.file(directory)
+                .loadReceiver('../escape')
+                .catch(e => e)) as PayjoinError;
+            expect(error.code).toBe('InvalidOptions');
+        });
+    });
+
+    function mapBackend(sessions: Map<string, IStoredSession>) {
+        return {
+            list: async () => [...sessions.values()],
+            load: async (id: string) => sessions.get(id),
+            save: async (session: IStoredSession) => {
+                sessions.set(session.id, session);
+            },
+            delete: async (id: string) => sessions.delete(id),
+        };
+    }
+
+    it('should store sessions through a JavaScript backend', async () => {
+        const sessions = new Map<string, IStoredSession>();
+        const store = SessionStore.custom(mapBackend(sessions));
+        const receiver = newReceiver();
+
+        const id = await store.saveReceiver(receiver);
+        expect(sessions.get(id)?.kind).toBe('Receiver');
+        expect((await store.loadReceiver(id))?.toJson()).toBe(receiver.toJson());
+        expect(await store.delete(id)).toBe(true);
+        expect(sessions.size).toBe(0);
+    });
+
+    it('should hand a JavaScript backend only encrypted sessions under a key', async () => {
+        const sessions = new Map<string, IStoredSession>();
+        const store = SessionStore.custom(mapBackend(sessions), { passphrase: 'correct horse' });
+        const receiver = newReceiver();
+
+        const id = await store.saveReceiver(receiver);
+        const stored = sessions.get(id)!;
+        expect(stored.json).not.toContain('context');
+        expect(JSON.parse(stored.json).kdf.name).toBe('scrypt');
+        expect(typeof stored.expiresAt).toBe('bigint');
+        expect((await store.loadReceiver(id))?.toJson()).toBe(receiver.toJson());
+
+        const wrongKey = SessionStore.custom(mapBackend(sessions), { passphrase: 'wrong' });
+        const error = (await wrongKey.loadReceiver(id).catch(e => e)) as PayjoinError;
+        expect(error.kind).toBe('DecryptionFailed');
+    });
+
+    it('should refuse an invalid store key up front', () => {
+        expect(() => SessionStore.memory({ key: new Uint8Array(16) })).toThrow(
+            expect.objectContaining({ code: 'InvalidOptions' })
+        );
+    });
+
+    it('should refuse an expiry that is not a valid timestamp', async () => {
+        const sessions = new Map<string, IStoredSession>([
+            ['NEGATIVE', { id: 'NEGATIVE', kind: 'Receiver', expiresAt: -1n, json: '{}' }],
+        ]);
+        const store = SessionStore.custom(mapBackend(sessions));
+
+        const error = (await store.list().catch(e => e)) as PayjoinError;
+        expect(error.code).toBe('SerializationFailed');
+        expect(error.message).toContain('value is negative');
+    });
+
+    it('should report a failing JavaScript backend as a callback error', async () => {
+        const store = SessionStore.custom({
+            list: async () => [],
+            load: async () => undefined,
+            save: async () => {
+                throw new Error('database offline');
+            },
+            delete: async () => false,
+        });
+
+        const error = (await store.saveReceiver(newReceiver()).catch(e => e)) as PayjoinError;
+        expect(error.code).toBe('CallbackFailed');
+    });
+});
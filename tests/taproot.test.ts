@@ -0,0 +1,108 @@
+import native from '../src/native';
+import { toPayjoinError } from '../src/index';
+
+const bytes = (hex: string) => Array.from(Buffer.from(hex, 'hex'));
+
+// Input 0 of the BIP371 vector "PSBT with one P2TR key only input with
+// internal key and its derivation path".
+const internalKey = 'fe349064c98d6e2a853fa3c9b12bd8b304a19c195ed0ef3fef72bea81b45f434';
+const keyPathInput = {
+    prevout: {
+        txid: '42b224669be683585854193052ef88b1efe2ee963af26c0dfe2730bfba4a7427',
+        vout: 0,
+    },
+    psbtData: {
+        witnessUtxo: {
+            amount: 5_000_000_000n,
+            scriptPubKey: '51205a2c2cf5b52cf31f83ad2e8da63ff03183ecd8f609c7510ae8a48e03910a0757',
+        },
+        tapBip32Derivation: [
+            {
+                pubkey: bytes(internalKey),
+                leafHashes: [],
+                fingerprintPath: bytes('f272d83e00000080000000800000008000000000'),
+                child: 0,
+            },
+        ],
+        tapInternalKey: bytes(internalKey),
+    },
+};
+
+// A single `<internalKey> OP_CHECKSIG` leaf and its TapLeaf hash.
+const leafScript = `20${internalKey}ac`;
+const leafHash = 'c1ba0e21675bddeb5ab8cf5ef5c7ae36732644286253d883f746f2e428714a45';
+const signature = '01'.repeat(64);
+
+function withPsbtData(psbtData: Record<string, unknown>) {
+    return { ...keyPathInput, psbtData: { ...keyPathInput.psbtData, ...psbtData } };
+}
+
+function validationError(input: unknown): { code: string; stage: string; kind?: string } {
+    try {
+        native.validateInputPair(input);
+    } catch (error) {
+        return toPayjoinError(error, 'Invalid input');
+    }
+    throw new Error('Expected validation to fail');
+}
+
+describe('Taproot input contribution', () => {
+    it('should accept the BIP371 key path input', () => {
+        expect(() => native.validateInputPair(keyPathInput)).not.toThrow();
+    });
+
+    it('should accept key path signatures with and without a sighash byte', () => {
+        expect(() => native.validateInputPair(withPsbtData({ tapKeySig: bytes(signature) }))).not.toThrow();
+        expect(() =>
+            native.validateInputPair(withPsbtData({ tapKeySig: bytes(`${signature}01`) }))
+        ).not.toThrow();
+    });
+
+    it('should accept script path metadata', () => {
+        const input = withPsbtData({
+            tapScripts: [{ controlBlock: bytes(`c0${internalKey}`), script: bytes(leafScript) }],
+            tapScriptSigs: [
+                { pubkey: bytes(internalKey), leafHash: bytes(leafHash), signature: bytes(signature) },
+            ],
+            tapMerkleRoot: bytes(leafHash),
+        });
+        expect(() => native.validateInputPair(input)).not.toThrow();
+    });
+
+    // Mirrors the BIP371 invalid vectors for each field.
+    it.each([
+        ['a key signature of 66 bytes', { tapKeySig: bytes(`${signature}0101`) }, 'SignatureError'],
+        ['an unknown sighash byte', { tapKeySig: bytes(`${signature}04`) }, 'SignatureError'],
+        ['an internal key of 31 bytes', { tapInternalKey: bytes(internalKey.slice(2)) }, 'Secp256k1Error'],
+        [
+            'a control block of the wrong length',
+            { tapScripts: [{ controlBlock: bytes(`c0${internalKey}00`), script: bytes(leafScript) }] },
+            'TaprootError',
+        ],
+        [
+            'an odd leaf version',
+            {
+                tapScripts: [
+                    { controlBlock: bytes(`c0${internalKey}`), script: bytes(leafScript), leafVersion: 0xc1 },
+                ],
+            },
+            '',
+        ],
+        [
+            'a leaf hash of 31 bytes',
+            {
+                tapScriptSigs: [
+                    { pubkey: bytes(internalKey), leafHash: bytes(leafHash.slice(2)), signature: bytes(signature) },
+                ],
+            },
+            'HashError',
+        ],
+        ['a merkle root of 33 bytes', { tapMerkleRoot: bytes(`${leafHash}00`) }, 'HashError'],
+    ])('should reject %s', (_, psbtData, kind) => {
+        expect(validationError(withPsbtData(psbtData))).toMatchObject({
+            code: 'InvalidInput',
+            stage: 'Proposal',
+            kind,
+        });
+    });
+});
@@ -77,5 +77,24 @@ describe('UriBuilder', () => {
             expect(uri).toContain('amount=0.001');
             expect(uri).toContain(validEndpoint.toUpperCase());
         });
+
+        it('should accept a bigint amount', () => {
+            const uri = new UriBuilder(validAddress, validEndpoint).amount(100000n).build();
+
+            expect(uri).toContain('amount=0.001');
+        });
+
+        it.each([
+            ['fractional', 1.5],
+            ['unsafe', Number.MAX_SAFE_INTEGER + 1],
+            ['negative', -1n],
+            ['above the supply', 2_100_000_000_000_001n],
+        ])('should reject a %s amount', (_, amount) => {
+            const builder = new UriBuilder(validAddress, validEndpoint);
+
+            expect(() => builder.amount(amount)).toThrow(
+                expect.objectContaining({ code: 'InvalidAmount' })
+            );
+        });
     });
 });
\ No newline at end of file